@@ -0,0 +1,206 @@
+use anyhow::Result;
+
+use crate::{
+    bip32::{
+        self, ExtendedPrivateKey, TPRV_TPUB, UPRV_UPUB, VPRV_VPUB, XPRV_XPUB, YPRV_YPUB, ZPRV_ZPUB,
+    },
+    derivation_path::{DerivationPath, KeyOrigin},
+    network::Network,
+    s256_point::S256Point,
+    script::Script,
+    utils,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Purpose {
+    Bip44,
+    Bip49,
+    Bip84,
+    Bip86,
+}
+
+// An account is the m/purpose'/coin_type'/account' node, its receive and change
+// addresses live below it at /0/i and /1/i.
+#[derive(Debug, Clone)]
+pub struct Account {
+    pub purpose: Purpose,
+    pub index: u32,
+    pub master_fingerprint: Vec<u8>,
+    pub key: ExtendedPrivateKey,
+    path: DerivationPath,
+}
+
+impl Purpose {
+    pub fn number(&self) -> u32 {
+        match self {
+            Purpose::Bip44 => 44,
+            Purpose::Bip49 => 49,
+            Purpose::Bip84 => 84,
+            Purpose::Bip86 => 86,
+        }
+    }
+
+    // SLIP-132 (private, public) version bytes: xprv/xpub for P2PKH and P2TR,
+    // yprv/ypub for P2SH-P2WPKH and zprv/zpub for P2WPKH.
+    pub fn version_bytes(&self, network: Network) -> ([u8; 4], [u8; 4]) {
+        match (self, network.is_testnet()) {
+            (Purpose::Bip44 | Purpose::Bip86, false) => XPRV_XPUB,
+            (Purpose::Bip44 | Purpose::Bip86, true) => TPRV_TPUB,
+            (Purpose::Bip49, false) => YPRV_YPUB,
+            (Purpose::Bip49, true) => UPRV_UPUB,
+            (Purpose::Bip84, false) => ZPRV_ZPUB,
+            (Purpose::Bip84, true) => VPRV_VPUB,
+        }
+    }
+
+    pub fn script_pubkey(&self, point: &S256Point) -> Script {
+        match self {
+            Purpose::Bip44 => utils::p2pkh_script(point.clone().hash160(Some(true))),
+            Purpose::Bip49 => {
+                let redeem_script = utils::p2wpkh_script(point.clone().hash160(Some(true)));
+                utils::p2sh_script(utils::hash160(&redeem_script.raw_serialize()))
+            }
+            Purpose::Bip84 => utils::p2wpkh_script(point.clone().hash160(Some(true))),
+            Purpose::Bip86 => utils::p2tr_script(point.taproot_output_key().xonly()),
+        }
+    }
+
+    pub fn address(&self, point: &S256Point, network: Network) -> String {
+        match self {
+            Purpose::Bip44 => point.clone().address(Some(true), Some(network)),
+            Purpose::Bip49 => point.clone().p2sh_p2wpkh_address(network),
+            Purpose::Bip84 => point.clone().p2wpkh_address(network),
+            Purpose::Bip86 => point.clone().p2tr_address(network),
+        }
+    }
+}
+
+impl Account {
+    pub fn new(master: &ExtendedPrivateKey, purpose: Purpose, index: u32) -> Result<Self> {
+        let path = DerivationPath::new(vec![
+            bip32::hardened(purpose.number())?,
+            bip32::hardened(master.network.coin_type())?,
+            bip32::hardened(index)?,
+        ]);
+        Ok(Account {
+            purpose,
+            index,
+            master_fingerprint: master.fingerprint(),
+            key: master.derive_path(&path)?,
+            path,
+        })
+    }
+
+    pub fn derivation_path(&self) -> DerivationPath {
+        self.path.clone()
+    }
+
+    pub fn key_origin(&self) -> KeyOrigin {
+        KeyOrigin::new(self.master_fingerprint.clone(), self.derivation_path())
+    }
+
+    pub fn xprv(&self) -> String {
+        let version = self.purpose.version_bytes(self.key.network).0;
+        utils::encode_base58_checksum(&self.key.serialize_with_version(version))
+    }
+
+    pub fn xpub(&self) -> String {
+        let version = self.purpose.version_bytes(self.key.network).1;
+        utils::encode_base58_checksum(
+            &self
+                .key
+                .to_extended_public_key()
+                .serialize_with_version(version),
+        )
+    }
+
+    pub fn public_key(&self, change: bool, index: u32) -> Result<S256Point> {
+        let key = self
+            .key
+            .derive_path(&DerivationPath::new(vec![change as u32, index]))?;
+        Ok(key.private_key.point)
+    }
+
+    pub fn script_pubkey(&self, change: bool, index: u32) -> Result<Script> {
+        Ok(self.purpose.script_pubkey(&self.public_key(change, index)?))
+    }
+
+    pub fn address(&self, change: bool, index: u32) -> Result<String> {
+        Ok(self
+            .purpose
+            .address(&self.public_key(change, index)?, self.key.network))
+    }
+}
+
+#[cfg(test)]
+mod account_tests {
+    use crate::{bip32::ExtendedPublicKey, bip39::Mnemonic, network::Network};
+
+    use super::{Account, Purpose};
+
+    const MNEMONIC: &str = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+
+    #[test]
+    fn test_bip44_account() {
+        let master = Mnemonic::parse(MNEMONIC)
+            .unwrap()
+            .to_master_key(None, Network::Mainnet)
+            .unwrap();
+        let account = Account::new(&master, Purpose::Bip44, 0).unwrap();
+        assert_eq!(account.key_origin().to_string(), "[73c5da0a/44'/0'/0']");
+        assert_eq!(
+            account.address(false, 0).unwrap(),
+            "1LqBGSKuX5yYUonjxT5qGfpUsXKYYWeabA"
+        );
+    }
+
+    #[test]
+    fn test_bip49_account() {
+        let master = Mnemonic::parse(MNEMONIC)
+            .unwrap()
+            .to_master_key(None, Network::Testnet)
+            .unwrap();
+        let account = Account::new(&master, Purpose::Bip49, 0).unwrap();
+        assert_eq!(account.derivation_path().to_string(), "m/49'/1'/0'");
+        assert_eq!(
+            account.address(false, 0).unwrap(),
+            "2Mww8dCYPUpKHofjgcXcBCEGmniw9CoaiD2"
+        );
+    }
+
+    #[test]
+    fn test_bip84_account() {
+        let master = Mnemonic::parse(MNEMONIC)
+            .unwrap()
+            .to_master_key(None, Network::Mainnet)
+            .unwrap();
+        let account = Account::new(&master, Purpose::Bip84, 0).unwrap();
+        assert_eq!(account.xpub(), "zpub6rFR7y4Q2AijBEqTUquhVz398htDFrtymD9xYYfG1m4wAcvPhXNfE3EfH1r1ADqtfSdVCToUG868RvUUkgDKf31mGDtKsAYz2oz2AGutZYs");
+        assert_eq!(
+            account.address(false, 0).unwrap(),
+            "bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu"
+        );
+        assert_eq!(
+            hex::encode(account.script_pubkey(false, 0).unwrap().serialize()),
+            "160014c0cebcd6c3d3ca8c75dc5ec62ebe55330ef910e2"
+        );
+        let zpub = ExtendedPublicKey::parse(&account.xpub()).unwrap();
+        assert_eq!(zpub.xpub(), account.key.to_extended_public_key().xpub());
+        // account' would overflow into the unhardened range
+        assert!(Account::new(&master, Purpose::Bip84, 0x8000_0000).is_err());
+    }
+
+    #[test]
+    fn test_bip86_account() {
+        let master = Mnemonic::parse(MNEMONIC)
+            .unwrap()
+            .to_master_key(None, Network::Mainnet)
+            .unwrap();
+        let account = Account::new(&master, Purpose::Bip86, 0).unwrap();
+        assert_eq!(account.xpub(), "xpub6BgBgsespWvERF3LHQu6CnqdvfEvtMcQjYrcRzx53QJjSxarj2afYWcLteoGVky7D3UKDP9QyrLprQ3VCECoY49yfdDEHGCtMMj92pReUsQ");
+        assert_eq!(
+            account.address(false, 0).unwrap(),
+            "bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr"
+        );
+    }
+}
@@ -0,0 +1,220 @@
+use std::{fmt::Display, str::FromStr};
+
+use anyhow::{bail, Error, Result};
+
+use crate::{
+    bech32,
+    network::Network,
+    script::{Script, ScriptType},
+    utils,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AddressPayload {
+    P2pkh(Vec<u8>),
+    P2sh(Vec<u8>),
+    P2wpkh(Vec<u8>),
+    P2wsh(Vec<u8>),
+    P2tr(Vec<u8>),
+}
+
+// Base58 addresses don't tell the test networks apart, they parse as Testnet.
+// The payload length is checked on construction, so every Address encodes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Address {
+    payload: AddressPayload,
+    network: Network,
+}
+
+impl Address {
+    pub fn new(payload: AddressPayload, network: Network) -> Result<Self> {
+        let (kind, expected, actual) = match &payload {
+            AddressPayload::P2pkh(h160) => ("P2PKH", 20, h160.len()),
+            AddressPayload::P2sh(h160) => ("P2SH", 20, h160.len()),
+            AddressPayload::P2wpkh(h160) => ("P2WPKH", 20, h160.len()),
+            AddressPayload::P2wsh(sha256) => ("P2WSH", 32, sha256.len()),
+            AddressPayload::P2tr(xonly) => ("P2TR", 32, xonly.len()),
+        };
+        if actual != expected {
+            bail!(
+                "{} payload must be {} bytes, got {}",
+                kind,
+                expected,
+                actual
+            )
+        }
+        Ok(Address { payload, network })
+    }
+
+    pub fn payload(&self) -> &AddressPayload {
+        &self.payload
+    }
+
+    pub fn network(&self) -> Network {
+        self.network
+    }
+
+    pub fn script_pubkey(&self) -> Script {
+        match &self.payload {
+            AddressPayload::P2pkh(h160) => utils::p2pkh_script(h160.clone()),
+            AddressPayload::P2sh(h160) => utils::p2sh_script(h160.clone()),
+            AddressPayload::P2wpkh(h160) => utils::p2wpkh_script(h160.clone()),
+            AddressPayload::P2wsh(sha256) => utils::p2wsh_script(sha256.clone()),
+            AddressPayload::P2tr(xonly) => utils::p2tr_script(xonly.clone()),
+        }
+    }
+
+    // Returns None for scripts that have no address form.
+    pub fn from_script(script: &Script, network: Network) -> Option<Self> {
+        let payload = match script.script_type() {
+            ScriptType::P2pkh => AddressPayload::P2pkh(script.pubkey_hash()?.to_vec()),
+            ScriptType::P2sh => AddressPayload::P2sh(script.script_hash()?.to_vec()),
+            ScriptType::P2wpkh => AddressPayload::P2wpkh(script.pubkey_hash()?.to_vec()),
+            ScriptType::P2wsh => AddressPayload::P2wsh(script.script_hash()?.to_vec()),
+            ScriptType::P2tr => AddressPayload::P2tr(script.witness_program()?.1.to_vec()),
+            _ => return None,
+        };
+        Address::new(payload, network).ok()
+    }
+
+    fn from_base58(s: &str) -> Result<Self> {
+        let (version, h160) = utils::decode_base58_check(s)?;
+        if h160.len() != 20 {
+            bail!("invalid base58 address length: {}", s)
+        }
+        let (payload, network) = match version {
+            0x00 => (AddressPayload::P2pkh(h160), Network::Mainnet),
+            0x05 => (AddressPayload::P2sh(h160), Network::Mainnet),
+            0x6f => (AddressPayload::P2pkh(h160), Network::Testnet),
+            0xc4 => (AddressPayload::P2sh(h160), Network::Testnet),
+            version => bail!("unknown address version byte: {}", version),
+        };
+        Ok(Address { payload, network })
+    }
+
+    fn from_bech32(s: &str, network: Network) -> Result<Self> {
+        let (version, program) = bech32::decode_segwit_address(network.bech32_hrp(), s)?;
+        let payload = match (version, program.len()) {
+            (0, 20) => AddressPayload::P2wpkh(program),
+            (0, 32) => AddressPayload::P2wsh(program),
+            (1, 32) => AddressPayload::P2tr(program),
+            _ => bail!("unsupported witness version {} in {}", version, s),
+        };
+        Ok(Address { payload, network })
+    }
+}
+
+impl FromStr for Address {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let hrp = s.to_lowercase();
+        for network in [Network::Mainnet, Network::Testnet, Network::Regtest] {
+            if hrp.starts_with(&format!("{}1", network.bech32_hrp())) {
+                return Address::from_bech32(s, network);
+            }
+        }
+        Address::from_base58(s)
+    }
+}
+
+impl Display for Address {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let hrp = self.network.bech32_hrp();
+        let encoded = match &self.payload {
+            AddressPayload::P2pkh(h160) => {
+                utils::encode_base58_checksum(&[&[self.network.p2pkh_prefix()], &h160[..]].concat())
+            }
+            AddressPayload::P2sh(h160) => {
+                utils::encode_base58_checksum(&[&[self.network.p2sh_prefix()], &h160[..]].concat())
+            }
+            // the program lengths were checked by Address::new
+            AddressPayload::P2wpkh(program) | AddressPayload::P2wsh(program) => {
+                bech32::encode_segwit_address(hrp, 0, program).unwrap()
+            }
+            AddressPayload::P2tr(program) => {
+                bech32::encode_segwit_address(hrp, 1, program).unwrap()
+            }
+        };
+        write!(f, "{}", encoded)
+    }
+}
+
+#[cfg(test)]
+mod address_tests {
+    use crate::network::Network;
+
+    use super::{Address, AddressPayload};
+
+    #[test]
+    fn test_parse_addresses() {
+        for (address, network, script_pubkey) in [
+            (
+                "1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2",
+                Network::Mainnet,
+                "76a91477bff20c60e522dfaa3350c39b030a5d004e839a88ac",
+            ),
+            (
+                "3J98t1WpEZ73CNmQviecrnyiWrnqRhWNLy",
+                Network::Mainnet,
+                "a914b472a266d0bd89c13706a4132ccfb16f7c3b9fcb87",
+            ),
+            (
+                "mipcBbFg9gMiCh81Kj8tqqdgoZub1ZJRfn",
+                Network::Testnet,
+                "76a914243f1394f44554f4ce3fd68649c19adc483ce92488ac",
+            ),
+            (
+                "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4",
+                Network::Mainnet,
+                "0014751e76e8199196d454941c45d1b3a323f1433bd6",
+            ),
+            (
+                "tb1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3q0sl5k7",
+                Network::Testnet,
+                "00201863143c14c5166804bd19203356da136c985678cd4d27a1b8c6329604903262",
+            ),
+            (
+                "bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr",
+                Network::Mainnet,
+                "5120a60869f0dbcf1dc659c9cecbaf8050135ea9e8cdc487053f1dc6880949dc684c",
+            ),
+        ] {
+            let parsed: Address = address.parse().unwrap();
+            assert_eq!(parsed.network(), network);
+            assert_eq!(parsed.to_string(), address);
+            let script = parsed.script_pubkey();
+            assert_eq!(hex::encode(script.raw_serialize()), script_pubkey);
+            assert_eq!(Address::from_script(&script, network).unwrap(), parsed);
+        }
+    }
+
+    #[test]
+    fn test_regtest_and_invalid_addresses() {
+        let address = Address::new(
+            AddressPayload::P2wpkh(
+                hex::decode("751e76e8199196d454941c45d1b3a323f1433bd6").unwrap(),
+            ),
+            Network::Regtest,
+        )
+        .unwrap();
+        assert_eq!(
+            address.to_string(),
+            "bcrt1qw508d6qejxtdg4y5r3zarvary0c5xw7kygt080"
+        );
+        assert_eq!(address.to_string().parse::<Address>().unwrap(), address);
+
+        assert!("1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN3"
+            .parse::<Address>()
+            .is_err());
+        assert!("bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t5"
+            .parse::<Address>()
+            .is_err());
+        assert!(Address::new(AddressPayload::P2wpkh(vec![1, 2, 3]), Network::Mainnet).is_err());
+        assert!(Address::new(AddressPayload::P2tr(vec![0; 20]), Network::Mainnet).is_err());
+        // unknown witness versions are not recognized
+        assert!("bc1zw508d6qejxtdg4y5r3zarvaryvaxxpcs"
+            .parse::<Address>()
+            .is_err());
+    }
+}
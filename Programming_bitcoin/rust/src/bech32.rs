@@ -0,0 +1,402 @@
+use std::fmt::Display;
+
+const CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+const BECH32_CONST: u32 = 1;
+const BECH32M_CONST: u32 = 0x2bc830a3;
+const CHECKSUM_LENGTH: usize = 6;
+const MAX_LENGTH: usize = 90;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Variant {
+    Bech32,
+    Bech32m,
+}
+
+// Positions are character offsets into the string that was decoded.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Bech32Error {
+    InvalidLength(usize),
+    MixedCase(usize),
+    InvalidCharacter(usize),
+    MissingSeparator,
+    InvalidHrp,
+    InvalidChecksum(Vec<usize>),
+    InvalidPadding,
+    HrpMismatch(String),
+    InvalidWitnessVersion(u8),
+    InvalidProgramLength(usize),
+    WrongVariant(Variant),
+}
+
+impl Display for Bech32Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Bech32Error::InvalidLength(length) => write!(f, "invalid bech32 length: {}", length),
+            Bech32Error::MixedCase(position) => write!(f, "mixed case at position {}", position),
+            Bech32Error::InvalidCharacter(position) => {
+                write!(f, "invalid character at position {}", position)
+            }
+            Bech32Error::MissingSeparator => write!(f, "missing separator '1'"),
+            Bech32Error::InvalidHrp => write!(f, "invalid human-readable part"),
+            Bech32Error::InvalidChecksum(positions) if positions.is_empty() => {
+                write!(f, "invalid checksum")
+            }
+            Bech32Error::InvalidChecksum(positions) => {
+                write!(
+                    f,
+                    "invalid checksum, likely error at position {:?}",
+                    positions
+                )
+            }
+            Bech32Error::InvalidPadding => write!(f, "invalid padding"),
+            Bech32Error::HrpMismatch(hrp) => write!(f, "unexpected human-readable part: {}", hrp),
+            Bech32Error::InvalidWitnessVersion(version) => {
+                write!(f, "invalid witness version: {}", version)
+            }
+            Bech32Error::InvalidProgramLength(length) => {
+                write!(f, "invalid witness program length: {}", length)
+            }
+            Bech32Error::WrongVariant(variant) => {
+                write!(
+                    f,
+                    "witness version encoded with the wrong checksum: {:?}",
+                    variant
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for Bech32Error {}
+
+impl Variant {
+    fn constant(&self) -> u32 {
+        match self {
+            Variant::Bech32 => BECH32_CONST,
+            Variant::Bech32m => BECH32M_CONST,
+        }
+    }
+
+    // Witness version 0 uses the BIP173 checksum, every later version uses BIP350.
+    pub fn for_witness_version(witness_version: u8) -> Self {
+        if witness_version == 0 {
+            Variant::Bech32
+        } else {
+            Variant::Bech32m
+        }
+    }
+}
+
+fn polymod(values: &[u8]) -> u32 {
+    let generator = [0x3b6a57b2, 0x26508e6d, 0x1ea119fa, 0x3d4233dd, 0x2a1462b3];
+    let mut chk: u32 = 1;
+    for value in values {
+        let top = chk >> 25;
+        chk = ((chk & 0x1ffffff) << 5) ^ *value as u32;
+        for (i, g) in generator.iter().enumerate() {
+            if (top >> i) & 1 == 1 {
+                chk ^= g;
+            }
+        }
+    }
+    chk
+}
+
+fn hrp_expand(hrp: &str) -> Vec<u8> {
+    let mut result: Vec<u8> = hrp.bytes().map(|c| c >> 5).collect();
+    result.push(0);
+    result.extend(hrp.bytes().map(|c| c & 31));
+    result
+}
+
+fn checksum_variant(hrp: &str, data: &[u8]) -> Option<Variant> {
+    let constant = polymod(&[hrp_expand(hrp), data.to_vec()].concat());
+    [Variant::Bech32, Variant::Bech32m]
+        .into_iter()
+        .find(|variant| variant.constant() == constant)
+}
+
+// Looks for a single substituted character that would make the checksum valid.
+fn locate_error(hrp: &str, data: &[u8]) -> Vec<usize> {
+    let mut data = data.to_vec();
+    for i in 0..data.len() {
+        let original = data[i];
+        for candidate in 0..32 {
+            if candidate == original {
+                continue;
+            }
+            data[i] = candidate;
+            if checksum_variant(hrp, &data).is_some() {
+                return vec![hrp.len() + 1 + i];
+            }
+        }
+        data[i] = original;
+    }
+    Vec::new()
+}
+
+pub fn convert_bits(data: &[u8], from: u32, to: u32, pad: bool) -> Option<Vec<u8>> {
+    let mut acc: u32 = 0;
+    let mut bits: u32 = 0;
+    let mut result: Vec<u8> = Vec::new();
+    let maxv = (1 << to) - 1;
+    for value in data {
+        if (*value as u32) >> from != 0 {
+            return None;
+        }
+        acc = (acc << from) | *value as u32;
+        bits += from;
+        while bits >= to {
+            bits -= to;
+            result.push(((acc >> bits) & maxv) as u8);
+        }
+    }
+    if pad {
+        if bits > 0 {
+            result.push(((acc << (to - bits)) & maxv) as u8);
+        }
+    } else if bits >= from || ((acc << (to - bits)) & maxv) != 0 {
+        return None;
+    }
+    Some(result)
+}
+
+// `data` holds 5 bit values, the hrp is expected in lower case.
+pub fn encode(hrp: &str, data: &[u8], variant: Variant) -> Result<String, Bech32Error> {
+    if hrp.is_empty() || hrp.bytes().any(|c| !(33..=126).contains(&c)) {
+        return Err(Bech32Error::InvalidHrp);
+    }
+    let length = hrp.len() + 1 + data.len() + CHECKSUM_LENGTH;
+    if length > MAX_LENGTH {
+        return Err(Bech32Error::InvalidLength(length));
+    }
+    let values = [hrp_expand(hrp), data.to_vec(), vec![0; CHECKSUM_LENGTH]].concat();
+    let polymod = polymod(&values) ^ variant.constant();
+    let checksum = (0..CHECKSUM_LENGTH).map(|i| ((polymod >> (5 * (5 - i))) & 31) as u8);
+    let encoded: String = data
+        .iter()
+        .copied()
+        .chain(checksum)
+        .map(|d| CHARSET[d as usize] as char)
+        .collect();
+    Ok(format!("{}1{}", hrp, encoded))
+}
+
+// Returns the lower cased hrp, the 5 bit data without checksum and the checksum variant.
+pub fn decode(s: &str) -> Result<(String, Vec<u8>, Variant), Bech32Error> {
+    if s.len() > MAX_LENGTH {
+        return Err(Bech32Error::InvalidLength(s.len()));
+    }
+    for (position, c) in s.bytes().enumerate() {
+        if !(33..=126).contains(&c) {
+            return Err(Bech32Error::InvalidCharacter(position));
+        }
+    }
+    let has_lower = s.bytes().any(|c| c.is_ascii_lowercase());
+    if let Some(position) = s.bytes().position(|c| c.is_ascii_uppercase()) {
+        if has_lower {
+            return Err(Bech32Error::MixedCase(position));
+        }
+    }
+    let s = s.to_lowercase();
+    let separator = match s.rfind('1') {
+        Some(separator) => separator,
+        None => return Err(Bech32Error::MissingSeparator),
+    };
+    if separator == 0 {
+        return Err(Bech32Error::InvalidHrp);
+    }
+    if separator + 1 + CHECKSUM_LENGTH > s.len() {
+        return Err(Bech32Error::InvalidLength(s.len()));
+    }
+    let hrp = &s[..separator];
+    let mut data: Vec<u8> = Vec::new();
+    for (position, c) in s.bytes().enumerate().skip(separator + 1) {
+        match CHARSET.iter().position(|x| *x == c) {
+            Some(value) => data.push(value as u8),
+            None => return Err(Bech32Error::InvalidCharacter(position)),
+        }
+    }
+    let variant = match checksum_variant(hrp, &data) {
+        Some(variant) => variant,
+        None => return Err(Bech32Error::InvalidChecksum(locate_error(hrp, &data))),
+    };
+    data.truncate(data.len() - CHECKSUM_LENGTH);
+    Ok((hrp.to_string(), data, variant))
+}
+
+fn check_witness_program(witness_version: u8, program: &[u8]) -> Result<(), Bech32Error> {
+    if witness_version > 16 {
+        return Err(Bech32Error::InvalidWitnessVersion(witness_version));
+    }
+    if program.len() < 2 || program.len() > 40 {
+        return Err(Bech32Error::InvalidProgramLength(program.len()));
+    }
+    if witness_version == 0 && program.len() != 20 && program.len() != 32 {
+        return Err(Bech32Error::InvalidProgramLength(program.len()));
+    }
+    Ok(())
+}
+
+pub fn encode_segwit_address(
+    hrp: &str,
+    witness_version: u8,
+    program: &[u8],
+) -> Result<String, Bech32Error> {
+    check_witness_program(witness_version, program)?;
+    let mut data = vec![witness_version];
+    data.append(&mut convert_bits(program, 8, 5, true).unwrap());
+    encode(hrp, &data, Variant::for_witness_version(witness_version))
+}
+
+// Returns the witness version and program of an address for the given hrp.
+pub fn decode_segwit_address(hrp: &str, address: &str) -> Result<(u8, Vec<u8>), Bech32Error> {
+    let (decoded_hrp, data, variant) = decode(address)?;
+    if decoded_hrp != hrp {
+        return Err(Bech32Error::HrpMismatch(decoded_hrp));
+    }
+    let witness_version = match data.first() {
+        Some(witness_version) => *witness_version,
+        None => return Err(Bech32Error::InvalidProgramLength(0)),
+    };
+    let program = match convert_bits(&data[1..], 5, 8, false) {
+        Some(program) => program,
+        None => return Err(Bech32Error::InvalidPadding),
+    };
+    check_witness_program(witness_version, &program)?;
+    if variant != Variant::for_witness_version(witness_version) {
+        return Err(Bech32Error::WrongVariant(variant));
+    }
+    Ok((witness_version, program))
+}
+
+#[cfg(test)]
+mod bech32_tests {
+    use super::{
+        decode, decode_segwit_address, encode, encode_segwit_address, Bech32Error, Variant,
+    };
+
+    // BIP173 and BIP350 test vectors
+    #[test]
+    fn test_valid_checksums() {
+        for s in [
+            "A12UEL5L",
+            "a12uel5l",
+            "an83characterlonghumanreadablepartthatcontainsthenumber1andtheexcludedcharactersbio1tt5tgs",
+            "abcdef1qpzry9x8gf2tvdw0s3jn54khce6mua7lmqqqxw",
+            "split1checkupstagehandshakeupstreamerranterredcaperred2y9e3w",
+        ] {
+            let (hrp, data, variant) = decode(s).unwrap();
+            assert_eq!(variant, Variant::Bech32);
+            assert_eq!(encode(&hrp, &data, variant).unwrap(), s.to_lowercase());
+        }
+        for s in [
+            "A1LQFN3A",
+            "abcdef1l7aum6echk45nj3s0wdvt2fg8x9yrzpqzd3ryx",
+            "split1checkupstagehandshakeupstreamerranterredcaperredlc445v",
+        ] {
+            let (hrp, data, variant) = decode(s).unwrap();
+            assert_eq!(variant, Variant::Bech32m);
+            assert_eq!(encode(&hrp, &data, variant).unwrap(), s.to_lowercase());
+        }
+    }
+
+    #[test]
+    fn test_invalid_strings() {
+        assert_eq!(decode("pzry9x0s0muk"), Err(Bech32Error::MissingSeparator));
+        assert_eq!(decode("1pzry9x0s0muk"), Err(Bech32Error::InvalidHrp));
+        assert_eq!(decode("x1b4n0q5v"), Err(Bech32Error::InvalidCharacter(2)));
+        assert_eq!(decode("li1dgmt3"), Err(Bech32Error::InvalidLength(8)));
+        assert_eq!(decode("A12uEL5L"), Err(Bech32Error::MixedCase(0)));
+        assert_eq!(
+            decode("\u{7f}1axkwrx"),
+            Err(Bech32Error::InvalidCharacter(0))
+        );
+        // a single typo is located
+        assert_eq!(
+            decode("bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t5"),
+            Err(Bech32Error::InvalidChecksum(vec![41]))
+        );
+        assert_eq!(
+            decode("bc1qw508d6qejxtdg4y5r3zarvarx0c5xw7kv8f3t4"),
+            Err(Bech32Error::InvalidChecksum(vec![28]))
+        );
+    }
+
+    #[test]
+    fn test_segwit_addresses() {
+        let program = hex::decode("751e76e8199196d454941c45d1b3a323f1433bd6").unwrap();
+        let address = "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4";
+        assert_eq!(encode_segwit_address("bc", 0, &program).unwrap(), address);
+        assert_eq!(
+            decode_segwit_address("bc", &address.to_uppercase()).unwrap(),
+            (0, program)
+        );
+
+        let program =
+            hex::decode("1863143c14c5166804bd19203356da136c985678cd4d27a1b8c6329604903262")
+                .unwrap();
+        let address = "tb1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3q0sl5k7";
+        assert_eq!(encode_segwit_address("tb", 0, &program).unwrap(), address);
+        assert_eq!(
+            decode_segwit_address("tb", address).unwrap(),
+            (0, program.clone())
+        );
+        assert_eq!(
+            decode_segwit_address("bc", address),
+            Err(Bech32Error::HrpMismatch("tb".to_string()))
+        );
+
+        let program =
+            hex::decode("a60869f0dbcf1dc659c9cecbaf8050135ea9e8cdc487053f1dc6880949dc684c")
+                .unwrap();
+        let address = "bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr";
+        assert_eq!(encode_segwit_address("bc", 1, &program).unwrap(), address);
+        assert_eq!(decode_segwit_address("bc", address).unwrap(), (1, program));
+
+        let program = hex::decode("751e").unwrap();
+        assert_eq!(
+            decode_segwit_address("bc", "BC1SW50QGDZ25J").unwrap(),
+            (16, program)
+        );
+        let program = hex::decode("751e76e8199196d454941c45d1b3a323f1433bd6").unwrap();
+        assert_eq!(
+            decode_segwit_address("bcrt", &encode_segwit_address("bcrt", 0, &program).unwrap())
+                .unwrap(),
+            (0, program)
+        );
+    }
+
+    #[test]
+    fn test_invalid_segwit_addresses() {
+        // bech32m checksum on a v0 program and bech32 on v1
+        assert_eq!(
+            decode_segwit_address("bc", "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kemeawh"),
+            Err(Bech32Error::WrongVariant(Variant::Bech32m))
+        );
+        assert_eq!(
+            decode_segwit_address(
+                "bc",
+                "bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqh2y7hd"
+            ),
+            Err(Bech32Error::WrongVariant(Variant::Bech32))
+        );
+        assert_eq!(
+            decode_segwit_address(
+                "bc",
+                "BC1S0XLXVLHEMJA6C4DQV22UAPCTQUPFHLXM9H8Z3K2E72Q4K9HCZ7VQ54WELL"
+            ),
+            Err(Bech32Error::WrongVariant(Variant::Bech32))
+        );
+        assert_eq!(
+            decode_segwit_address("bc", "bc1pw5dgrnzv"),
+            Err(Bech32Error::InvalidProgramLength(1))
+        );
+        assert_eq!(
+            decode_segwit_address("bc", "BC1QR508D6QEJXTDG4Y5R3ZARVARYV98GJ9P"),
+            Err(Bech32Error::InvalidProgramLength(16))
+        );
+        assert!(encode_segwit_address("bc", 17, &[0; 20]).is_err());
+        assert!(encode_segwit_address("bc", 0, &[0; 21]).is_err());
+    }
+}
@@ -0,0 +1,332 @@
+// A small btcdeb-like script debugger.
+//
+//     script_debugger <script hex>
+//     script_debugger --tx <tx hex> --input <index> [--script-pubkey <hex>]
+//                     [--network <name>] [--standard]
+//
+// Without --script-pubkey the previous output is fetched from the network.
+use std::io::{self, BufRead, Cursor, Write};
+
+use anyhow::{bail, Context, Result};
+use rust::{
+    interpreter::Interpreter,
+    network::Network,
+    op,
+    script::{Command, Script, VerifyFlags},
+    signature_checker::{NoSignatureChecker, SignatureChecker, TransactionSignatureChecker},
+    tx::Tx,
+    utils,
+};
+
+const HELP: &str = "commands: [s]tep (or enter), [b]ack, [r]un, [p]rint, [t]race, [h]elp, [q]uit";
+
+struct Phase {
+    label: &'static str,
+    script: Script,
+}
+
+struct Session<'a> {
+    phases: &'a [Phase],
+    checker: &'a dyn SignatureChecker,
+    flags: VerifyFlags,
+    interpreters: Vec<Interpreter<'a>>,
+}
+
+impl<'a> Session<'a> {
+    fn new(phases: &'a [Phase], checker: &'a dyn SignatureChecker, flags: VerifyFlags) -> Self {
+        let mut session = Session {
+            phases,
+            checker,
+            flags,
+            interpreters: Vec::new(),
+        };
+        session.start_phase(Vec::new());
+        session
+    }
+
+    fn start_phase(&mut self, stack: Vec<Vec<u8>>) {
+        let phase = &self.phases[self.interpreters.len()];
+        let mut interpreter = Interpreter::new(&phase.script, stack, self.checker, self.flags);
+        interpreter.enable_trace();
+        self.interpreters.push(interpreter);
+    }
+
+    fn current(&self) -> &Interpreter<'a> {
+        self.interpreters.last().unwrap()
+    }
+
+    // Returns false once there is nothing left to run.
+    fn step(&mut self) -> bool {
+        let index = self.interpreters.len() - 1;
+        match self.interpreters[index].step() {
+            Ok(true) => true,
+            Ok(false) => {
+                let stack = self.current().stack().to_vec();
+                let success = matches!(stack.last(), Some(top) if op::cast_to_bool(top));
+                // like VerifyScript the scriptPubKey runs on whatever the
+                // scriptSig left, only the later phases have to leave true
+                let checked = self.phases[index].label != "scriptSig";
+                if (checked && !success) || index + 1 == self.phases.len() {
+                    println!(
+                        "{} finished, script {}",
+                        self.phases[index].label,
+                        verdict(success)
+                    );
+                    return false;
+                }
+                // BIP16: the redeem script runs on the stack the scriptSig left,
+                // without the serialized redeem script itself
+                let stack = if self.phases[index + 1].label == "redeemScript" {
+                    let mut stack = self.interpreters[0].stack().to_vec();
+                    stack.pop();
+                    stack
+                } else {
+                    stack
+                };
+                println!(
+                    "{} finished, continuing with {}",
+                    self.phases[index].label,
+                    self.phases[index + 1].label
+                );
+                self.start_phase(stack);
+                true
+            }
+            Err(err) => {
+                println!("error: {}", err);
+                false
+            }
+        }
+    }
+
+    fn step_back(&mut self) -> bool {
+        if self.interpreters.last_mut().unwrap().step_back() {
+            return true;
+        }
+        if self.interpreters.len() > 1 {
+            self.interpreters.pop();
+            return true;
+        }
+        false
+    }
+
+    fn print(&self) {
+        let interpreter = self.current();
+        let label = self.phases[self.interpreters.len() - 1].label;
+        println!("{}:", label);
+        let instructions = interpreter.instructions();
+        for (pc, instruction) in instructions.iter().enumerate() {
+            let marker = if pc == interpreter.pc() { "->" } else { "  " };
+            println!(
+                "{} {:4} {}",
+                marker,
+                pc,
+                format_command(&instruction.command)
+            );
+        }
+        let raw = interpreter.script().as_bytes();
+        let decoded = instructions.last().map_or(0, |instruction| instruction.end);
+        if decoded < raw.len() {
+            println!("        0x{} (undecodable)", hex::encode(&raw[decoded..]));
+        }
+        if interpreter.pc() >= instructions.len() {
+            println!("->      <end>");
+        }
+        print_stack("stack", interpreter.stack());
+        print_stack("altstack", interpreter.altstack());
+        let condition_stack = interpreter.condition_stack();
+        if !condition_stack.empty() {
+            println!(
+                "if depth {}, {}",
+                condition_stack.depth(),
+                if condition_stack.all_true() {
+                    "executing"
+                } else {
+                    "skipping"
+                }
+            );
+        }
+        if let Some(err) = interpreter.error() {
+            println!("error: {}", err);
+        }
+    }
+
+    fn print_trace(&self) {
+        for step in self.current().trace() {
+            let top = match step.state.stack.last() {
+                Some(top) => format_element(top),
+                None => "<empty>".to_string(),
+            };
+            println!(
+                "{:4} {:<24} top: {}",
+                step.pc,
+                format_command(&step.command),
+                top
+            );
+        }
+    }
+}
+
+fn verdict(success: bool) -> &'static str {
+    if success {
+        "succeeded"
+    } else {
+        "evaluated to false"
+    }
+}
+
+fn format_element(element: &[u8]) -> String {
+    if element.is_empty() {
+        "0x (empty)".to_string()
+    } else {
+        format!("0x{}", hex::encode(element))
+    }
+}
+
+fn format_command(cmd: &Command) -> String {
+    match cmd {
+        Command::Element(element) => format_element(element),
+        Command::Operation(op) => op::get_op_names(op).to_string(),
+    }
+}
+
+fn print_stack(name: &str, stack: &[Vec<u8>]) {
+    println!("{} ({} items, top first):", name, stack.len());
+    for element in stack.iter().rev() {
+        println!("    {}", format_element(element));
+    }
+}
+
+fn parse_script(raw_hex: &str) -> Result<Script> {
+    let raw = hex::decode(raw_hex).context("script is not valid hex")?;
+    let mut cursor = Cursor::new([utils::encode_varint(raw.len()), raw].concat());
+    Ok(Script::parse(&mut cursor)?)
+}
+
+struct Args {
+    script: Option<String>,
+    tx: Option<String>,
+    input: usize,
+    script_pubkey: Option<String>,
+    network: Network,
+    flags: VerifyFlags,
+}
+
+fn parse_args() -> Result<Args> {
+    let mut args = Args {
+        script: None,
+        tx: None,
+        input: 0,
+        script_pubkey: None,
+        network: Network::Mainnet,
+        flags: VerifyFlags::MANDATORY,
+    };
+    let mut iter = std::env::args().skip(1);
+    while let Some(arg) = iter.next() {
+        let mut value = || {
+            iter.next()
+                .with_context(|| format!("{} needs a value", arg))
+        };
+        match arg.as_str() {
+            "--tx" => args.tx = Some(value()?),
+            "--input" => args.input = value()?.parse().context("bad input index")?,
+            "--script-pubkey" => args.script_pubkey = Some(value()?),
+            "--network" => args.network = value()?.parse()?,
+            "--standard" => args.flags = VerifyFlags::STANDARD,
+            _ if !arg.starts_with("--") && args.script.is_none() => args.script = Some(arg),
+            _ => bail!("unexpected argument: {}", arg),
+        }
+    }
+    if args.script.is_none() && args.tx.is_none() {
+        bail!("usage: script_debugger <script hex> | --tx <hex> --input <index> [--script-pubkey <hex>] [--network <name>] [--standard]");
+    }
+    Ok(args)
+}
+
+fn main() -> Result<()> {
+    let args = parse_args()?;
+    let mut phases = Vec::new();
+    let tx = match &args.tx {
+        Some(raw) => {
+            let raw = hex::decode(raw).context("transaction is not valid hex")?;
+            Some(Tx::parse(&mut Cursor::new(raw), args.network)?)
+        }
+        None => None,
+    };
+
+    match &tx {
+        Some(tx) => {
+            let tx_in = tx
+                .tx_ins
+                .get(args.input)
+                .context("input index out of range")?;
+            let script_pubkey = match &args.script_pubkey {
+                Some(raw) => parse_script(raw)?,
+                None => tx_in.script_pubkey(args.network)?,
+            };
+            let script_sig = tx_in.script_sig.clone().unwrap_or(Script::new(None));
+            let redeem_script = if script_pubkey.is_p2sh() {
+                script_sig.redeem_script()
+            } else {
+                None
+            };
+            phases.push(Phase {
+                label: "scriptSig",
+                script: script_sig,
+            });
+            phases.push(Phase {
+                label: "scriptPubKey",
+                script: script_pubkey,
+            });
+            if let Some(redeem_script) = redeem_script {
+                phases.push(Phase {
+                    label: "redeemScript",
+                    script: redeem_script,
+                });
+            }
+        }
+        None => phases.push(Phase {
+            label: "script",
+            script: parse_script(args.script.as_ref().unwrap())?,
+        }),
+    }
+    // without a transaction every signature check fails
+    let checker: Box<dyn SignatureChecker> = match &tx {
+        Some(tx) => Box::new(TransactionSignatureChecker::new(tx, args.input)),
+        None => Box::new(NoSignatureChecker),
+    };
+
+    let mut session = Session::new(&phases, checker.as_ref(), args.flags);
+    println!("{}", HELP);
+    session.print();
+    let stdin = io::stdin();
+    loop {
+        print!("> ");
+        io::stdout().flush()?;
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line)? == 0 {
+            break;
+        }
+        match line.trim() {
+            "" | "s" | "step" => {
+                session.step();
+                session.print();
+            }
+            "b" | "back" => {
+                if !session.step_back() {
+                    println!("already at the start");
+                }
+                session.print();
+            }
+            "r" | "run" => {
+                while session.step() {}
+                session.print();
+            }
+            "p" | "print" => session.print(),
+            "t" | "trace" => session.print_trace(),
+            "h" | "help" => println!("{}", HELP),
+            "q" | "quit" => break,
+            other => println!("unknown command: {}\n{}", other, HELP),
+        }
+    }
+    Ok(())
+}
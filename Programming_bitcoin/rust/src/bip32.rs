@@ -0,0 +1,354 @@
+use anyhow::{bail, Result};
+use num_bigint::BigInt;
+
+use crate::{
+    derivation_path::DerivationPath, network::Network, private_key::PrivateKey,
+    s256_point::S256Point, utils, G, N,
+};
+
+pub const HARDENED_OFFSET: u32 = 0x80000000;
+
+// The hardened child number for an index below 2^31.
+pub fn hardened(index: u32) -> Result<u32> {
+    if index >= HARDENED_OFFSET {
+        bail!("hardened index out of range: {}", index)
+    }
+    Ok(index + HARDENED_OFFSET)
+}
+
+// SLIP-132 (private, public) version bytes. xprv/xpub also serve P2TR, test
+// networks share the t, u and v versions.
+pub const XPRV_XPUB: ([u8; 4], [u8; 4]) = ([0x04, 0x88, 0xad, 0xe4], [0x04, 0x88, 0xb2, 0x1e]);
+pub const TPRV_TPUB: ([u8; 4], [u8; 4]) = ([0x04, 0x35, 0x83, 0x94], [0x04, 0x35, 0x87, 0xcf]);
+// P2SH-P2WPKH
+pub const YPRV_YPUB: ([u8; 4], [u8; 4]) = ([0x04, 0x9d, 0x78, 0x78], [0x04, 0x9d, 0x7c, 0xb2]);
+pub const UPRV_UPUB: ([u8; 4], [u8; 4]) = ([0x04, 0x4a, 0x4e, 0x28], [0x04, 0x4a, 0x52, 0x62]);
+// P2WPKH
+pub const ZPRV_ZPUB: ([u8; 4], [u8; 4]) = ([0x04, 0xb2, 0x43, 0x0c], [0x04, 0xb2, 0x47, 0x46]);
+pub const VPRV_VPUB: ([u8; 4], [u8; 4]) = ([0x04, 0x5f, 0x18, 0xbc], [0x04, 0x5f, 0x1c, 0xf6]);
+
+// The plain xprv/xpub versions of a network.
+fn default_version_bytes(network: Network) -> ([u8; 4], [u8; 4]) {
+    if network.is_testnet() {
+        TPRV_TPUB
+    } else {
+        XPRV_XPUB
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ExtendedPrivateKey {
+    pub private_key: PrivateKey,
+    pub chain_code: Vec<u8>,
+    pub depth: u8,
+    pub parent_fingerprint: Vec<u8>,
+    pub child_number: u32,
+    pub network: Network,
+}
+
+#[derive(Debug, Clone)]
+pub struct ExtendedPublicKey {
+    pub point: S256Point,
+    pub chain_code: Vec<u8>,
+    pub depth: u8,
+    pub parent_fingerprint: Vec<u8>,
+    pub child_number: u32,
+    pub network: Network,
+}
+
+impl ExtendedPrivateKey {
+    pub fn new_master(seed: &[u8], network: Network) -> Result<Self> {
+        if seed.len() < 16 || seed.len() > 64 {
+            bail!("seed must be between 16 and 64 bytes, got {}", seed.len())
+        }
+        let i = utils::hmac_sha512(b"Bitcoin seed", seed);
+        let secret = BigInt::from_bytes_be(num_bigint::Sign::Plus, &i[0..32]);
+        if secret == BigInt::from(0) || secret >= N.to_owned() {
+            bail!("seed produces an invalid master key")
+        }
+        Ok(ExtendedPrivateKey {
+            private_key: PrivateKey::new(&PrivateKey::generate_simple_secret(secret)),
+            chain_code: i[32..64].to_vec(),
+            depth: 0,
+            parent_fingerprint: vec![0; 4],
+            child_number: 0,
+            network,
+        })
+    }
+
+    // Indexes at or above HARDENED_OFFSET derive hardened children, which commit to
+    // the parent private key instead of the public key.
+    pub fn derive_child(&self, index: u32) -> Result<Self> {
+        // the depth is a single byte in the serialization
+        let depth = match self.depth.checked_add(1) {
+            Some(depth) => depth,
+            None => bail!("cannot derive a child below depth {}", self.depth),
+        };
+        let data = if index >= HARDENED_OFFSET {
+            [
+                vec![0],
+                utils::int_to_big_endian(&self.private_key.secret, 32),
+                index.to_be_bytes().to_vec(),
+            ]
+            .concat()
+        } else {
+            [
+                self.private_key.point.clone().sec(Some(true)),
+                index.to_be_bytes().to_vec(),
+            ]
+            .concat()
+        };
+        let i = utils::hmac_sha512(&self.chain_code, &data);
+        let tweak = BigInt::from_bytes_be(num_bigint::Sign::Plus, &i[0..32]);
+        if tweak >= N.to_owned() {
+            bail!("invalid child key at index {}", index)
+        }
+        let secret = (tweak + &self.private_key.secret) % N.to_owned();
+        if secret == BigInt::from(0) {
+            bail!("invalid child key at index {}", index)
+        }
+        Ok(ExtendedPrivateKey {
+            private_key: PrivateKey::new(&PrivateKey::generate_simple_secret(secret)),
+            chain_code: i[32..64].to_vec(),
+            depth,
+            parent_fingerprint: self.fingerprint(),
+            child_number: index,
+            network: self.network,
+        })
+    }
+
+    pub fn derive_path(&self, path: &DerivationPath) -> Result<Self> {
+        let mut key = self.clone();
+        for index in &path.path {
+            key = key.derive_child(*index)?;
+        }
+        Ok(key)
+    }
+
+    pub fn fingerprint(&self) -> Vec<u8> {
+        self.private_key.point.clone().hash160(Some(true))[0..4].to_vec()
+    }
+
+    pub fn to_extended_public_key(&self) -> ExtendedPublicKey {
+        ExtendedPublicKey {
+            point: self.private_key.point.clone(),
+            chain_code: self.chain_code.clone(),
+            depth: self.depth,
+            parent_fingerprint: self.parent_fingerprint.clone(),
+            child_number: self.child_number,
+            network: self.network,
+        }
+    }
+
+    pub fn parse(s: &str) -> Result<Self> {
+        let (data, network) = parse_extended_key(s, true)?;
+        if data[45] != 0 {
+            bail!("extended private key must have a zero padding byte")
+        }
+        let secret = BigInt::from_bytes_be(num_bigint::Sign::Plus, &data[46..78]);
+        if secret == BigInt::from(0) || secret >= N.to_owned() {
+            bail!("extended private key is out of range")
+        }
+        Ok(ExtendedPrivateKey {
+            private_key: PrivateKey::new(&PrivateKey::generate_simple_secret(secret)),
+            chain_code: data[13..45].to_vec(),
+            depth: data[4],
+            parent_fingerprint: data[5..9].to_vec(),
+            child_number: u32::from_be_bytes(data[9..13].try_into().unwrap()),
+            network,
+        })
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        self.serialize_with_version(default_version_bytes(self.network).0)
+    }
+
+    // Lets accounts use their SLIP-132 version bytes (yprv, zprv, ...) instead of xprv.
+    pub fn serialize_with_version(&self, version: [u8; 4]) -> Vec<u8> {
+        [
+            version.to_vec(),
+            vec![self.depth],
+            self.parent_fingerprint.clone(),
+            self.child_number.to_be_bytes().to_vec(),
+            self.chain_code.clone(),
+            vec![0],
+            utils::int_to_big_endian(&self.private_key.secret, 32),
+        ]
+        .concat()
+    }
+
+    pub fn xprv(&self) -> String {
+        utils::encode_base58_checksum(&self.serialize())
+    }
+}
+
+impl ExtendedPublicKey {
+    pub fn parse(s: &str) -> Result<Self> {
+        let (data, network) = parse_extended_key(s, false)?;
+        Ok(ExtendedPublicKey {
+            point: S256Point::parse(&data[45..78])?,
+            chain_code: data[13..45].to_vec(),
+            depth: data[4],
+            parent_fingerprint: data[5..9].to_vec(),
+            child_number: u32::from_be_bytes(data[9..13].try_into().unwrap()),
+            network,
+        })
+    }
+
+    pub fn derive_child(&self, index: u32) -> Result<Self> {
+        if index >= HARDENED_OFFSET {
+            bail!("cannot derive hardened child {} from a public key", index)
+        }
+        // the depth is a single byte in the serialization
+        let depth = match self.depth.checked_add(1) {
+            Some(depth) => depth,
+            None => bail!("cannot derive a child below depth {}", self.depth),
+        };
+        let data = [
+            self.point.clone().sec(Some(true)),
+            index.to_be_bytes().to_vec(),
+        ]
+        .concat();
+        let i = utils::hmac_sha512(&self.chain_code, &data);
+        let tweak = BigInt::from_bytes_be(num_bigint::Sign::Plus, &i[0..32]);
+        if tweak >= N.to_owned() {
+            bail!("invalid child key at index {}", index)
+        }
+        let point = tweak * &G.to_owned() + self.point.point.clone();
+        Ok(ExtendedPublicKey {
+            point: S256Point { point },
+            chain_code: i[32..64].to_vec(),
+            depth,
+            parent_fingerprint: self.fingerprint(),
+            child_number: index,
+            network: self.network,
+        })
+    }
+
+    pub fn derive_path(&self, path: &DerivationPath) -> Result<Self> {
+        let mut key = self.clone();
+        for index in &path.path {
+            key = key.derive_child(*index)?;
+        }
+        Ok(key)
+    }
+
+    pub fn fingerprint(&self) -> Vec<u8> {
+        self.point.clone().hash160(Some(true))[0..4].to_vec()
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        self.serialize_with_version(default_version_bytes(self.network).1)
+    }
+
+    pub fn serialize_with_version(&self, version: [u8; 4]) -> Vec<u8> {
+        [
+            version.to_vec(),
+            vec![self.depth],
+            self.parent_fingerprint.clone(),
+            self.child_number.to_be_bytes().to_vec(),
+            self.chain_code.clone(),
+            self.point.clone().sec(Some(true)),
+        ]
+        .concat()
+    }
+
+    pub fn xpub(&self) -> String {
+        utils::encode_base58_checksum(&self.serialize())
+    }
+}
+
+// Returns the 78 byte payload and the network its version bytes belong to.
+fn parse_extended_key(s: &str, private: bool) -> Result<(Vec<u8>, Network)> {
+    let data = utils::decode_base58_checksum(s)?;
+    if data.len() != 78 {
+        bail!("extended key must be 78 bytes, got {}", data.len())
+    }
+    let version: [u8; 4] = data[0..4].try_into().unwrap();
+    let known = [
+        (XPRV_XPUB, Network::Mainnet),
+        (YPRV_YPUB, Network::Mainnet),
+        (ZPRV_ZPUB, Network::Mainnet),
+        (TPRV_TPUB, Network::Testnet),
+        (UPRV_UPUB, Network::Testnet),
+        (VPRV_VPUB, Network::Testnet),
+    ];
+    let found = known.iter().find_map(|((private, public), network)| {
+        if version == *private {
+            Some((*network, true))
+        } else if version == *public {
+            Some((*network, false))
+        } else {
+            None
+        }
+    });
+    match found {
+        Some((network, is_private)) if is_private == private => Ok((data, network)),
+        Some(_) if private => bail!("expected an extended private key"),
+        Some(_) => bail!("expected an extended public key"),
+        None => bail!("unknown extended key version: {}", hex::encode(version)),
+    }
+}
+
+#[cfg(test)]
+mod bip32_tests {
+    use crate::network::Network;
+
+    use super::{ExtendedPrivateKey, ExtendedPublicKey, HARDENED_OFFSET};
+
+    #[test]
+    fn test_vector_1() {
+        let seed = hex::decode("000102030405060708090a0b0c0d0e0f").unwrap();
+        let master = ExtendedPrivateKey::new_master(&seed, Network::Mainnet).unwrap();
+        assert_eq!(master.xprv(), "xprv9s21ZrQH143K3QTDL4LXw2F7HEK3wJUD2nW2nRk4stbPy6cq3jPPqjiChkVvvNKmPGJxWUtg6LnF5kejMRNNU3TGtRBeJgk33yuGBxrMPHi");
+        assert_eq!(master.to_extended_public_key().xpub(), "xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gZ29ESFjqJoCu1Rupje8YtGqsefD265TMg7usUDFdp6W1EGMcet8");
+
+        let child = master.derive_child(HARDENED_OFFSET).unwrap();
+        assert_eq!(child.xprv(), "xprv9uHRZZhk6KAJC1avXpDAp4MDc3sQKNxDiPvvkX8Br5ngLNv1TxvUxt4cV1rGL5hj6KCesnDYUhd7oWgT11eZG7XnxHrnYeSvkzY7d2bhkJ7");
+        assert_eq!(child.to_extended_public_key().xpub(), "xpub68Gmy5EdvgibQVfPdqkBBCHxA5htiqg55crXYuXoQRKfDBFA1WEjWgP6LHhwBZeNK1VTsfTFUHCdrfp1bgwQ9xv5ski8PX9rL2dZXvgGDnw");
+
+        let grandchild = child.derive_child(1).unwrap();
+        assert_eq!(grandchild.xprv(), "xprv9wTYmMFdV23N2TdNG573QoEsfRrWKQgWeibmLntzniatZvR9BmLnvSxqu53Kw1UmYPxLgboyZQaXwTCg8MSY3H2EU4pWcQDnRnrVA1xe8fs");
+        assert_eq!(grandchild.to_extended_public_key().xpub(), "xpub6ASuArnXKPbfEwhqN6e3mwBcDTgzisQN1wXN9BJcM47sSikHjJf3UFHKkNAWbWMiGj7Wf5uMash7SyYq527Hqck2AxYysAA7xmALppuCkwQ");
+    }
+
+    #[test]
+    fn test_public_derivation_matches_private() {
+        let seed = hex::decode("000102030405060708090a0b0c0d0e0f").unwrap();
+        let child = ExtendedPrivateKey::new_master(&seed, Network::Mainnet)
+            .unwrap()
+            .derive_child(HARDENED_OFFSET)
+            .unwrap();
+        let from_public = child.to_extended_public_key().derive_child(1).unwrap();
+        assert_eq!(from_public.xpub(), "xpub6ASuArnXKPbfEwhqN6e3mwBcDTgzisQN1wXN9BJcM47sSikHjJf3UFHKkNAWbWMiGj7Wf5uMash7SyYq527Hqck2AxYysAA7xmALppuCkwQ");
+        assert!(child
+            .to_extended_public_key()
+            .derive_child(HARDENED_OFFSET)
+            .is_err());
+    }
+
+    #[test]
+    fn test_parse_extended_keys() {
+        let xprv = "xprv9wTYmMFdV23N2TdNG573QoEsfRrWKQgWeibmLntzniatZvR9BmLnvSxqu53Kw1UmYPxLgboyZQaXwTCg8MSY3H2EU4pWcQDnRnrVA1xe8fs";
+        let key = ExtendedPrivateKey::parse(xprv).unwrap();
+        assert_eq!(key.depth, 2);
+        assert_eq!(key.child_number, 1);
+        assert_eq!(key.xprv(), xprv);
+        let xpub = "xpub6ASuArnXKPbfEwhqN6e3mwBcDTgzisQN1wXN9BJcM47sSikHjJf3UFHKkNAWbWMiGj7Wf5uMash7SyYq527Hqck2AxYysAA7xmALppuCkwQ";
+        assert_eq!(ExtendedPublicKey::parse(xpub).unwrap().xpub(), xpub);
+        assert!(ExtendedPrivateKey::parse(xpub).is_err());
+        assert!(ExtendedPublicKey::parse(xprv).is_err());
+    }
+
+    #[test]
+    fn test_derive_at_max_depth() {
+        let seed = hex::decode("000102030405060708090a0b0c0d0e0f").unwrap();
+        let mut key = ExtendedPrivateKey::new_master(&seed, Network::Mainnet).unwrap();
+        key.depth = 255;
+        let key = ExtendedPrivateKey::parse(&key.xprv()).unwrap();
+        assert_eq!(key.depth, 255);
+        assert!(key.derive_child(0).is_err());
+        assert!(key.derive_child(HARDENED_OFFSET).is_err());
+        assert!(key.to_extended_public_key().derive_child(0).is_err());
+    }
+}
@@ -0,0 +1,227 @@
+use std::fmt::Display;
+
+use anyhow::{bail, Result};
+use once_cell::sync::Lazy;
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+use crate::{bip32::ExtendedPrivateKey, network::Network, utils};
+
+const PBKDF2_ROUNDS: u32 = 2048;
+
+pub static ENGLISH_WORDLIST: Lazy<Vec<&'static str>> = Lazy::new(|| {
+    include_str!("wordlists/bip39_english.txt")
+        .lines()
+        .collect()
+});
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mnemonic {
+    pub words: Vec<String>,
+}
+
+impl Mnemonic {
+    pub fn from_entropy(entropy: &[u8]) -> Result<Self> {
+        if entropy.len() < 16 || entropy.len() > 32 || !entropy.len().is_multiple_of(4) {
+            bail!(
+                "entropy must be 16, 20, 24, 28 or 32 bytes, got {}",
+                entropy.len()
+            )
+        }
+        let checksum_bits = entropy.len() / 4;
+        let checksum = Sha256::digest(entropy);
+        let mut bits: Vec<bool> = Vec::new();
+        for byte in entropy.iter().chain(checksum.iter().take(1)) {
+            for i in (0..8).rev() {
+                bits.push((byte >> i) & 1 == 1);
+            }
+        }
+        bits.truncate(entropy.len() * 8 + checksum_bits);
+        let words = bits
+            .chunks(11)
+            .map(|chunk| {
+                let index = chunk
+                    .iter()
+                    .fold(0_usize, |acc, bit| (acc << 1) | *bit as usize);
+                ENGLISH_WORDLIST[index].to_string()
+            })
+            .collect();
+        Ok(Mnemonic { words })
+    }
+
+    pub fn generate(word_count: usize) -> Result<Self> {
+        if ![12, 15, 18, 21, 24].contains(&word_count) {
+            bail!(
+                "word count must be 12, 15, 18, 21 or 24, got {}",
+                word_count
+            )
+        }
+        let mut entropy = vec![0; word_count * 4 / 3];
+        rand::thread_rng().fill_bytes(&mut entropy);
+        Mnemonic::from_entropy(&entropy)
+    }
+
+    pub fn parse(phrase: &str) -> Result<Self> {
+        let words: Vec<String> = phrase.split_whitespace().map(str::to_lowercase).collect();
+        let mnemonic = Mnemonic { words };
+        mnemonic.to_entropy()?;
+        Ok(mnemonic)
+    }
+
+    pub fn to_entropy(&self) -> Result<Vec<u8>> {
+        if ![12, 15, 18, 21, 24].contains(&self.words.len()) {
+            bail!(
+                "mnemonic must have 12, 15, 18, 21 or 24 words, got {}",
+                self.words.len()
+            )
+        }
+        let mut bits: Vec<bool> = Vec::new();
+        for word in &self.words {
+            let index = match ENGLISH_WORDLIST.binary_search(&word.as_str()) {
+                Ok(index) => index,
+                Err(_) => bail!("unknown mnemonic word: {}", word),
+            };
+            for i in (0..11).rev() {
+                bits.push((index >> i) & 1 == 1);
+            }
+        }
+        let checksum_bits = bits.len() / 33;
+        let entropy: Vec<u8> = bits[..bits.len() - checksum_bits]
+            .chunks(8)
+            .map(|chunk| chunk.iter().fold(0_u8, |acc, bit| (acc << 1) | *bit as u8))
+            .collect();
+        let checksum = Sha256::digest(&entropy)[0] >> (8 - checksum_bits);
+        let expected = bits[bits.len() - checksum_bits..]
+            .iter()
+            .fold(0_u8, |acc, bit| (acc << 1) | *bit as u8);
+        if checksum != expected {
+            bail!("invalid mnemonic checksum")
+        }
+        Ok(entropy)
+    }
+
+    // Non-ASCII passphrases must already be NFKD normalized, the English wordlist is plain ASCII.
+    pub fn to_seed(&self, passphrase: Option<&str>) -> Vec<u8> {
+        let salt = format!("mnemonic{}", passphrase.unwrap_or(""));
+        utils::pbkdf2_hmac_sha512(
+            self.to_string().as_bytes(),
+            salt.as_bytes(),
+            PBKDF2_ROUNDS,
+            64,
+        )
+    }
+
+    pub fn to_master_key(
+        &self,
+        passphrase: Option<&str>,
+        network: Network,
+    ) -> Result<ExtendedPrivateKey> {
+        ExtendedPrivateKey::new_master(&self.to_seed(passphrase), network)
+    }
+}
+
+impl Display for Mnemonic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.words.join(" "))
+    }
+}
+
+#[cfg(test)]
+mod bip39_tests {
+    use crate::network::Network;
+
+    use super::Mnemonic;
+
+    // https://github.com/trezor/python-mnemonic/blob/master/vectors.json
+    const TREZOR_VECTORS: [(&str, &str, &str, &str); 8] = [
+        (
+            "00000000000000000000000000000000",
+            "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about",
+            "c55257c360c07c72029aebc1b53c05ed0362ada38ead3e3e9efa3708e53495531f09a6987599d18264c1e1c92f2cf141630c7a3c4ab7c81b2f001698e7463b04",
+            "xprv9s21ZrQH143K3h3fDYiay8mocZ3afhfULfb5GX8kCBdno77K4HiA15Tg23wpbeF1pLfs1c5SPmYHrEpTuuRhxMwvKDwqdKiGJS9XFKzUsAF",
+        ),
+        (
+            "7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f",
+            "legal winner thank year wave sausage worth useful legal winner thank yellow",
+            "2e8905819b8723fe2c1d161860e5ee1830318dbf49a83bd451cfb8440c28bd6fa457fe1296106559a3c80937a1c1069be3a3a5bd381ee6260e8d9739fce1f607",
+            "xprv9s21ZrQH143K2gA81bYFHqU68xz1cX2APaSq5tt6MFSLeXnCKV1RVUJt9FWNTbrrryem4ZckN8k4Ls1H6nwdvDTvnV7zEXs2HgPezuVccsq",
+        ),
+        (
+            "80808080808080808080808080808080",
+            "letter advice cage absurd amount doctor acoustic avoid letter advice cage above",
+            "d71de856f81a8acc65e6fc851a38d4d7ec216fd0796d0a6827a3ad6ed5511a30fa280f12eb2e47ed2ac03b5c462a0358d18d69fe4f985ec81778c1b370b652a8",
+            "xprv9s21ZrQH143K2shfP28KM3nr5Ap1SXjz8gc2rAqqMEynmjt6o1qboCDpxckqXavCwdnYds6yBHZGKHv7ef2eTXy461PXUjBFQg6PrwY4Gzq",
+        ),
+        (
+            "ffffffffffffffffffffffffffffffff",
+            "zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo wrong",
+            "ac27495480225222079d7be181583751e86f571027b0497b5b5d11218e0a8a13332572917f0f8e5a589620c6f15b11c61dee327651a14c34e18231052e48c069",
+            "xprv9s21ZrQH143K2V4oox4M8Zmhi2Fjx5XK4Lf7GKRvPSgydU3mjZuKGCTg7UPiBUD7ydVPvSLtg9hjp7MQTYsW67rZHAXeccqYqrsx8LcXnyd",
+        ),
+        (
+            "000000000000000000000000000000000000000000000000",
+            "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon agent",
+            "035895f2f481b1b0f01fcf8c289c794660b289981a78f8106447707fdd9666ca06da5a9a565181599b79f53b844d8a71dd9f439c52a3d7b3e8a79c906ac845fa",
+            "xprv9s21ZrQH143K3mEDrypcZ2usWqFgzKB6jBBx9B6GfC7fu26X6hPRzVjzkqkPvDqp6g5eypdk6cyhGnBngbjeHTe4LsuLG1cCmKJka5SMkmU",
+        ),
+        (
+            "8080808080808080808080808080808080808080808080808080808080808080",
+            "letter advice cage absurd amount doctor acoustic avoid letter advice cage absurd amount doctor acoustic avoid letter advice cage absurd amount doctor acoustic bless",
+            "c0c519bd0e91a2ed54357d9d1ebef6f5af218a153624cf4f2da911a0ed8f7a09e2ef61af0aca007096df430022f7a2b6fb91661a9589097069720d015e4e982f",
+            "xprv9s21ZrQH143K3CSnQNYC3MqAAqHwxeTLhDbhF43A4ss4ciWNmCY9zQGvAKUSqVUf2vPHBTSE1rB2pg4avopqSiLVzXEU8KziNnVPauTqLRo",
+        ),
+        (
+            "9e885d952ad362caeb4efe34a8e91bd2",
+            "ozone drill grab fiber curtain grace pudding thank cruise elder eight picnic",
+            "274ddc525802f7c828d8ef7ddbcdc5304e87ac3535913611fbbfa986d0c9e5476c91689f9c8a54fd55bd38606aa6a8595ad213d4c9c9f9aca3fb217069a41028",
+            "xprv9s21ZrQH143K2oZ9stBYpoaZ2ktHj7jLz7iMqpgg1En8kKFTXJHsjxry1JbKH19YrDTicVwKPehFKTbmaxgVEc5TpHdS1aYhB2s9aFJBeJH",
+        ),
+        (
+            "f585c11aec520db57dd353c69554b21a89b20fb0650966fa0a9d6f74fd989d8f",
+            "void come effort suffer camp survey warrior heavy shoot primary clutch crush open amazing screen patrol group space point ten exist slush involve unfold",
+            "01f5bced59dec48e362f2c45b5de68b9fd6c92c6634f44d6d40aab69056506f0e35524a518034ddc1192e1dacd32c1ed3eaa3c3b131c88ed8e7e54c49a5d0998",
+            "xprv9s21ZrQH143K39rnQJknpH1WEPFJrzmAqqasiDcVrNuk926oizzJDDQkdiTvNPr2FYDYzWgiMiC63YmfPAa2oPyNB23r2g7d1yiK6WpqaQS",
+        ),
+    ];
+
+    #[test]
+    fn test_trezor_vectors() {
+        for (entropy, phrase, seed, xprv) in TREZOR_VECTORS {
+            let entropy = hex::decode(entropy).unwrap();
+            let mnemonic = Mnemonic::from_entropy(&entropy).unwrap();
+            assert_eq!(mnemonic.to_string(), phrase);
+            assert_eq!(
+                Mnemonic::parse(phrase).unwrap().to_entropy().unwrap(),
+                entropy
+            );
+            assert_eq!(hex::encode(mnemonic.to_seed(Some("TREZOR"))), seed);
+            assert_eq!(
+                mnemonic
+                    .to_master_key(Some("TREZOR"), Network::Mainnet)
+                    .unwrap()
+                    .xprv(),
+                xprv
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalid_mnemonic() {
+        assert!(Mnemonic::parse(
+            "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon"
+        )
+        .is_err());
+        assert!(Mnemonic::parse("abandon abandon abandon").is_err());
+        assert!(Mnemonic::parse(
+            "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon bitcoins"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_generate() {
+        let mnemonic = Mnemonic::generate(24).unwrap();
+        assert_eq!(mnemonic.words.len(), 24);
+        assert!(Mnemonic::parse(&mnemonic.to_string()).is_ok());
+    }
+}
@@ -0,0 +1,407 @@
+use std::fmt::Display;
+
+use anyhow::{bail, Result};
+use num_bigint::BigInt;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    bip32::{self, ExtendedPrivateKey, ExtendedPublicKey},
+    derivation_path::DerivationPath,
+    network::Network,
+    op::OpCodeFunctions,
+    private_key::PrivateKey,
+    s256_point::S256Point,
+    script::{Command, Script},
+    tx::{Tx, TxIn, TxOut},
+    utils, PointWrapper, G, N,
+};
+
+const PAYMENT_CODE_PREFIX: u8 = 0x47;
+const PAYMENT_CODE_LENGTH: usize = 80;
+const NOTIFICATION_AMOUNT: u64 = 546;
+
+// A version 1 payment code: the public key and chain code of the m/47'/coin'/account' node.
+#[derive(Debug, Clone)]
+pub struct PaymentCode {
+    pub version: u8,
+    pub features: u8,
+    pub public_key: S256Point,
+    pub chain_code: Vec<u8>,
+}
+
+impl PaymentCode {
+    pub fn from_extended_public_key(key: &ExtendedPublicKey) -> Self {
+        PaymentCode {
+            version: 1,
+            features: 0,
+            public_key: key.point.clone(),
+            chain_code: key.chain_code.clone(),
+        }
+    }
+
+    pub fn account_key(master: &ExtendedPrivateKey, index: u32) -> Result<ExtendedPrivateKey> {
+        master.derive_path(&DerivationPath::new(vec![
+            bip32::hardened(47)?,
+            bip32::hardened(master.network.coin_type())?,
+            bip32::hardened(index)?,
+        ]))
+    }
+
+    pub fn from_master(master: &ExtendedPrivateKey, index: u32) -> Result<Self> {
+        let account = PaymentCode::account_key(master, index)?;
+        Ok(PaymentCode::from_extended_public_key(
+            &account.to_extended_public_key(),
+        ))
+    }
+
+    pub fn parse(s: &str) -> Result<Self> {
+        let (version, payload) = utils::decode_base58_check(s)?;
+        if version != PAYMENT_CODE_PREFIX {
+            bail!("not a payment code: {}", s)
+        }
+        PaymentCode::parse_payload(&payload)
+    }
+
+    pub fn parse_payload(payload: &[u8]) -> Result<Self> {
+        if payload.len() != PAYMENT_CODE_LENGTH {
+            bail!(
+                "payment code must be {} bytes, got {}",
+                PAYMENT_CODE_LENGTH,
+                payload.len()
+            )
+        }
+        if payload[0] != 1 {
+            bail!("unsupported payment code version: {}", payload[0])
+        }
+        if payload[2] != 2 && payload[2] != 3 {
+            bail!("invalid payment code public key")
+        }
+        Ok(PaymentCode {
+            version: payload[0],
+            features: payload[1],
+            public_key: S256Point::parse(&payload[2..35])?,
+            chain_code: payload[35..67].to_vec(),
+        })
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        [
+            vec![self.version, self.features],
+            self.public_key.clone().sec(Some(true)),
+            self.chain_code.clone(),
+            vec![0; 13],
+        ]
+        .concat()
+    }
+
+    pub fn to_extended_public_key(&self) -> ExtendedPublicKey {
+        ExtendedPublicKey {
+            point: self.public_key.clone(),
+            chain_code: self.chain_code.clone(),
+            depth: 0,
+            parent_fingerprint: vec![0; 4],
+            child_number: 0,
+            network: Network::Mainnet,
+        }
+    }
+
+    pub fn derive_public_key(&self, index: u32) -> Result<S256Point> {
+        Ok(self.to_extended_public_key().derive_child(index)?.point)
+    }
+
+    pub fn notification_address(&self, network: Network) -> Result<String> {
+        Ok(self
+            .derive_public_key(0)?
+            .address(Some(true), Some(network)))
+    }
+
+    // The x coordinate and chain code are xored with HMAC-SHA512(outpoint, x(S)).
+    pub fn blind(&self, outpoint: &[u8], secret_point: &S256Point) -> Vec<u8> {
+        let mask = utils::hmac_sha512(outpoint, &secret_point.xonly());
+        let mut payload = self.serialize();
+        for (byte, m) in payload[3..67].iter_mut().zip(mask) {
+            *byte ^= m;
+        }
+        payload
+    }
+
+    pub fn unblind(payload: &[u8], outpoint: &[u8], secret_point: &S256Point) -> Result<Self> {
+        if payload.len() != PAYMENT_CODE_LENGTH {
+            bail!("blinded payment code must be {} bytes", PAYMENT_CODE_LENGTH)
+        }
+        let mask = utils::hmac_sha512(outpoint, &secret_point.xonly());
+        let mut payload = payload.to_vec();
+        for (byte, m) in payload[3..67].iter_mut().zip(mask) {
+            *byte ^= m;
+        }
+        PaymentCode::parse_payload(&payload)
+    }
+
+    // Recovers the sender's payment code from a notification transaction sent to us,
+    // the designated input is the first one and must reveal its public key.
+    pub fn from_notification_tx(tx: &Tx, notification_key: &PrivateKey) -> Result<Self> {
+        let payload = match tx.tx_outs.iter().find_map(op_return_payload) {
+            Some(payload) => payload,
+            None => bail!("transaction has no payment code output"),
+        };
+        let designated = match tx.tx_ins.first() {
+            Some(tx_in) => tx_in,
+            None => bail!("transaction has no inputs"),
+        };
+        let public_key = match designated
+            .script_sig
+            .as_ref()
+            .and_then(|script| script.cmds().ok()?.pop())
+        {
+            Some(Command::Element(sec)) if sec.len() == 33 || sec.len() == 65 => {
+                S256Point::parse(&sec)?
+            }
+            _ => bail!("designated input does not expose a public key"),
+        };
+        let secret_point = ecdh(notification_key, &public_key)?;
+        PaymentCode::unblind(&payload, &outpoint(designated), &secret_point)
+    }
+}
+
+impl Display for PaymentCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            utils::encode_base58_checksum(&[vec![PAYMENT_CODE_PREFIX], self.serialize()].concat())
+        )
+    }
+}
+
+pub fn outpoint(tx_in: &TxIn) -> Vec<u8> {
+    let mut prev_tx = tx_in.prev_tx.clone();
+    prev_tx.reverse();
+    [prev_tx, utils::int_to_little_endian(&tx_in.prev_index, 4)].concat()
+}
+
+fn ecdh(private_key: &PrivateKey, point: &S256Point) -> Result<S256Point> {
+    let secret_point = &private_key.secret * point;
+    if let PointWrapper::Inf = secret_point {
+        bail!("shared secret is the point at infinity")
+    }
+    Ok(S256Point {
+        point: secret_point,
+    })
+}
+
+fn shared_secret(private_key: &PrivateKey, point: &S256Point) -> Result<BigInt> {
+    let x = ecdh(private_key, point)?.xonly();
+    let s = BigInt::from_bytes_be(num_bigint::Sign::Plus, &Sha256::digest(x));
+    if s >= N.to_owned() {
+        bail!("shared secret is not a valid scalar")
+    }
+    Ok(s)
+}
+
+fn op_return_payload(tx_out: &TxOut) -> Option<Vec<u8>> {
+    match tx_out.script_pubkey.cmds().ok()?.as_slice() {
+        [Command::Operation(OpCodeFunctions::OpReturn(_)), Command::Element(payload)]
+            if payload.len() == PAYMENT_CODE_LENGTH =>
+        {
+            Some(payload.clone())
+        }
+        _ => None,
+    }
+}
+
+pub fn notification_script(
+    sender: &PaymentCode,
+    recipient: &PaymentCode,
+    designated_key: &PrivateKey,
+    designated_input: &TxIn,
+) -> Result<Script> {
+    let secret_point = ecdh(designated_key, &recipient.derive_public_key(0)?)?;
+    Ok(Script::new(Some(vec![
+        Command::Operation(OpCodeFunctions::op_return()),
+        Command::Element(sender.blind(&outpoint(designated_input), &secret_point)),
+    ])))
+}
+
+// Builds the unsigned notification transaction: a dust output to the recipient's
+// notification address, the blinded payment code and any extra (change) outputs.
+// `designated_key` must be the key spending the first input.
+pub fn notification_tx(
+    sender: &PaymentCode,
+    recipient: &PaymentCode,
+    designated_key: &PrivateKey,
+    tx_ins: Vec<TxIn>,
+    change_outs: Vec<TxOut>,
+    network: Network,
+) -> Result<Tx> {
+    let designated_input = match tx_ins.first() {
+        Some(tx_in) => tx_in,
+        None => bail!("a notification transaction needs at least one input"),
+    };
+    let notification_h160 = recipient.derive_public_key(0)?.hash160(Some(true));
+    let mut tx_outs = vec![
+        TxOut::new(
+            BigInt::from(NOTIFICATION_AMOUNT),
+            utils::p2pkh_script(notification_h160),
+        ),
+        TxOut::new(
+            BigInt::from(0),
+            notification_script(sender, recipient, designated_key, designated_input)?,
+        ),
+    ];
+    tx_outs.extend(change_outs);
+    Ok(Tx::new(
+        BigInt::from(1),
+        tx_ins,
+        tx_outs,
+        BigInt::from(0),
+        network,
+    ))
+}
+
+// The public key the sender pays to for the recipient's `index`-th payment.
+pub fn send_public_key(
+    sender_account: &ExtendedPrivateKey,
+    recipient: &PaymentCode,
+    index: u32,
+) -> Result<S256Point> {
+    let a = sender_account.derive_child(0)?.private_key;
+    let b = recipient.derive_public_key(index)?;
+    let s = shared_secret(&a, &b)?;
+    Ok(S256Point {
+        point: s * &G.to_owned() + b.point,
+    })
+}
+
+pub fn send_address(
+    sender_account: &ExtendedPrivateKey,
+    recipient: &PaymentCode,
+    index: u32,
+) -> Result<String> {
+    Ok(send_public_key(sender_account, recipient, index)?
+        .address(Some(true), Some(sender_account.network)))
+}
+
+// The private key the recipient uses to spend the sender's `index`-th payment.
+pub fn receive_private_key(
+    recipient_account: &ExtendedPrivateKey,
+    sender: &PaymentCode,
+    index: u32,
+) -> Result<PrivateKey> {
+    let b = recipient_account.derive_child(index)?.private_key;
+    let a = sender.derive_public_key(0)?;
+    let s = shared_secret(&b, &a)?;
+    let secret = (b.secret + s) % N.to_owned();
+    Ok(PrivateKey::new(&PrivateKey::generate_simple_secret(secret)))
+}
+
+#[cfg(test)]
+mod bip47_tests {
+    use num_bigint::BigInt;
+
+    use crate::{bip39::Mnemonic, network::Network, private_key::PrivateKey, tx::TxIn};
+
+    use super::{notification_tx, outpoint, receive_private_key, send_address, PaymentCode};
+
+    // https://gist.github.com/SamouraiDev/6aad669604c5930864bd
+    const ALICE: &str =
+        "response seminar brave tip suit recall often sound stick owner lottery motion";
+    const BOB: &str =
+        "reward upper indicate eight swift arch injury crystal super wrestle already dentist";
+    const ALICE_CODE: &str = "PM8TJTLJbPRGxSbc8EJi42Wrr6QbNSaSSVJ5Y3E4pbCYiTHUskHg13935Ubb7q8tx9GVbh2UuRnBc3WSyJHhUrw8KhprKnn9eDznYGieTzFcwQRya4GA";
+    const BOB_CODE: &str = "PM8TJS2JxQ5ztXUpBBRnpTbcUXbUHy2T1abfrb3KkAAtMEGNbey4oumH7Hc578WgQJhPjBxteQ5GHHToTYHE3A1w6p7tU6KSoFmWBVbFGjKPisZDbP97";
+
+    fn master(mnemonic: &str) -> crate::bip32::ExtendedPrivateKey {
+        Mnemonic::parse(mnemonic)
+            .unwrap()
+            .to_master_key(None, Network::Mainnet)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_payment_codes() {
+        let alice = PaymentCode::from_master(&master(ALICE), 0).unwrap();
+        assert_eq!(alice.to_string(), ALICE_CODE);
+        assert_eq!(
+            alice.notification_address(Network::Mainnet).unwrap(),
+            "1JDdmqFLhpzcUwPeinhJbUPw4Co3aWLyzW"
+        );
+        let bob = PaymentCode::parse(BOB_CODE).unwrap();
+        assert_eq!(bob.to_string(), BOB_CODE);
+        assert_eq!(
+            bob.notification_address(Network::Mainnet).unwrap(),
+            "1ChvUUvht2hUQufHBXF8NgLhW8SwE2ecGV"
+        );
+        assert!(PaymentCode::parse("1ChvUUvht2hUQufHBXF8NgLhW8SwE2ecGV").is_err());
+        assert!(PaymentCode::from_master(&master(ALICE), 0x8000_0000).is_err());
+    }
+
+    #[test]
+    fn test_payment_addresses() {
+        let alice_account = PaymentCode::account_key(&master(ALICE), 0).unwrap();
+        let bob_account = PaymentCode::account_key(&master(BOB), 0).unwrap();
+        let alice = PaymentCode::parse(ALICE_CODE).unwrap();
+        let bob = PaymentCode::parse(BOB_CODE).unwrap();
+        let expected = [
+            "141fi7TY3h936vRUKh1qfUZr8rSBuYbVBK",
+            "12u3Uued2fuko2nY4SoSFGCoGLCBUGPkk6",
+            "1FsBVhT5dQutGwaPePTYMe5qvYqqjxyftc",
+        ];
+        for (index, address) in expected.iter().enumerate() {
+            assert_eq!(
+                send_address(&alice_account, &bob, index as u32).unwrap(),
+                *address
+            );
+            let key = receive_private_key(&bob_account, &alice, index as u32).unwrap();
+            assert_eq!(
+                key.point.address(Some(true), Some(Network::Mainnet)),
+                *address
+            );
+        }
+    }
+
+    #[test]
+    fn test_notification_transaction() {
+        let alice = PaymentCode::parse(ALICE_CODE).unwrap();
+        let bob = PaymentCode::parse(BOB_CODE).unwrap();
+        let bob_account = PaymentCode::account_key(&master(BOB), 0).unwrap();
+        let designated_key = PrivateKey::new(&PrivateKey::generate_simple_secret(
+            BigInt::parse_bytes(
+                b"1b7a10f45118e2519a8dd46ef81591c1ae501d082b6610fdda3de7a3c932880d",
+                16,
+            )
+            .unwrap(),
+        ));
+        let mut prev_tx =
+            hex::decode("86f411ab1c8e70ae8a0795ab7a6757aea6e4d5ae1826fc7b8f00c597d500609c")
+                .unwrap();
+        prev_tx.reverse();
+        let tx_in = TxIn::new(prev_tx, BigInt::from(1), None, None);
+        assert_eq!(
+            hex::encode(outpoint(&tx_in)),
+            "86f411ab1c8e70ae8a0795ab7a6757aea6e4d5ae1826fc7b8f00c597d500609c01000000"
+        );
+
+        let mut tx = notification_tx(
+            &alice,
+            &bob,
+            &designated_key,
+            vec![tx_in],
+            vec![],
+            Network::Mainnet,
+        )
+        .unwrap();
+        assert_eq!(
+            hex::encode(tx.tx_outs[1].script_pubkey.serialize()),
+            "536a4c50010002063e4eb95e62791b06c50e1a3a942e1ecaaa9afbbeb324d16ae6821e091611fa96c0cf048f607fe51a0327f5e2528979311c78cb2de0d682c61e1180fc3d543b00000000000000000000000000"
+        );
+
+        // Bob only sees the revealed public key of the designated input.
+        tx.tx_ins[0].script_sig = Some(crate::script::Script::new(Some(vec![
+            crate::script::Command::Element(vec![0x30]),
+            crate::script::Command::Element(designated_key.point.clone().sec(Some(true))),
+        ])));
+        let notification_key = bob_account.derive_child(0).unwrap().private_key;
+        let sender = PaymentCode::from_notification_tx(&tx, &notification_key).unwrap();
+        assert_eq!(sender.to_string(), ALICE_CODE);
+    }
+}
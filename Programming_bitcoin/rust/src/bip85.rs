@@ -0,0 +1,227 @@
+use anyhow::{bail, Result};
+use num_bigint::BigInt;
+
+use crate::{
+    bip32::{self, ExtendedPrivateKey},
+    bip39::Mnemonic,
+    derivation_path::DerivationPath,
+    private_key::PrivateKey,
+    utils, N,
+};
+
+const BIP85_PURPOSE: u32 = 83696968;
+const APP_BIP39: u32 = 39;
+const APP_WIF: u32 = 2;
+const APP_XPRV: u32 = 32;
+const APP_HEX: u32 = 128169;
+const APP_PWD_BASE64: u32 = 707764;
+const APP_PWD_BASE85: u32 = 707785;
+
+const BASE64_ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+// RFC 1924 alphabet, the one python's base64.b85encode uses
+const BASE85_ALPHABET: &[u8] =
+    b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz!#$%&()*+-;<=>?@^_`{|}~";
+
+// Every BIP85 index is hardened, so each must be below 2^31.
+fn hardened_path(indexes: &[u32]) -> Result<DerivationPath> {
+    Ok(DerivationPath::new(
+        [BIP85_PURPOSE]
+            .iter()
+            .chain(indexes)
+            .map(|index| bip32::hardened(*index))
+            .collect::<Result<_>>()?,
+    ))
+}
+
+pub fn derive_entropy(root: &ExtendedPrivateKey, path: &DerivationPath) -> Result<Vec<u8>> {
+    let child = root.derive_path(path)?;
+    Ok(utils::hmac_sha512(
+        b"bip-entropy-from-k",
+        &utils::int_to_big_endian(&child.private_key.secret, 32),
+    ))
+}
+
+// Only the English wordlist (language 0) is available.
+pub fn mnemonic(
+    root: &ExtendedPrivateKey,
+    language: u32,
+    words: usize,
+    index: u32,
+) -> Result<Mnemonic> {
+    if language != 0 {
+        bail!("unsupported BIP39 language code: {}", language)
+    }
+    if ![12, 18, 24].contains(&words) {
+        bail!(
+            "BIP85 mnemonics must have 12, 18 or 24 words, got {}",
+            words
+        )
+    }
+    let path = hardened_path(&[APP_BIP39, language, words as u32, index])?;
+    let entropy = derive_entropy(root, &path)?;
+    Mnemonic::from_entropy(&entropy[..words * 4 / 3])
+}
+
+pub fn wif(root: &ExtendedPrivateKey, index: u32) -> Result<String> {
+    let entropy = derive_entropy(root, &hardened_path(&[APP_WIF, index])?)?;
+    let secret = BigInt::from_bytes_be(num_bigint::Sign::Plus, &entropy[..32]);
+    if secret == BigInt::from(0) || secret >= N.to_owned() {
+        bail!("derived entropy is not a valid private key")
+    }
+    let private_key = PrivateKey::new(&PrivateKey::generate_simple_secret(secret));
+    Ok(private_key.wif(Some(true), Some(root.network)))
+}
+
+pub fn xprv(root: &ExtendedPrivateKey, index: u32) -> Result<ExtendedPrivateKey> {
+    let entropy = derive_entropy(root, &hardened_path(&[APP_XPRV, index])?)?;
+    let secret = BigInt::from_bytes_be(num_bigint::Sign::Plus, &entropy[32..64]);
+    if secret == BigInt::from(0) || secret >= N.to_owned() {
+        bail!("derived entropy is not a valid private key")
+    }
+    Ok(ExtendedPrivateKey {
+        private_key: PrivateKey::new(&PrivateKey::generate_simple_secret(secret)),
+        chain_code: entropy[0..32].to_vec(),
+        depth: 0,
+        parent_fingerprint: vec![0; 4],
+        child_number: 0,
+        network: root.network,
+    })
+}
+
+pub fn hex(root: &ExtendedPrivateKey, num_bytes: usize, index: u32) -> Result<String> {
+    if !(16..=64).contains(&num_bytes) {
+        bail!(
+            "hex entropy must be between 16 and 64 bytes, got {}",
+            num_bytes
+        )
+    }
+    let entropy = derive_entropy(root, &hardened_path(&[APP_HEX, num_bytes as u32, index])?)?;
+    Ok(hex::encode(&entropy[..num_bytes]))
+}
+
+pub fn pwd_base64(root: &ExtendedPrivateKey, pwd_len: usize, index: u32) -> Result<String> {
+    if !(20..=86).contains(&pwd_len) {
+        bail!(
+            "base64 passwords must be between 20 and 86 characters, got {}",
+            pwd_len
+        )
+    }
+    let path = hardened_path(&[APP_PWD_BASE64, pwd_len as u32, index])?;
+    let entropy = derive_entropy(root, &path)?;
+    Ok(encode_base64(&entropy)[..pwd_len].to_string())
+}
+
+pub fn pwd_base85(root: &ExtendedPrivateKey, pwd_len: usize, index: u32) -> Result<String> {
+    if !(10..=80).contains(&pwd_len) {
+        bail!(
+            "base85 passwords must be between 10 and 80 characters, got {}",
+            pwd_len
+        )
+    }
+    let path = hardened_path(&[APP_PWD_BASE85, pwd_len as u32, index])?;
+    let entropy = derive_entropy(root, &path)?;
+    Ok(encode_base85(&entropy)[..pwd_len].to_string())
+}
+
+fn encode_base64(data: &[u8]) -> String {
+    let mut result = String::new();
+    for chunk in data.chunks(3) {
+        let mut buffer = [0_u8; 3];
+        buffer[..chunk.len()].copy_from_slice(chunk);
+        let n = u32::from_be_bytes([0, buffer[0], buffer[1], buffer[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                result.push(BASE64_ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                result.push('=');
+            }
+        }
+    }
+    result
+}
+
+// The entropy is always 64 bytes, so every chunk is a full 4 byte word.
+fn encode_base85(data: &[u8]) -> String {
+    let mut result = String::new();
+    for chunk in data.chunks(4) {
+        let mut n = u32::from_be_bytes(chunk.try_into().unwrap());
+        let mut encoded = [0_u8; 5];
+        for c in encoded.iter_mut().rev() {
+            *c = BASE85_ALPHABET[(n % 85) as usize];
+            n /= 85;
+        }
+        result.push_str(std::str::from_utf8(&encoded).unwrap());
+    }
+    result
+}
+
+#[cfg(test)]
+mod bip85_tests {
+    use crate::bip32::ExtendedPrivateKey;
+
+    use super::{derive_entropy, hardened_path};
+
+    const ROOT: &str = "xprv9s21ZrQH143K2LBWUUQRFXhucrQqBpKdRRxNVq2zBqsx8HVqFk2uYo8kmbaLLHRdqtQpUm98uKfu3vca1LqdGhUtyoFnCNkfmXRyPXLjbKb";
+
+    #[test]
+    fn test_derive_entropy() {
+        let root = ExtendedPrivateKey::parse(ROOT).unwrap();
+        assert_eq!(
+            hex::encode(derive_entropy(&root, &hardened_path(&[0, 0]).unwrap()).unwrap()),
+            "efecfbccffea313214232d29e71563d941229afb4338c21f9517c41aaa0d16f00b83d2a09ef747e7a64e8e2bd5a14869e693da66ce94ac2da570ab7ee48618f7"
+        );
+        assert_eq!(
+            hex::encode(derive_entropy(&root, &hardened_path(&[0, 1]).unwrap()).unwrap()),
+            "70c6e3e8ebee8dc4c0dbba66076819bb8c09672527c4277ca8729532ad711872218f826919f6b67218adde99018a6df9095ab2b58d803b5b93ec9802085a690e"
+        );
+    }
+
+    #[test]
+    fn test_bip39_application() {
+        let root = ExtendedPrivateKey::parse(ROOT).unwrap();
+        assert_eq!(
+            super::mnemonic(&root, 0, 12, 0).unwrap().to_string(),
+            "girl mad pet galaxy egg matter matrix prison refuse sense ordinary nose"
+        );
+        assert_eq!(
+            super::mnemonic(&root, 0, 18, 0).unwrap().to_string(),
+            "near account window bike charge season chef number sketch tomorrow excuse sniff circle vital hockey outdoor supply token"
+        );
+        assert_eq!(
+            super::mnemonic(&root, 0, 24, 0).unwrap().to_string(),
+            "puppy ocean match cereal symbol another shed magic wrap hammer bulb intact gadget divorce twin tonight reason outdoor destroy simple truth cigar social volcano"
+        );
+        assert!(super::mnemonic(&root, 1, 12, 0).is_err());
+    }
+
+    #[test]
+    fn test_wif_and_xprv_applications() {
+        let root = ExtendedPrivateKey::parse(ROOT).unwrap();
+        assert_eq!(
+            super::wif(&root, 0).unwrap(),
+            "Kzyv4uF39d4Jrw2W7UryTHwZr1zQVNk4dAFyqE6BuMrMh1Za7uhp"
+        );
+        assert_eq!(
+            super::xprv(&root, 0).unwrap().xprv(),
+            "xprv9s21ZrQH143K2srSbCSg4m4kLvPMzcWydgmKEnMmoZUurYuBuYG46c6P71UGXMzmriLzCCBvKQWBUv3vPB3m1SATMhp3uEjXHJ42jFg7myX"
+        );
+    }
+
+    #[test]
+    fn test_hex_and_pwd_applications() {
+        let root = ExtendedPrivateKey::parse(ROOT).unwrap();
+        assert_eq!(
+            super::hex(&root, 64, 0).unwrap(),
+            "492db4698cf3b73a5a24998aa3e9d7fa96275d85724a91e71aa2d645442f878555d078fd1f1f67e368976f04137b1f7a0d19232136ca50c44614af72b5582a5c"
+        );
+        assert_eq!(
+            super::pwd_base64(&root, 21, 0).unwrap(),
+            "dKLoepugzdVJvdL56ogNV"
+        );
+        assert_eq!(super::pwd_base85(&root, 12, 0).unwrap(), "_s`{TW89)i4`");
+        assert!(super::hex(&root, 15, 0).is_err());
+        // indexes are hardened, so they must stay below 2^31
+        assert!(super::hex(&root, 32, 0x8000_0000).is_err());
+        assert!(super::wif(&root, u32::MAX).is_err());
+    }
+}
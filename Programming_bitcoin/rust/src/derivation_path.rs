@@ -0,0 +1,176 @@
+use std::{fmt::Display, str::FromStr};
+
+use anyhow::{bail, Error, Result};
+
+use crate::bip32::HARDENED_OFFSET;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DerivationPath {
+    pub path: Vec<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyOrigin {
+    pub fingerprint: Vec<u8>,
+    pub path: DerivationPath,
+}
+
+impl DerivationPath {
+    pub fn new(path: Vec<u32>) -> Self {
+        DerivationPath { path }
+    }
+
+    pub fn child(&self, index: u32) -> Self {
+        let mut path = self.path.clone();
+        path.push(index);
+        DerivationPath { path }
+    }
+
+    fn parse_index(component: &str) -> Result<u32> {
+        let (number, hardened) = match component.strip_suffix(['\'', 'h', 'H']) {
+            Some(number) => (number, true),
+            None => (component, false),
+        };
+        let index: u32 = match number.parse() {
+            Ok(index) => index,
+            Err(_) => bail!("invalid derivation path component: {}", component),
+        };
+        if index >= HARDENED_OFFSET {
+            bail!("derivation index out of range: {}", component)
+        }
+        if hardened {
+            Ok(index + HARDENED_OFFSET)
+        } else {
+            Ok(index)
+        }
+    }
+
+    fn format_path(&self) -> String {
+        self.path
+            .iter()
+            .map(|index| {
+                if *index >= HARDENED_OFFSET {
+                    format!("{}'", index - HARDENED_OFFSET)
+                } else {
+                    format!("{}", index)
+                }
+            })
+            .collect::<Vec<String>>()
+            .join("/")
+    }
+}
+
+// Accepts both "m/84'/0'/0'" and the relative "84h/0h/0h" form used inside key origins.
+impl FromStr for DerivationPath {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let relative = match s.strip_prefix('m') {
+            Some(rest) => match rest.strip_prefix('/') {
+                Some(rest) => rest,
+                None if rest.is_empty() => rest,
+                None => bail!("invalid derivation path: {}", s),
+            },
+            None => s,
+        };
+        if relative.is_empty() {
+            return Ok(DerivationPath::new(Vec::new()));
+        }
+        let path = relative
+            .split('/')
+            .map(DerivationPath::parse_index)
+            .collect::<Result<Vec<u32>>>()?;
+        Ok(DerivationPath { path })
+    }
+}
+
+impl Display for DerivationPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "m")
+        } else {
+            write!(f, "m/{}", self.format_path())
+        }
+    }
+}
+
+impl KeyOrigin {
+    pub fn new(fingerprint: Vec<u8>, path: DerivationPath) -> Self {
+        KeyOrigin { fingerprint, path }
+    }
+}
+
+impl FromStr for KeyOrigin {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let inner = match s.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+            Some(inner) => inner,
+            None => bail!("key origin must be enclosed in brackets: {}", s),
+        };
+        let (fingerprint, path) = match inner.split_once('/') {
+            Some((fingerprint, path)) => (fingerprint, path),
+            None => (inner, ""),
+        };
+        let fingerprint = match hex::decode(fingerprint) {
+            Ok(fingerprint) if fingerprint.len() == 4 => fingerprint,
+            _ => bail!("invalid key origin fingerprint: {}", fingerprint),
+        };
+        Ok(KeyOrigin {
+            fingerprint,
+            path: path.parse()?,
+        })
+    }
+}
+
+impl Display for KeyOrigin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.path.path.is_empty() {
+            write!(f, "[{}]", hex::encode(&self.fingerprint))
+        } else {
+            write!(
+                f,
+                "[{}/{}]",
+                hex::encode(&self.fingerprint),
+                self.path.format_path()
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod derivation_path_tests {
+    use crate::bip32::HARDENED_OFFSET;
+
+    use super::{DerivationPath, KeyOrigin};
+
+    #[test]
+    fn test_parse_derivation_path() {
+        let path: DerivationPath = "m/84'/0'/0'/0/5".parse().unwrap();
+        assert_eq!(
+            path.path,
+            vec![84 + HARDENED_OFFSET, HARDENED_OFFSET, HARDENED_OFFSET, 0, 5]
+        );
+        assert_eq!(path, "m/84h/0h/0H/0/5".parse().unwrap());
+        assert_eq!(path.to_string(), "m/84'/0'/0'/0/5");
+        assert_eq!("m".parse::<DerivationPath>().unwrap().path, Vec::<u32>::new());
+        assert!("m/2147483648".parse::<DerivationPath>().is_err());
+        assert!("m/0x/1".parse::<DerivationPath>().is_err());
+        assert!("m//1".parse::<DerivationPath>().is_err());
+        assert!("n/1".parse::<DerivationPath>().is_err());
+    }
+
+    #[test]
+    fn test_parse_key_origin() {
+        let origin: KeyOrigin = "[d34db33f/49h/0'/0']".parse().unwrap();
+        assert_eq!(hex::encode(&origin.fingerprint), "d34db33f");
+        assert_eq!(origin.path.to_string(), "m/49'/0'/0'");
+        assert_eq!(origin.to_string(), "[d34db33f/49'/0'/0']");
+        assert_eq!(
+            "[d34db33f]".parse::<KeyOrigin>().unwrap().to_string(),
+            "[d34db33f]"
+        );
+        assert!("d34db33f/49'".parse::<KeyOrigin>().is_err());
+        assert!("[d34db3/49']".parse::<KeyOrigin>().is_err());
+    }
+}
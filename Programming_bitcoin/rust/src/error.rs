@@ -0,0 +1,61 @@
+use std::fmt::Display;
+
+// Errors raised while decoding transactions, scripts, keys and signatures.
+#[derive(Debug)]
+pub enum Error {
+    Io(std::io::Error),
+    Truncated,
+    NonCanonicalVarint(u64),
+    UnknownOpcode(u32),
+    ScriptLength { expected: u64, actual: u64 },
+    BadDer(&'static str),
+    BadPoint(&'static str),
+    BadScriptNum(&'static str),
+    BadWitness(&'static str),
+    BadAsm(String),
+    Fetch(String),
+    Unsupported(&'static str),
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        match err.kind() {
+            std::io::ErrorKind::UnexpectedEof => Error::Truncated,
+            _ => Error::Io(err),
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "i/o error: {}", err),
+            Error::Truncated => write!(f, "unexpected end of data"),
+            Error::NonCanonicalVarint(value) => {
+                write!(f, "non-canonical varint encoding of {}", value)
+            }
+            Error::UnknownOpcode(op_code) => write!(f, "unknown opcode: {:#04x}", op_code),
+            Error::ScriptLength { expected, actual } => write!(
+                f,
+                "script length mismatch: expected {} bytes, parsed {}",
+                expected, actual
+            ),
+            Error::BadDer(reason) => write!(f, "bad DER signature: {}", reason),
+            Error::BadPoint(reason) => write!(f, "bad SEC point: {}", reason),
+            Error::BadScriptNum(reason) => write!(f, "bad script number: {}", reason),
+            Error::BadWitness(reason) => write!(f, "bad witness data: {}", reason),
+            Error::BadAsm(token) => write!(f, "bad script asm token: {}", token),
+            Error::Fetch(reason) => write!(f, "failed to fetch transaction: {}", reason),
+            Error::Unsupported(what) => write!(f, "not supported yet: {}", what),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
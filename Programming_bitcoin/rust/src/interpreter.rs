@@ -0,0 +1,418 @@
+use crate::{
+    op::{self, OpCodeFunctions},
+    script::{
+        Command, ConditionStack, Instruction, Script, VerifyFlags, MAX_OPS_PER_SCRIPT,
+        MAX_SCRIPT_ELEMENT_SIZE, MAX_SCRIPT_SIZE, MAX_STACK_SIZE,
+    },
+    script_error::ScriptError,
+    signature_checker::SignatureChecker,
+};
+
+// Everything the interpreter carries from one command to the next.
+#[derive(Debug, Clone, Default)]
+pub struct ExecState {
+    // index into the decoded instructions of the next command to run
+    pub pc: usize,
+    pub stack: Vec<Vec<u8>>,
+    pub altstack: Vec<Vec<u8>>,
+    pub condition_stack: ConditionStack,
+    pub op_count: usize,
+    // byte offset just past the last executed OP_CODESEPARATOR, where the
+    // scriptCode signatures commit to starts
+    pub code_separator: usize,
+}
+
+// One executed command and the state it left behind.
+#[derive(Debug, Clone)]
+pub struct TraceStep {
+    pub pc: usize,
+    pub command: Command,
+    pub state: ExecState,
+}
+
+// Runs a script one command at a time, like Bitcoin Core's EvalScript with
+// the loop unrolled. Script::execute drives it to the end; a debugger can
+// inspect the state between steps and, with the trace enabled, go back.
+pub struct Interpreter<'a> {
+    script: &'a Script,
+    instructions: Vec<Instruction>,
+    // a push runs past the end after the last instruction, which fails
+    // like Core's GetOp once it is reached
+    truncated: bool,
+    checker: &'a dyn SignatureChecker,
+    flags: VerifyFlags,
+    initial: ExecState,
+    state: ExecState,
+    trace: Option<Vec<TraceStep>>,
+    error: Option<ScriptError>,
+}
+
+impl<'a> Interpreter<'a> {
+    pub fn new(
+        script: &'a Script,
+        stack: Vec<Vec<u8>>,
+        checker: &'a dyn SignatureChecker,
+        flags: VerifyFlags,
+    ) -> Self {
+        let state = ExecState {
+            stack,
+            ..ExecState::default()
+        };
+        let mut instructions = Vec::new();
+        let mut truncated = false;
+        for instruction in script.instructions() {
+            match instruction {
+                Ok(instruction) => instructions.push(instruction),
+                Err(_) => truncated = true,
+            }
+        }
+        let error = if script.serialized_len() > MAX_SCRIPT_SIZE {
+            Some(ScriptError::ScriptSize)
+        } else {
+            None
+        };
+        Interpreter {
+            script,
+            instructions,
+            truncated,
+            checker,
+            flags,
+            initial: state.clone(),
+            state,
+            trace: None,
+            error,
+        }
+    }
+
+    // Records a snapshot after every step, needed by step_back.
+    pub fn enable_trace(&mut self) {
+        if self.trace.is_none() {
+            self.trace = Some(Vec::new());
+        }
+    }
+
+    pub fn script(&self) -> &Script {
+        self.script
+    }
+
+    pub fn instructions(&self) -> &[Instruction] {
+        &self.instructions
+    }
+
+    pub fn pc(&self) -> usize {
+        self.state.pc
+    }
+
+    // The command the next step will run.
+    pub fn current(&self) -> Option<&Command> {
+        self.instructions
+            .get(self.state.pc)
+            .map(|instruction| &instruction.command)
+    }
+
+    pub fn state(&self) -> &ExecState {
+        &self.state
+    }
+
+    pub fn stack(&self) -> &[Vec<u8>] {
+        &self.state.stack
+    }
+
+    pub fn altstack(&self) -> &[Vec<u8>] {
+        &self.state.altstack
+    }
+
+    pub fn condition_stack(&self) -> &ConditionStack {
+        &self.state.condition_stack
+    }
+
+    pub fn trace(&self) -> &[TraceStep] {
+        self.trace.as_deref().unwrap_or(&[])
+    }
+
+    pub fn error(&self) -> Option<ScriptError> {
+        self.error
+    }
+
+    // True once the script failed or every command ran.
+    pub fn is_done(&self) -> bool {
+        self.error.is_some() || (self.state.pc >= self.instructions.len() && !self.truncated)
+    }
+
+    pub fn into_stack(self) -> Vec<Vec<u8>> {
+        self.state.stack
+    }
+
+    // Runs the next command. Returns Ok(false) once the end of the script is
+    // reached, which is also when unbalanced conditionals are reported.
+    pub fn step(&mut self) -> Result<bool, ScriptError> {
+        if let Some(err) = self.error {
+            return Err(err);
+        }
+        let instruction = match self.instructions.get(self.state.pc) {
+            Some(instruction) => instruction.clone(),
+            None if self.truncated => {
+                self.error = Some(ScriptError::BadOpcode);
+                return Err(ScriptError::BadOpcode);
+            }
+            None => {
+                if !self.state.condition_stack.empty() {
+                    self.error = Some(ScriptError::UnbalancedConditional);
+                    return Err(ScriptError::UnbalancedConditional);
+                }
+                return Ok(false);
+            }
+        };
+        if let Err(err) = self.execute_instruction(&instruction) {
+            self.error = Some(err);
+            return Err(err);
+        }
+        let pc = self.state.pc;
+        self.state.pc += 1;
+        if let Some(trace) = &mut self.trace {
+            trace.push(TraceStep {
+                pc,
+                command: instruction.command,
+                state: self.state.clone(),
+            });
+        }
+        Ok(true)
+    }
+
+    // Undoes the last step, or clears the error the last step ran into.
+    // Returns false when there is nothing to undo or the trace is disabled.
+    pub fn step_back(&mut self) -> bool {
+        let trace = match &mut self.trace {
+            Some(trace) => trace,
+            None => return false,
+        };
+        // an oversized script fails before anything runs, there is no step
+        // to undo
+        if self.script.serialized_len() > MAX_SCRIPT_SIZE {
+            return false;
+        }
+        // a failed step leaves the state half updated and the pc in place
+        if self.error.take().is_none() && trace.pop().is_none() {
+            return false;
+        }
+        self.state = self.last_state();
+        true
+    }
+
+    pub fn run(&mut self) -> Result<(), ScriptError> {
+        while self.step()? {}
+        Ok(())
+    }
+
+    fn last_state(&self) -> ExecState {
+        match self.trace.as_ref().and_then(|trace| trace.last()) {
+            Some(step) => step.state.clone(),
+            None => self.initial.clone(),
+        }
+    }
+
+    fn execute_instruction(&mut self, instruction: &Instruction) -> Result<(), ScriptError> {
+        let state = &mut self.state;
+        let executing = state.condition_stack.all_true();
+        match instruction.command.clone() {
+            Command::Element(elem) => {
+                if elem.len() > MAX_SCRIPT_ELEMENT_SIZE {
+                    return Err(ScriptError::PushSize);
+                }
+                if executing {
+                    if self.flags.contains(VerifyFlags::MINIMALDATA)
+                        && !is_minimal_push(instruction.op_code, &elem)
+                    {
+                        return Err(ScriptError::MinimalData);
+                    }
+                    state.stack.push(elem)
+                }
+            }
+            Command::Operation(op_code) => {
+                // pushes up to OP_16 don't count towards the limit
+                if *op_code.as_ref() > 0x60 {
+                    state.op_count += 1;
+                    if state.op_count > MAX_OPS_PER_SCRIPT {
+                        return Err(ScriptError::OpCount);
+                    }
+                }
+                if op_code.is_disabled() {
+                    return Err(ScriptError::DisabledOpcode);
+                }
+                match op_code {
+                    OpCodeFunctions::OpIf(_) | OpCodeFunctions::OpNotif(_) => {
+                        let mut value = false;
+                        if executing {
+                            let element = state
+                                .stack
+                                .pop()
+                                .ok_or(ScriptError::UnbalancedConditional)?;
+                            value = op::cast_to_bool(&element);
+                            if let OpCodeFunctions::OpNotif(_) = op_code {
+                                value = !value;
+                            }
+                        }
+                        state.condition_stack.push_back(value);
+                    }
+                    OpCodeFunctions::OpElse(_) => {
+                        if state.condition_stack.empty() {
+                            return Err(ScriptError::UnbalancedConditional);
+                        }
+                        state.condition_stack.toggle_top();
+                    }
+                    OpCodeFunctions::OpEndif(_) => {
+                        if state.condition_stack.empty() {
+                            return Err(ScriptError::UnbalancedConditional);
+                        }
+                        state.condition_stack.pop_back();
+                    }
+                    // these fail even inside an unexecuted branch
+                    OpCodeFunctions::OpVerif(_) | OpCodeFunctions::OpVernotif(_) => {
+                        return Err(ScriptError::BadOpcode)
+                    }
+                    OpCodeFunctions::OpCodeseparator(_) => {
+                        if self.flags.contains(VerifyFlags::CONST_SCRIPTCODE) {
+                            return Err(ScriptError::OpCodeseparator);
+                        }
+                        if executing {
+                            state.code_separator = instruction.end;
+                        }
+                    }
+                    _ => {
+                        if executing {
+                            op::operation(
+                                op_code,
+                                &mut state.stack,
+                                &mut state.altstack,
+                                self.checker,
+                                &self.script.as_bytes()[state.code_separator..],
+                                self.flags,
+                                &mut state.op_count,
+                            )?;
+                        }
+                    }
+                }
+            }
+        }
+        if state.stack.len() + state.altstack.len() > MAX_STACK_SIZE {
+            return Err(ScriptError::StackSize);
+        }
+        Ok(())
+    }
+}
+
+// Core's CheckMinimalPush: the data must use the smallest push opcode that
+// can hold it, single bytes 1..16 and 0x81 have their own opcodes.
+fn is_minimal_push(op_code: u8, element: &[u8]) -> bool {
+    match element {
+        [] => op_code == 0x00,
+        [byte] if (1..=16).contains(byte) || *byte == 0x81 => false,
+        _ => match element.len() {
+            0..=0x4b => op_code as usize == element.len(),
+            0x4c..=0xff => op_code == 0x4c,
+            0x100..=0xffff => op_code == 0x4d,
+            _ => true,
+        },
+    }
+}
+
+#[cfg(test)]
+mod interpreter_tests {
+    use std::io::Cursor;
+
+    use crate::{
+        script::{Command, Script, VerifyFlags},
+        script_error::ScriptError,
+        signature::Signature,
+        signature_checker::SignatureHashChecker,
+        utils,
+    };
+
+    use super::Interpreter;
+
+    fn parse_hex(raw: &str) -> Script {
+        let raw = hex::decode(raw).unwrap();
+        let mut cursor = Cursor::new([utils::encode_varint(raw.len()), raw].concat());
+        Script::parse(&mut cursor).unwrap()
+    }
+
+    #[test]
+    fn test_step_and_step_back() {
+        // 1 2 ADD 0 IF RETURN ENDIF 3 EQUAL
+        let script = parse_hex("51529300636a685387");
+        let checker = SignatureHashChecker::new(Signature::signature_hash_from_hex("00"));
+        let mut interpreter = Interpreter::new(&script, vec![], &checker, VerifyFlags::NONE);
+        interpreter.enable_trace();
+        assert_eq!(interpreter.pc(), 0);
+        assert_eq!(interpreter.step(), Ok(true));
+        assert_eq!(interpreter.step(), Ok(true));
+        assert_eq!(interpreter.stack(), &[vec![1], vec![2]]);
+        assert!(matches!(interpreter.current(), Some(Command::Operation(_))));
+        assert_eq!(interpreter.step(), Ok(true));
+        assert_eq!(interpreter.stack(), &[vec![3]]);
+        // 0 IF
+        interpreter.step().unwrap();
+        interpreter.step().unwrap();
+        assert_eq!(interpreter.condition_stack().depth(), 1);
+        assert!(!interpreter.condition_stack().all_true());
+        // RETURN is skipped
+        interpreter.step().unwrap();
+        interpreter.step().unwrap();
+        assert!(interpreter.condition_stack().empty());
+
+        assert!(interpreter.step_back());
+        assert_eq!(interpreter.pc(), 6);
+        assert_eq!(interpreter.condition_stack().depth(), 1);
+        while interpreter.step_back() {}
+        assert_eq!(interpreter.pc(), 0);
+        assert!(interpreter.stack().is_empty());
+
+        assert_eq!(interpreter.run(), Ok(()));
+        assert!(interpreter.is_done());
+        assert_eq!(interpreter.stack(), &[vec![1]]);
+        let trace = interpreter.trace();
+        assert_eq!(trace.len(), 9);
+        assert_eq!(trace[2].pc, 2);
+        assert_eq!(trace[2].state.stack, vec![vec![3]]);
+        assert_eq!(interpreter.step(), Ok(false));
+    }
+
+    #[test]
+    fn test_step_into_error() {
+        // 1 VERIFY 0 VERIFY 1
+        let script = parse_hex("5169006951");
+        let checker = SignatureHashChecker::new(Signature::signature_hash_from_hex("00"));
+        let mut interpreter = Interpreter::new(&script, vec![], &checker, VerifyFlags::NONE);
+        interpreter.enable_trace();
+        assert_eq!(interpreter.run(), Err(ScriptError::Verify));
+        assert_eq!(interpreter.error(), Some(ScriptError::Verify));
+        assert_eq!(interpreter.pc(), 3);
+        assert!(interpreter.is_done());
+        assert_eq!(interpreter.step(), Err(ScriptError::Verify));
+        // going back restores the state in front of the failing command
+        assert!(interpreter.step_back());
+        assert_eq!(interpreter.error(), None);
+        assert_eq!(interpreter.pc(), 3);
+        assert_eq!(interpreter.stack(), &[Vec::<u8>::new()]);
+
+        // without a trace there is nothing to go back to
+        let mut interpreter = Interpreter::new(&script, vec![], &checker, VerifyFlags::NONE);
+        interpreter.step().unwrap();
+        assert!(!interpreter.step_back());
+        assert!(interpreter.trace().is_empty());
+    }
+
+    #[test]
+    fn test_step_back_oversized_script() {
+        // 20 pushes of 520 bytes, 10,460 bytes in all
+        let push = Script::new(Some(vec![Command::Element(vec![0; 520])])).raw_serialize();
+        let script = Script::from_bytes(push.repeat(20));
+        assert_eq!(script.as_bytes().len(), 10_460);
+        let checker = SignatureHashChecker::new(Signature::signature_hash_from_hex("00"));
+        let mut interpreter = Interpreter::new(&script, vec![], &checker, VerifyFlags::NONE);
+        interpreter.enable_trace();
+        assert!(!interpreter.step_back());
+        assert_eq!(interpreter.error(), Some(ScriptError::ScriptSize));
+        assert_eq!(interpreter.run(), Err(ScriptError::ScriptSize));
+    }
+}
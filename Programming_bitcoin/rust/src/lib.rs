@@ -5,11 +5,27 @@ pub mod real_numbers_point;
 pub mod s256_field;
 pub mod s256_point;
 pub mod signature;
+pub mod signature_checker;
 pub mod utils;
 pub mod tx;
 pub mod script;
+pub mod script_builder;
+pub mod script_error;
+pub mod script_num;
 pub mod tx_fetcher;
 pub mod op;
+pub mod interpreter;
+pub mod bip32;
+pub mod bip39;
+pub mod derivation_path;
+pub mod account;
+pub mod bip85;
+pub mod slip39;
+pub mod bip47;
+pub mod bech32;
+pub mod network;
+pub mod address;
+pub mod error;
 
 use num_bigint::BigInt;
 use once_cell::sync::Lazy;
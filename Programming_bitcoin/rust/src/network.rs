@@ -0,0 +1,187 @@
+use std::{fmt::Display, str::FromStr};
+
+use anyhow::{bail, Error, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Network {
+    Mainnet,
+    Testnet,
+    Testnet4,
+    Signet,
+    Regtest,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChainParams {
+    pub name: &'static str,
+    pub p2pkh_prefix: u8,
+    pub p2sh_prefix: u8,
+    pub wif_prefix: u8,
+    pub bech32_hrp: &'static str,
+    pub magic: [u8; 4],
+    pub default_port: u16,
+    // Block hash in the usual (reversed) hex form.
+    pub genesis_hash: &'static str,
+}
+
+const MAINNET_PARAMS: ChainParams = ChainParams {
+    name: "main",
+    p2pkh_prefix: 0x00,
+    p2sh_prefix: 0x05,
+    wif_prefix: 0x80,
+    bech32_hrp: "bc",
+    magic: [0xf9, 0xbe, 0xb4, 0xd9],
+    default_port: 8333,
+    genesis_hash: "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f",
+};
+
+const TESTNET_PARAMS: ChainParams = ChainParams {
+    name: "test",
+    p2pkh_prefix: 0x6f,
+    p2sh_prefix: 0xc4,
+    wif_prefix: 0xef,
+    bech32_hrp: "tb",
+    magic: [0x0b, 0x11, 0x09, 0x07],
+    default_port: 18333,
+    genesis_hash: "000000000933ea01ad0ee984209779baaec3ced90fa3f408719526f8d77f4943",
+};
+
+const TESTNET4_PARAMS: ChainParams = ChainParams {
+    name: "testnet4",
+    p2pkh_prefix: 0x6f,
+    p2sh_prefix: 0xc4,
+    wif_prefix: 0xef,
+    bech32_hrp: "tb",
+    magic: [0x1c, 0x16, 0x3f, 0x28],
+    default_port: 48333,
+    genesis_hash: "00000000da84f2bafbbc53dee25a72ae507ff4914b867c565be350b0da8bf043",
+};
+
+const SIGNET_PARAMS: ChainParams = ChainParams {
+    name: "signet",
+    p2pkh_prefix: 0x6f,
+    p2sh_prefix: 0xc4,
+    wif_prefix: 0xef,
+    bech32_hrp: "tb",
+    magic: [0x0a, 0x03, 0xcf, 0x40],
+    default_port: 38333,
+    genesis_hash: "00000008819873e925422c1ff0f99f7cc9bbb232af63a077a480a3633bee1ef6",
+};
+
+const REGTEST_PARAMS: ChainParams = ChainParams {
+    name: "regtest",
+    p2pkh_prefix: 0x6f,
+    p2sh_prefix: 0xc4,
+    wif_prefix: 0xef,
+    bech32_hrp: "bcrt",
+    magic: [0xfa, 0xbf, 0xb5, 0xda],
+    default_port: 18444,
+    genesis_hash: "0f9188f13cb7b2c71f2a335e3a4fc328bf5beb436012afca590b1a11466e2206",
+};
+
+impl Network {
+    pub fn params(&self) -> &'static ChainParams {
+        match self {
+            Network::Mainnet => &MAINNET_PARAMS,
+            Network::Testnet => &TESTNET_PARAMS,
+            Network::Testnet4 => &TESTNET4_PARAMS,
+            Network::Signet => &SIGNET_PARAMS,
+            Network::Regtest => &REGTEST_PARAMS,
+        }
+    }
+
+    // Every network but mainnet shares the test address, WIF and BIP32 prefixes.
+    pub fn is_testnet(&self) -> bool {
+        *self != Network::Mainnet
+    }
+
+    // BIP44 coin type: 0 for bitcoin, 1 for every test network.
+    pub fn coin_type(&self) -> u32 {
+        if self.is_testnet() {
+            1
+        } else {
+            0
+        }
+    }
+
+    pub fn p2pkh_prefix(&self) -> u8 {
+        self.params().p2pkh_prefix
+    }
+
+    pub fn p2sh_prefix(&self) -> u8 {
+        self.params().p2sh_prefix
+    }
+
+    pub fn wif_prefix(&self) -> u8 {
+        self.params().wif_prefix
+    }
+
+    pub fn bech32_hrp(&self) -> &'static str {
+        self.params().bech32_hrp
+    }
+
+    pub fn magic(&self) -> [u8; 4] {
+        self.params().magic
+    }
+
+    pub fn default_port(&self) -> u16 {
+        self.params().default_port
+    }
+
+    pub fn from_magic(magic: [u8; 4]) -> Option<Self> {
+        [
+            Network::Mainnet,
+            Network::Testnet,
+            Network::Testnet4,
+            Network::Signet,
+            Network::Regtest,
+        ]
+        .into_iter()
+        .find(|network| network.magic() == magic)
+    }
+}
+
+impl FromStr for Network {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "main" | "mainnet" | "bitcoin" => Ok(Network::Mainnet),
+            "test" | "testnet" | "testnet3" => Ok(Network::Testnet),
+            "testnet4" => Ok(Network::Testnet4),
+            "signet" => Ok(Network::Signet),
+            "regtest" => Ok(Network::Regtest),
+            _ => bail!("unknown network: {}", s),
+        }
+    }
+}
+
+impl Display for Network {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.params().name)
+    }
+}
+
+#[cfg(test)]
+mod network_tests {
+    use super::Network;
+
+    #[test]
+    fn test_chain_params() {
+        let mainnet = Network::Mainnet.params();
+        assert_eq!(mainnet.default_port, 8333);
+        assert_eq!(hex::encode(mainnet.magic), "f9beb4d9");
+        assert_eq!(Network::Regtest.bech32_hrp(), "bcrt");
+        assert_eq!(Network::Signet.wif_prefix(), 0xef);
+        assert_eq!(
+            Network::from_magic([0x1c, 0x16, 0x3f, 0x28]),
+            Some(Network::Testnet4)
+        );
+        assert_eq!("signet".parse::<Network>().unwrap(), Network::Signet);
+        assert_eq!(
+            Network::Testnet.to_string().parse::<Network>().unwrap(),
+            Network::Testnet
+        );
+        assert!("litecoin".parse::<Network>().is_err());
+    }
+}
@@ -1,34 +1,181 @@
 use std::io::Cursor;
 
-use byteorder::{BigEndian, ByteOrder};
 use crypto::{digest::Digest, sha1::Sha1};
 
 use crate::{
-    s256_point::S256Point,
-    script::Command,
-    signature::{Signature, SignatureHash},
-    utils,
+    error::Error,
+    script::{self, Command, Script, VerifyFlags, MAX_OPS_PER_SCRIPT, MAX_PUBKEYS_PER_MULTISIG},
+    script_error::ScriptError,
+    script_num::{ScriptNum, DEFAULT_MAX_NUM_SIZE, LOCKTIME_MAX_NUM_SIZE},
+    signature::{Signature, SIGHASH_ALL, SIGHASH_ANYONECANPAY, SIGHASH_SINGLE},
+    signature_checker::{SigVersion, SignatureChecker},
+    utils, N,
 };
 
-#[derive(Debug, Clone)]
-pub enum OpCodeFunctions {
-    Op0(u32),
-    OpChecksig(u32),
-    OpDup(u32),
-    OpHash160(u32),
-    OpHash256(u32),
-    OpEqualverify(u32),
-    OpEqual(u32),
-    OpVerify(u32),
-    Op6(u32),
-    OpAdd(u32),
-    OpMul(u32),
-    Op2(u32),
-    Op2dup(u32),
-    OpSwap(u32),
-    OpNot(u32),
-    OpSha1(u32),
-    OpSigHashAll(u32),
+// One entry per opcode byte: variant, byte, name as printed by Bitcoin Core's GetOpName.
+// Direct pushes (0x01-0x4b) and the unassigned bytes (0xbb-0xfe) are covered by
+// OpPushBytes and OpUnknown, which keep the byte in their payload.
+macro_rules! op_codes {
+    ($($variant:ident = $byte:literal => $name:literal,)*) => {
+        #[derive(Debug, Clone, PartialEq)]
+        pub enum OpCodeFunctions {
+            $($variant(u32),)*
+            OpPushBytes(u32),
+            OpUnknown(u32),
+        }
+
+        impl AsRef<u32> for OpCodeFunctions {
+            fn as_ref(&self) -> &u32 {
+                match self {
+                    $(OpCodeFunctions::$variant(_) => &$byte,)*
+                    OpCodeFunctions::OpPushBytes(op) => op,
+                    OpCodeFunctions::OpUnknown(op) => op,
+                }
+            }
+        }
+
+        pub fn parse_raw_op_codes(op_code: u32) -> Result<OpCodeFunctions, Error> {
+            let op = match op_code {
+                $($byte => OpCodeFunctions::$variant(op_code),)*
+                0x01..=0x4b => OpCodeFunctions::OpPushBytes(op_code),
+                0xbb..=0xfe => OpCodeFunctions::OpUnknown(op_code),
+                unknown => return Err(Error::UnknownOpcode(unknown)),
+            };
+            Ok(op)
+        }
+
+        pub fn get_op_names(op_code: &OpCodeFunctions) -> &str {
+            match op_code {
+                $(OpCodeFunctions::$variant(_) => $name,)*
+                OpCodeFunctions::OpPushBytes(_) => "OP_UNKNOWN",
+                OpCodeFunctions::OpUnknown(_) => "OP_UNKNOWN",
+            }
+        }
+
+        // Inverse of get_op_names.
+        pub fn op_code_from_name(name: &str) -> Option<OpCodeFunctions> {
+            match name {
+                $($name => Some(OpCodeFunctions::$variant($byte)),)*
+                _ => None,
+            }
+        }
+    };
+}
+
+op_codes! {
+    Op0 = 0x00 => "0",
+    OpPushdata1 = 0x4c => "OP_PUSHDATA1",
+    OpPushdata2 = 0x4d => "OP_PUSHDATA2",
+    OpPushdata4 = 0x4e => "OP_PUSHDATA4",
+    Op1negate = 0x4f => "-1",
+    OpReserved = 0x50 => "OP_RESERVED",
+    Op1 = 0x51 => "1",
+    Op2 = 0x52 => "2",
+    Op3 = 0x53 => "3",
+    Op4 = 0x54 => "4",
+    Op5 = 0x55 => "5",
+    Op6 = 0x56 => "6",
+    Op7 = 0x57 => "7",
+    Op8 = 0x58 => "8",
+    Op9 = 0x59 => "9",
+    Op10 = 0x5a => "10",
+    Op11 = 0x5b => "11",
+    Op12 = 0x5c => "12",
+    Op13 = 0x5d => "13",
+    Op14 = 0x5e => "14",
+    Op15 = 0x5f => "15",
+    Op16 = 0x60 => "16",
+    OpNop = 0x61 => "OP_NOP",
+    OpVer = 0x62 => "OP_VER",
+    OpIf = 0x63 => "OP_IF",
+    OpNotif = 0x64 => "OP_NOTIF",
+    OpVerif = 0x65 => "OP_VERIF",
+    OpVernotif = 0x66 => "OP_VERNOTIF",
+    OpElse = 0x67 => "OP_ELSE",
+    OpEndif = 0x68 => "OP_ENDIF",
+    OpVerify = 0x69 => "OP_VERIFY",
+    OpReturn = 0x6a => "OP_RETURN",
+    OpToaltstack = 0x6b => "OP_TOALTSTACK",
+    OpFromaltstack = 0x6c => "OP_FROMALTSTACK",
+    Op2drop = 0x6d => "OP_2DROP",
+    Op2dup = 0x6e => "OP_2DUP",
+    Op3dup = 0x6f => "OP_3DUP",
+    Op2over = 0x70 => "OP_2OVER",
+    Op2rot = 0x71 => "OP_2ROT",
+    Op2swap = 0x72 => "OP_2SWAP",
+    OpIfdup = 0x73 => "OP_IFDUP",
+    OpDepth = 0x74 => "OP_DEPTH",
+    OpDrop = 0x75 => "OP_DROP",
+    OpDup = 0x76 => "OP_DUP",
+    OpNip = 0x77 => "OP_NIP",
+    OpOver = 0x78 => "OP_OVER",
+    OpPick = 0x79 => "OP_PICK",
+    OpRoll = 0x7a => "OP_ROLL",
+    OpRot = 0x7b => "OP_ROT",
+    OpSwap = 0x7c => "OP_SWAP",
+    OpTuck = 0x7d => "OP_TUCK",
+    OpCat = 0x7e => "OP_CAT",
+    OpSubstr = 0x7f => "OP_SUBSTR",
+    OpLeft = 0x80 => "OP_LEFT",
+    OpRight = 0x81 => "OP_RIGHT",
+    OpSize = 0x82 => "OP_SIZE",
+    OpInvert = 0x83 => "OP_INVERT",
+    OpAnd = 0x84 => "OP_AND",
+    OpOr = 0x85 => "OP_OR",
+    OpXor = 0x86 => "OP_XOR",
+    OpEqual = 0x87 => "OP_EQUAL",
+    OpEqualverify = 0x88 => "OP_EQUALVERIFY",
+    OpReserved1 = 0x89 => "OP_RESERVED1",
+    OpReserved2 = 0x8a => "OP_RESERVED2",
+    Op1add = 0x8b => "OP_1ADD",
+    Op1sub = 0x8c => "OP_1SUB",
+    Op2mul = 0x8d => "OP_2MUL",
+    Op2div = 0x8e => "OP_2DIV",
+    OpNegate = 0x8f => "OP_NEGATE",
+    OpAbs = 0x90 => "OP_ABS",
+    OpNot = 0x91 => "OP_NOT",
+    Op0notequal = 0x92 => "OP_0NOTEQUAL",
+    OpAdd = 0x93 => "OP_ADD",
+    OpSub = 0x94 => "OP_SUB",
+    OpMul = 0x95 => "OP_MUL",
+    OpDiv = 0x96 => "OP_DIV",
+    OpMod = 0x97 => "OP_MOD",
+    OpLshift = 0x98 => "OP_LSHIFT",
+    OpRshift = 0x99 => "OP_RSHIFT",
+    OpBooland = 0x9a => "OP_BOOLAND",
+    OpBoolor = 0x9b => "OP_BOOLOR",
+    OpNumequal = 0x9c => "OP_NUMEQUAL",
+    OpNumequalverify = 0x9d => "OP_NUMEQUALVERIFY",
+    OpNumnotequal = 0x9e => "OP_NUMNOTEQUAL",
+    OpLessthan = 0x9f => "OP_LESSTHAN",
+    OpGreaterthan = 0xa0 => "OP_GREATERTHAN",
+    OpLessthanorequal = 0xa1 => "OP_LESSTHANOREQUAL",
+    OpGreaterthanorequal = 0xa2 => "OP_GREATERTHANOREQUAL",
+    OpMin = 0xa3 => "OP_MIN",
+    OpMax = 0xa4 => "OP_MAX",
+    OpWithin = 0xa5 => "OP_WITHIN",
+    OpRipemd160 = 0xa6 => "OP_RIPEMD160",
+    OpSha1 = 0xa7 => "OP_SHA1",
+    OpSha256 = 0xa8 => "OP_SHA256",
+    OpHash160 = 0xa9 => "OP_HASH160",
+    OpHash256 = 0xaa => "OP_HASH256",
+    OpCodeseparator = 0xab => "OP_CODESEPARATOR",
+    OpChecksig = 0xac => "OP_CHECKSIG",
+    OpChecksigverify = 0xad => "OP_CHECKSIGVERIFY",
+    OpCheckmultisig = 0xae => "OP_CHECKMULTISIG",
+    OpCheckmultisigverify = 0xaf => "OP_CHECKMULTISIGVERIFY",
+    OpNop1 = 0xb0 => "OP_NOP1",
+    OpChecklocktimeverify = 0xb1 => "OP_CHECKLOCKTIMEVERIFY",
+    OpChecksequenceverify = 0xb2 => "OP_CHECKSEQUENCEVERIFY",
+    OpNop4 = 0xb3 => "OP_NOP4",
+    OpNop5 = 0xb4 => "OP_NOP5",
+    OpNop6 = 0xb5 => "OP_NOP6",
+    OpNop7 = 0xb6 => "OP_NOP7",
+    OpNop8 = 0xb7 => "OP_NOP8",
+    OpNop9 = 0xb8 => "OP_NOP9",
+    OpNop10 = 0xb9 => "OP_NOP10",
+    OpChecksigadd = 0xba => "OP_CHECKSIGADD",
+    OpInvalidopcode = 0xff => "OP_INVALIDOPCODE",
 }
 
 impl OpCodeFunctions {
@@ -36,6 +183,10 @@ impl OpCodeFunctions {
         OpCodeFunctions::Op0(0)
     }
 
+    pub fn op1() -> Self {
+        OpCodeFunctions::Op1(0x51)
+    }
+
     pub fn op_checksig() -> Self {
         OpCodeFunctions::OpChecksig(172)
     }
@@ -64,301 +215,644 @@ impl OpCodeFunctions {
         OpCodeFunctions::OpVerify(105)
     }
 
-    pub fn op_sig_hash_all() -> Self {
-        OpCodeFunctions::OpSigHashAll(1)
+    pub fn op_return() -> Self {
+        OpCodeFunctions::OpReturn(0x6a)
     }
-}
 
-impl AsRef<u32> for OpCodeFunctions {
-    fn as_ref(&self) -> &u32 {
-        match &self {
-            OpCodeFunctions::Op0(op) => op,
-            OpCodeFunctions::OpChecksig(op) => op,
-            OpCodeFunctions::OpDup(op) => op,
-            OpCodeFunctions::OpHash160(op) => op,
-            OpCodeFunctions::OpEqualverify(op) => op,
-            OpCodeFunctions::OpEqual(op) => op,
-            OpCodeFunctions::OpHash256(op) => op,
-            OpCodeFunctions::OpVerify(op) => op,
-            OpCodeFunctions::Op6(op) => op,
-            OpCodeFunctions::OpAdd(op) => op,
-            OpCodeFunctions::OpMul(op) => op,
-            OpCodeFunctions::Op2(op) => op,
-            OpCodeFunctions::Op2dup(op) => op,
-            OpCodeFunctions::OpSwap(op) => op,
-            OpCodeFunctions::OpNot(op) => op,
-            OpCodeFunctions::OpSha1(op) => op,
-            OpCodeFunctions::OpSigHashAll(op) => op,
-        }
+    // Disabled since 0.3.x, these fail a script even inside an unexecuted branch.
+    pub fn is_disabled(&self) -> bool {
+        matches!(
+            self,
+            OpCodeFunctions::OpCat(_)
+                | OpCodeFunctions::OpSubstr(_)
+                | OpCodeFunctions::OpLeft(_)
+                | OpCodeFunctions::OpRight(_)
+                | OpCodeFunctions::OpInvert(_)
+                | OpCodeFunctions::OpAnd(_)
+                | OpCodeFunctions::OpOr(_)
+                | OpCodeFunctions::OpXor(_)
+                | OpCodeFunctions::Op2mul(_)
+                | OpCodeFunctions::Op2div(_)
+                | OpCodeFunctions::OpMul(_)
+                | OpCodeFunctions::OpDiv(_)
+                | OpCodeFunctions::OpMod(_)
+                | OpCodeFunctions::OpLshift(_)
+                | OpCodeFunctions::OpRshift(_)
+        )
     }
-}
 
-pub fn parse_raw_op_codes(op_code: u32) -> OpCodeFunctions {
-    match op_code {
-        0 => OpCodeFunctions::Op0(op_code),
-        172 => OpCodeFunctions::OpChecksig(op_code),
-        118 => OpCodeFunctions::OpDup(op_code),
-        169 => OpCodeFunctions::OpHash160(op_code),
-        136 => OpCodeFunctions::OpEqualverify(op_code),
-        135 => OpCodeFunctions::OpEqual(op_code),
-        170 => OpCodeFunctions::OpHash256(op_code),
-        105 => OpCodeFunctions::OpVerify(op_code),
-        86 => OpCodeFunctions::Op6(86),
-        147 => OpCodeFunctions::OpAdd(147),
-        149 => OpCodeFunctions::OpMul(149),
-        0x52 => OpCodeFunctions::Op2(2),
-        0x6e => OpCodeFunctions::Op2dup(0x6e),
-        0x7c => OpCodeFunctions::OpSwap(0x7c),
-        0x91 => OpCodeFunctions::OpNot(0x91),
-        0xa7 => OpCodeFunctions::OpSha1(0xa7),
-        unknow => panic!("unknown opCode: {}", unknow),
-    }
-}
-
-pub fn get_op_names(op_code: &OpCodeFunctions) -> &str {
-    match op_code {
-        OpCodeFunctions::Op0(_) => "OP_0",
-        OpCodeFunctions::OpChecksig(_) => "OP_CHECK_SIG",
-        OpCodeFunctions::OpDup(_) => "OP_DUP",
-        OpCodeFunctions::OpHash160(_) => "OP_HASH_160",
-        OpCodeFunctions::OpHash256(_) => "OP_HASH_256",
-        OpCodeFunctions::OpEqualverify(_) => "OP_EQUAL_VERIFY",
-        OpCodeFunctions::OpEqual(_) => "OP_EQUAL",
-        OpCodeFunctions::OpVerify(_) => "OP_VERIFY",
-        OpCodeFunctions::Op6(_) => "OP_6",
-        OpCodeFunctions::OpAdd(_) => "OP_ADD",
-        OpCodeFunctions::OpMul(_) => "OP_MUL",
-        OpCodeFunctions::Op2(_) => "OP_2",
-        OpCodeFunctions::Op2dup(_) => "OP_2_DUP",
-        OpCodeFunctions::OpSwap(_) => "OP_SWAP",
-        OpCodeFunctions::OpNot(_) => "OP_NOT",
-        OpCodeFunctions::OpSha1(_) => "OP_SHA1",
-        OpCodeFunctions::OpSigHashAll(_) => "OP_SIG_HASH_ALL",
+    // BIP342 OP_SUCCESSx: these make a tapscript succeed unconditionally.
+    pub fn is_op_success(&self) -> bool {
+        matches!(
+            *self.as_ref(),
+            80 | 98 | 126..=129 | 131..=134 | 137..=138 | 141..=142 | 149..=153 | 187..=254
+        )
     }
 }
 
+// Runs a single opcode inside an executed branch. Pushes, flow control and the
+// resource limits are handled by Script::execute.
+// script_code is what signatures commit to: the executing script from the
+// last OP_CODESEPARATOR on.
 pub fn operation(
     op_code: OpCodeFunctions,
     stack: &mut Vec<Vec<u8>>,
-    cmds: &mut Vec<Command>,
     altstack: &mut Vec<Vec<u8>>,
-    z: &SignatureHash,
-) -> bool {
+    checker: &dyn SignatureChecker,
+    script_code: &[u8],
+    flags: VerifyFlags,
+    op_count: &mut usize,
+) -> Result<(), ScriptError> {
     match op_code {
-        OpCodeFunctions::Op0(_) => {
-            stack.push(encode_num(0));
-            true
+        OpCodeFunctions::Op0(_) => stack.push(ScriptNum::new(0).encode()),
+        OpCodeFunctions::Op1negate(_) => stack.push(ScriptNum::new(-1).encode()),
+        OpCodeFunctions::Op1(_)
+        | OpCodeFunctions::Op2(_)
+        | OpCodeFunctions::Op3(_)
+        | OpCodeFunctions::Op4(_)
+        | OpCodeFunctions::Op5(_)
+        | OpCodeFunctions::Op6(_)
+        | OpCodeFunctions::Op7(_)
+        | OpCodeFunctions::Op8(_)
+        | OpCodeFunctions::Op9(_)
+        | OpCodeFunctions::Op10(_)
+        | OpCodeFunctions::Op11(_)
+        | OpCodeFunctions::Op12(_)
+        | OpCodeFunctions::Op13(_)
+        | OpCodeFunctions::Op14(_)
+        | OpCodeFunctions::Op15(_)
+        | OpCodeFunctions::Op16(_) => {
+            stack.push(ScriptNum::new(*op_code.as_ref() as i64 - 0x50).encode());
         }
-        OpCodeFunctions::OpChecksig(_) => {
-            if stack.len() < 2 {
-                return false;
-            } else {
-                let sec_pubkey = stack.pop().unwrap();
-                let der_signature = stack.pop().unwrap();
-                let mut der_signature_cursor = Cursor::new(der_signature);
-                let point = S256Point::parse(&sec_pubkey);
-                let sig = Signature::parse(&mut der_signature_cursor);
-                if point.verify(z, sig) {
-                    stack.push(encode_num(1))
-                } else {
-                    stack.push(encode_num(0))
+        OpCodeFunctions::OpChecksig(_) | OpCodeFunctions::OpChecksigverify(_) => {
+            check_stack_size(stack, 2)?;
+            let sec_pubkey = stack.pop().unwrap();
+            let der_signature = stack.pop().unwrap();
+            let script_code =
+                find_and_delete(script_code, std::slice::from_ref(&der_signature), flags)?;
+            check_signature_encoding(&der_signature, flags)?;
+            check_pubkey_encoding(&sec_pubkey, flags)?;
+            let success =
+                checker.check_sig(&der_signature, &sec_pubkey, &script_code, SigVersion::Base);
+            if !success && flags.contains(VerifyFlags::NULLFAIL) && !der_signature.is_empty() {
+                return Err(ScriptError::SigNullFail);
+            }
+            if let OpCodeFunctions::OpChecksigverify(_) = op_code {
+                if !success {
+                    return Err(ScriptError::CheckSigVerify);
                 }
+            } else {
+                stack.push(ScriptNum::new(success as i64).encode());
             }
-            true
         }
-        OpCodeFunctions::OpDup(_) => {
-            if stack.len() < 1 {
-                return false;
+        OpCodeFunctions::OpCheckmultisig(_) => {
+            let success = check_multisig(stack, checker, script_code, flags, op_count)?;
+            stack.push(ScriptNum::new(success as i64).encode());
+        }
+        OpCodeFunctions::OpCheckmultisigverify(_) => {
+            if !check_multisig(stack, checker, script_code, flags, op_count)? {
+                return Err(ScriptError::CheckMultisigVerify);
             }
+        }
+        OpCodeFunctions::OpDup(_) => {
+            check_stack_size(stack, 1)?;
             stack.push(stack.last().unwrap().to_vec());
-            true
+        }
+        OpCodeFunctions::OpRipemd160(_) => {
+            let element = pop(stack)?;
+            stack.push(utils::ripemd160(&element));
+        }
+        OpCodeFunctions::OpSha1(_) => {
+            let mut out = [0u8; 20];
+            let element = pop(stack)?;
+            let mut hasher = Sha1::new();
+            hasher.input(element.as_slice());
+            hasher.result(&mut out);
+            stack.push(out.to_vec());
+        }
+        OpCodeFunctions::OpSha256(_) => {
+            let element = pop(stack)?;
+            stack.push(utils::sha256(&element));
         }
         OpCodeFunctions::OpHash160(_) => {
-            if stack.len() < 1 {
-                return false;
-            }
-            let element = stack.pop().unwrap();
+            let element = pop(stack)?;
             stack.push(utils::hash160(&element));
-            return true;
         }
-        OpCodeFunctions::OpEqualverify(_) => {
-            operation(OpCodeFunctions::op_equal(), stack, cmds, altstack, z)
-                && operation(OpCodeFunctions::op_verify(), stack, cmds, altstack, z)
+        OpCodeFunctions::OpHash256(_) => {
+            let element = pop(stack)?;
+            stack.push(utils::hash256(&element));
         }
-        OpCodeFunctions::OpEqual(_) => {
-            if stack.len() < 2 {
-                return false;
-            }
+        OpCodeFunctions::OpEqual(_) | OpCodeFunctions::OpEqualverify(_) => {
+            check_stack_size(stack, 2)?;
             let element1 = stack.pop().unwrap();
             let element2 = stack.pop().unwrap();
-            if element1 == element2 {
-                stack.push(encode_num(1))
+            let equal = element1 == element2;
+            if let OpCodeFunctions::OpEqualverify(_) = op_code {
+                if !equal {
+                    return Err(ScriptError::EqualVerify);
+                }
             } else {
-                stack.push(encode_num(0))
+                stack.push(ScriptNum::new(equal as i64).encode());
             }
-            return true;
-        }
-        OpCodeFunctions::OpHash256(_) => {
-            if stack.len() < 1 {
-                return false;
-            }
-            let element = stack.pop().unwrap();
-            stack.push(utils::hash256(&element));
-            return true;
         }
         OpCodeFunctions::OpVerify(_) => {
-            if stack.len() < 1 {
-                return false;
-            }
-            let element = stack.pop().unwrap();
-            if decode_num(element) == 0 {
-                return false;
+            let element = pop(stack)?;
+            if !cast_to_bool(&element) {
+                return Err(ScriptError::Verify);
             }
-            return true;
         }
-        OpCodeFunctions::Op6(_) => {
-            stack.push(encode_num(6));
-            true
+        OpCodeFunctions::OpReturn(_) => return Err(ScriptError::OpReturn),
+        OpCodeFunctions::Op1add(_)
+        | OpCodeFunctions::Op1sub(_)
+        | OpCodeFunctions::OpNegate(_)
+        | OpCodeFunctions::OpAbs(_)
+        | OpCodeFunctions::OpNot(_)
+        | OpCodeFunctions::Op0notequal(_) => {
+            let num = pop_num(stack, flags)?;
+            let result = match op_code {
+                OpCodeFunctions::Op1add(_) => num + ScriptNum::new(1),
+                OpCodeFunctions::Op1sub(_) => num - ScriptNum::new(1),
+                OpCodeFunctions::OpNegate(_) => -num,
+                OpCodeFunctions::OpAbs(_) => ScriptNum::new(num.value().abs()),
+                OpCodeFunctions::OpNot(_) => ScriptNum::new((num.value() == 0) as i64),
+                _ => ScriptNum::new((num.value() != 0) as i64),
+            };
+            stack.push(result.encode());
         }
-        OpCodeFunctions::OpAdd(_) => {
-            if stack.len() < 2 {
-                return false;
-            }
-            let element1 = decode_num(stack.pop().unwrap());
-            let element2 = decode_num(stack.pop().unwrap()); //BigEndian::read_i32(&[0, 0, 0, stack.pop().unwrap()[0]]);
-            stack.push(encode_num(element1 + element2));
-            return true;
-        }
-        OpCodeFunctions::OpMul(_) => {
-            if stack.len() < 2 {
-                return false;
-            }
-            let element1 = decode_num(stack.pop().unwrap());
-            let element2 = decode_num(stack.pop().unwrap()); //BigEndian::read_i32(&[0, 0, 0, stack.pop().unwrap()[0]]);
-            stack.push(encode_num(element1 * element2));
-            return true;
+        OpCodeFunctions::OpAdd(_)
+        | OpCodeFunctions::OpSub(_)
+        | OpCodeFunctions::OpBooland(_)
+        | OpCodeFunctions::OpBoolor(_)
+        | OpCodeFunctions::OpNumequal(_)
+        | OpCodeFunctions::OpNumequalverify(_)
+        | OpCodeFunctions::OpNumnotequal(_)
+        | OpCodeFunctions::OpLessthan(_)
+        | OpCodeFunctions::OpGreaterthan(_)
+        | OpCodeFunctions::OpLessthanorequal(_)
+        | OpCodeFunctions::OpGreaterthanorequal(_)
+        | OpCodeFunctions::OpMin(_)
+        | OpCodeFunctions::OpMax(_) => {
+            check_stack_size(stack, 2)?;
+            let b = pop_num(stack, flags)?;
+            let a = pop_num(stack, flags)?;
+            let result = match op_code {
+                OpCodeFunctions::OpAdd(_) => a + b,
+                OpCodeFunctions::OpSub(_) => a - b,
+                OpCodeFunctions::OpBooland(_) => {
+                    ScriptNum::new((a.value() != 0 && b.value() != 0) as i64)
+                }
+                OpCodeFunctions::OpBoolor(_) => {
+                    ScriptNum::new((a.value() != 0 || b.value() != 0) as i64)
+                }
+                OpCodeFunctions::OpNumequal(_) => ScriptNum::new((a == b) as i64),
+                OpCodeFunctions::OpNumequalverify(_) => {
+                    if a != b {
+                        return Err(ScriptError::NumEqualVerify);
+                    }
+                    return Ok(());
+                }
+                OpCodeFunctions::OpNumnotequal(_) => ScriptNum::new((a != b) as i64),
+                OpCodeFunctions::OpLessthan(_) => ScriptNum::new((a < b) as i64),
+                OpCodeFunctions::OpGreaterthan(_) => ScriptNum::new((a > b) as i64),
+                OpCodeFunctions::OpLessthanorequal(_) => ScriptNum::new((a <= b) as i64),
+                OpCodeFunctions::OpGreaterthanorequal(_) => ScriptNum::new((a >= b) as i64),
+                OpCodeFunctions::OpMin(_) => a.min(b),
+                _ => a.max(b),
+            };
+            stack.push(result.encode());
         }
-        OpCodeFunctions::Op2(_) => {
-            stack.push(encode_num(2));
-            true
+        // x min max WITHIN: min <= x < max
+        OpCodeFunctions::OpWithin(_) => {
+            check_stack_size(stack, 3)?;
+            let max = pop_num(stack, flags)?;
+            let min = pop_num(stack, flags)?;
+            let x = pop_num(stack, flags)?;
+            stack.push(ScriptNum::new((min <= x && x < max) as i64).encode());
         }
         OpCodeFunctions::Op2dup(_) => {
-            if stack.len() < 2 {
-                return false;
-            }
+            check_stack_size(stack, 2)?;
             stack.append(&mut stack[stack.len() - 2..].to_vec());
-            return true;
         }
-        OpCodeFunctions::OpSwap(_) => {
-            if stack.len() < 2 {
-                return false;
+        OpCodeFunctions::OpToaltstack(_) => altstack.push(pop(stack)?),
+        OpCodeFunctions::OpFromaltstack(_) => match altstack.pop() {
+            Some(element) => stack.push(element),
+            None => return Err(ScriptError::InvalidAltstackOperation),
+        },
+        OpCodeFunctions::Op2drop(_) => {
+            check_stack_size(stack, 2)?;
+            stack.truncate(stack.len() - 2);
+        }
+        OpCodeFunctions::Op3dup(_) => {
+            check_stack_size(stack, 3)?;
+            stack.append(&mut stack[stack.len() - 3..].to_vec());
+        }
+        OpCodeFunctions::Op2over(_) => {
+            check_stack_size(stack, 4)?;
+            stack.append(&mut stack[stack.len() - 4..stack.len() - 2].to_vec());
+        }
+        OpCodeFunctions::Op2rot(_) => {
+            check_stack_size(stack, 6)?;
+            let len = stack.len();
+            let mut pair: Vec<Vec<u8>> = stack.drain(len - 6..len - 4).collect();
+            stack.append(&mut pair);
+        }
+        OpCodeFunctions::Op2swap(_) => {
+            check_stack_size(stack, 4)?;
+            let len = stack.len();
+            stack.swap(len - 4, len - 2);
+            stack.swap(len - 3, len - 1);
+        }
+        OpCodeFunctions::OpIfdup(_) => match stack.last() {
+            Some(element) if cast_to_bool(element) => stack.push(element.to_vec()),
+            Some(_) => {}
+            None => return Err(ScriptError::InvalidStackOperation),
+        },
+        OpCodeFunctions::OpDepth(_) => stack.push(ScriptNum::new(stack.len() as i64).encode()),
+        OpCodeFunctions::OpDrop(_) => {
+            pop(stack)?;
+        }
+        OpCodeFunctions::OpNip(_) => {
+            check_stack_size(stack, 2)?;
+            stack.remove(stack.len() - 2);
+        }
+        OpCodeFunctions::OpOver(_) => {
+            check_stack_size(stack, 2)?;
+            stack.push(stack[stack.len() - 2].to_vec());
+        }
+        OpCodeFunctions::OpPick(_) | OpCodeFunctions::OpRoll(_) => {
+            check_stack_size(stack, 2)?;
+            let n = pop_num(stack, flags)?.to_i32();
+            if n < 0 || n as usize >= stack.len() {
+                return Err(ScriptError::InvalidStackOperation);
             }
+            let index = stack.len() - 1 - n as usize;
+            let element = if let OpCodeFunctions::OpRoll(_) = op_code {
+                stack.remove(index)
+            } else {
+                stack[index].to_vec()
+            };
+            stack.push(element);
+        }
+        OpCodeFunctions::OpRot(_) => {
+            check_stack_size(stack, 3)?;
+            let element = stack.remove(stack.len() - 3);
+            stack.push(element);
+        }
+        OpCodeFunctions::OpSwap(_) => {
+            check_stack_size(stack, 2)?;
             let len = stack.len();
             stack.swap(len - 1, len - 2);
-            return true;
         }
-        OpCodeFunctions::OpNot(_) => {
-            if stack.len() < 2 {
-                return false;
+        OpCodeFunctions::OpTuck(_) => {
+            check_stack_size(stack, 2)?;
+            let top = stack.last().unwrap().to_vec();
+            stack.insert(stack.len() - 2, top);
+        }
+        OpCodeFunctions::OpSize(_) => match stack.last() {
+            Some(element) => stack.push(ScriptNum::new(element.len() as i64).encode()),
+            None => return Err(ScriptError::InvalidStackOperation),
+        },
+        OpCodeFunctions::OpChecklocktimeverify(_) => {
+            if !flags.contains(VerifyFlags::CHECKLOCKTIMEVERIFY) {
+                return check_upgradable_nop(flags);
             }
-            if decode_num(stack.pop().unwrap()) == 0 {
-                stack.push(encode_num(1))
-            } else {
-                stack.push(encode_num(0))
+            // the argument stays on the stack, scripts usually OP_DROP it
+            let element = stack.last().ok_or(ScriptError::InvalidStackOperation)?;
+            let lock_time = decode_lock_time(element, flags)?;
+            if lock_time < 0 {
+                return Err(ScriptError::NegativeLocktime);
+            }
+            if !checker.check_lock_time(lock_time) {
+                return Err(ScriptError::UnsatisfiedLocktime);
             }
-            return true;
         }
-        OpCodeFunctions::OpSha1(_) => {
-            if stack.len() < 1 {
-                return false;
+        OpCodeFunctions::OpChecksequenceverify(_) => {
+            if !flags.contains(VerifyFlags::CHECKSEQUENCEVERIFY) {
+                return check_upgradable_nop(flags);
+            }
+            let element = stack.last().ok_or(ScriptError::InvalidStackOperation)?;
+            let sequence = decode_lock_time(element, flags)?;
+            if sequence < 0 {
+                return Err(ScriptError::NegativeLocktime);
+            }
+            // with the disable flag set the opcode is a NOP
+            if sequence & (1 << 31) != 0 {
+                return Ok(());
+            }
+            if !checker.check_sequence(sequence) {
+                return Err(ScriptError::UnsatisfiedLocktime);
             }
-            let mut out = [0u8; 20];
-            let element = stack.pop().unwrap();
-            let mut hasher = Sha1::new();
-            hasher.input(element.as_slice());
-            hasher.result(&mut out);
-            stack.push(out.to_vec());
-            return true;
         }
-        OpCodeFunctions::OpSigHashAll(_) => todo!(),
+        OpCodeFunctions::OpNop(_) => {}
+        OpCodeFunctions::OpNop1(_)
+        | OpCodeFunctions::OpNop4(_)
+        | OpCodeFunctions::OpNop5(_)
+        | OpCodeFunctions::OpNop6(_)
+        | OpCodeFunctions::OpNop7(_)
+        | OpCodeFunctions::OpNop8(_)
+        | OpCodeFunctions::OpNop9(_)
+        | OpCodeFunctions::OpNop10(_) => return check_upgradable_nop(flags),
+        // reserved and unassigned opcodes, and OP_CHECKSIGADD outside tapscript
+        _ => return Err(ScriptError::BadOpcode),
     }
+    Ok(())
 }
 
-pub fn encode_num(num: i32) -> Vec<u8> {
-    if num == 0 {
-        return b"".to_vec();
-    }
-    let mut abs_num = num.abs();
-    let negative = num < 0;
-    let mut result: Vec<u8> = Vec::new();
-    while abs_num > 0 {
-        result.append(&mut utils::i32_to_little_endian(abs_num & 0xff, 1));
-        abs_num >>= 8
-    }
-    match result.last() {
-        Some(last) => {
-            let last_c = last.clone();
-            if (last_c & 0x80) == 1 {
-                if negative {
-                    result.push(0x80)
-                } else {
-                    result.push(0x0)
-                }
-            } else if negative {
-                result.pop();
-                result.push(last_c | 0x80)
+fn check_stack_size(stack: &[Vec<u8>], size: usize) -> Result<(), ScriptError> {
+    if stack.len() < size {
+        return Err(ScriptError::InvalidStackOperation);
+    }
+    Ok(())
+}
+
+fn pop(stack: &mut Vec<Vec<u8>>) -> Result<Vec<u8>, ScriptError> {
+    stack.pop().ok_or(ScriptError::InvalidStackOperation)
+}
+
+// The NOPs reserved for soft forks may be rejected by policy.
+fn check_upgradable_nop(flags: VerifyFlags) -> Result<(), ScriptError> {
+    if flags.contains(VerifyFlags::DISCOURAGE_UPGRADABLE_NOPS) {
+        return Err(ScriptError::DiscourageUpgradableNops);
+    }
+    Ok(())
+}
+
+// FindAndDelete: removes the pushes of the signatures from a legacy
+// scriptCode. Like Core it matches the serialized push at every opcode
+// boundary, so data pushed with a longer push opcode than needed stays.
+fn find_and_delete(
+    script_code: &[u8],
+    sigs: &[Vec<u8>],
+    flags: VerifyFlags,
+) -> Result<Script, ScriptError> {
+    let mut raw = script_code.to_vec();
+    let mut found = 0;
+    for sig in sigs {
+        let pattern = Script::new(Some(vec![Command::Element(sig.clone())])).raw_serialize();
+        let mut result = Vec::with_capacity(raw.len());
+        let mut pc = 0;
+        let mut pc2 = 0;
+        loop {
+            result.extend_from_slice(&raw[pc2..pc]);
+            while raw[pc..].starts_with(&pattern) {
+                pc += pattern.len();
+                found += 1;
+            }
+            pc2 = pc;
+            match script::decode_instruction(&raw, pc) {
+                Some(Ok(instruction)) => pc = instruction.end,
+                _ => break,
             }
         }
-        None => panic!("unable to get last element of encoded num"),
+        result.extend_from_slice(&raw[pc2..]);
+        raw = result;
+    }
+    if found > 0 && flags.contains(VerifyFlags::CONST_SCRIPTCODE) {
+        return Err(ScriptError::SigFindAndDelete);
     }
-    result
+    Ok(Script::from_bytes(raw))
 }
 
-fn decode_num(element: Vec<u8>) -> i32 {
-    if element == b"".to_vec() {
-        return 0;
-    }
-    let mut result: i32;
-    let mut big_endian = element.clone();
-    big_endian.reverse();
-    let negative: bool;
-    if (big_endian[0] & 0x80) == 1 {
-        negative = true;
-        result = BigEndian::read_i32(&[0, 0, 0, big_endian[0] & 0x7f]) as i32;
-    } else {
-        negative = false;
-        result = BigEndian::read_i32(&[0, 0, 0, big_endian[0]]) as i32;
-    }
-    for c in &big_endian[1..] {
-        result <<= 8;
-        result += BigEndian::read_i32(&[0, 0, 0, c.clone()]) as i32;
-    }
-    if negative {
-        return -result;
-    } else {
-        return result;
+// Empty signatures are always allowed so a failing check can leave a
+// falsy result on the stack.
+fn check_signature_encoding(sig: &[u8], flags: VerifyFlags) -> Result<(), ScriptError> {
+    if sig.is_empty() {
+        return Ok(());
+    }
+    if (flags.contains(VerifyFlags::DERSIG)
+        || flags.contains(VerifyFlags::LOW_S)
+        || flags.contains(VerifyFlags::STRICTENC))
+        && !is_valid_signature_encoding(sig)
+    {
+        return Err(ScriptError::SigDer);
+    }
+    if flags.contains(VerifyFlags::LOW_S) && !is_low_der_signature(sig) {
+        return Err(ScriptError::SigHighS);
     }
+    if flags.contains(VerifyFlags::STRICTENC) && !is_defined_hashtype_signature(sig) {
+        return Err(ScriptError::SigHashtype);
+    }
+    Ok(())
+}
+
+fn check_pubkey_encoding(sec_pubkey: &[u8], flags: VerifyFlags) -> Result<(), ScriptError> {
+    let valid = match sec_pubkey.first() {
+        Some(0x04) => sec_pubkey.len() == 65,
+        Some(0x02) | Some(0x03) => sec_pubkey.len() == 33,
+        _ => false,
+    };
+    if flags.contains(VerifyFlags::STRICTENC) && !valid {
+        return Err(ScriptError::PubkeyType);
+    }
+    Ok(())
+}
+
+// BIP66 strict DER: 0x30 <len> 0x02 <lenR> <R> 0x02 <lenS> <S> <hashtype>, with
+// R and S positive and without unnecessary leading zero bytes.
+fn is_valid_signature_encoding(sig: &[u8]) -> bool {
+    if sig.len() < 9 || sig.len() > 73 {
+        return false;
+    }
+    if sig[0] != 0x30 || sig[1] as usize != sig.len() - 3 {
+        return false;
+    }
+    let len_r = sig[3] as usize;
+    if 5 + len_r >= sig.len() {
+        return false;
+    }
+    let len_s = sig[5 + len_r] as usize;
+    if len_r + len_s + 7 != sig.len() {
+        return false;
+    }
+    if sig[2] != 0x02 || len_r == 0 || sig[4] & 0x80 != 0 {
+        return false;
+    }
+    if len_r > 1 && sig[4] == 0x00 && sig[5] & 0x80 == 0 {
+        return false;
+    }
+    if sig[len_r + 4] != 0x02 || len_s == 0 || sig[len_r + 6] & 0x80 != 0 {
+        return false;
+    }
+    !(len_s > 1 && sig[len_r + 6] == 0x00 && sig[len_r + 7] & 0x80 == 0)
+}
+
+// BIP62: S must be in the lower half of the curve order.
+fn is_low_der_signature(sig: &[u8]) -> bool {
+    let mut cursor = Cursor::new(&sig[..sig.len() - 1]);
+    match Signature::parse(&mut cursor) {
+        Ok(signature) => signature.s <= N.to_owned() / 2,
+        Err(_) => false,
+    }
+}
+
+fn is_defined_hashtype_signature(sig: &[u8]) -> bool {
+    let hash_type = sig[sig.len() - 1] as u32 & !SIGHASH_ANYONECANPAY;
+    (SIGHASH_ALL..=SIGHASH_SINGLE).contains(&hash_type)
+}
+
+// Pops <dummy> <sigs...> <m> <pubkeys...> <n> and returns whether the
+// signatures match. Signatures have to appear in the same order as their
+// keys, so a single pass from the top of the stack pairs each signature with
+// the next key it verifies against.
+fn check_multisig(
+    stack: &mut Vec<Vec<u8>>,
+    checker: &dyn SignatureChecker,
+    script_code: &[u8],
+    flags: VerifyFlags,
+    op_count: &mut usize,
+) -> Result<bool, ScriptError> {
+    let mut i = 1;
+    check_stack_size(stack, i)?;
+    let require_minimal = flags.contains(VerifyFlags::MINIMALDATA);
+    let mut keys_count = ScriptNum::decode(
+        &stack[stack.len() - i],
+        require_minimal,
+        DEFAULT_MAX_NUM_SIZE,
+    )
+    .map_err(|_| ScriptError::UnknownError)?
+    .to_i32();
+    if keys_count < 0 || keys_count as usize > MAX_PUBKEYS_PER_MULTISIG {
+        return Err(ScriptError::PubkeyCount);
+    }
+    // every key counts towards the 201 opcode limit
+    *op_count += keys_count as usize;
+    if *op_count > MAX_OPS_PER_SCRIPT {
+        return Err(ScriptError::OpCount);
+    }
+    i += 1;
+    let mut ikey = i;
+    i += keys_count as usize;
+    check_stack_size(stack, i)?;
+    let sigs_start = i + 1;
+    let mut sigs_count = ScriptNum::decode(
+        &stack[stack.len() - i],
+        require_minimal,
+        DEFAULT_MAX_NUM_SIZE,
+    )
+    .map_err(|_| ScriptError::UnknownError)?
+    .to_i32();
+    if sigs_count < 0 || sigs_count > keys_count {
+        return Err(ScriptError::SigCount);
+    }
+    i += 1;
+    let mut isig = i;
+    i += sigs_count as usize;
+    check_stack_size(stack, i)?;
+    // a signature can't sign itself
+    let sigs: Vec<Vec<u8>> = (isig..i).map(|k| stack[stack.len() - k].clone()).collect();
+    let script_code = find_and_delete(script_code, &sigs, flags)?;
+
+    let mut success = true;
+    while success && sigs_count > 0 {
+        let sig = &stack[stack.len() - isig];
+        let sec_pubkey = &stack[stack.len() - ikey];
+        check_signature_encoding(sig, flags)?;
+        check_pubkey_encoding(sec_pubkey, flags)?;
+        if checker.check_sig(sig, sec_pubkey, &script_code, SigVersion::Base) {
+            isig += 1;
+            sigs_count -= 1;
+        }
+        ikey += 1;
+        keys_count -= 1;
+        // not enough keys left for the remaining signatures
+        if sigs_count > keys_count {
+            success = false;
+        }
+    }
+
+    if !success && flags.contains(VerifyFlags::NULLFAIL) {
+        let sigs = &stack[stack.len() - (i - 1)..=stack.len() - sigs_start];
+        if sigs.iter().any(|sig| !sig.is_empty()) {
+            return Err(ScriptError::SigNullFail);
+        }
+    }
+    stack.truncate(stack.len() - (i - 1));
+    // the historic off-by-one: one extra element is consumed
+    let dummy = pop(stack)?;
+    if flags.contains(VerifyFlags::NULLDUMMY) && !dummy.is_empty() {
+        return Err(ScriptError::SigNullDummy);
+    }
+    Ok(success)
+}
+
+// Core turns script number errors into SCRIPT_ERR_UNKNOWN_ERROR.
+fn pop_num(stack: &mut Vec<Vec<u8>>, flags: VerifyFlags) -> Result<ScriptNum, ScriptError> {
+    let element = pop(stack)?;
+    let require_minimal = flags.contains(VerifyFlags::MINIMALDATA);
+    ScriptNum::decode(&element, require_minimal, DEFAULT_MAX_NUM_SIZE)
+        .map_err(|_| ScriptError::UnknownError)
+}
+
+fn decode_lock_time(element: &[u8], flags: VerifyFlags) -> Result<i64, ScriptError> {
+    let require_minimal = flags.contains(VerifyFlags::MINIMALDATA);
+    ScriptNum::decode(element, require_minimal, LOCKTIME_MAX_NUM_SIZE)
+        .map(|num| num.value())
+        .map_err(|_| ScriptError::UnknownError)
+}
+
+// Any non-zero byte makes an element true, except a lone sign bit (negative zero).
+pub fn cast_to_bool(element: &[u8]) -> bool {
+    for (i, byte) in element.iter().enumerate() {
+        if *byte != 0 {
+            return !(i == element.len() - 1 && *byte == 0x80);
+        }
+    }
+    false
 }
 
 #[cfg(test)]
 mod op_tests {
-    use crate::op::decode_num;
+    use crate::error::Error;
 
-    use super::encode_num;
+    use super::{get_op_names, parse_raw_op_codes, OpCodeFunctions};
 
     #[test]
-    fn test_encode_num() {
-        assert_eq!("e703", hex::encode(encode_num(999)));
-        assert_eq!("01", hex::encode(encode_num(1)));
-        assert_eq!("02", hex::encode(encode_num(2)));
-        assert_eq!("", hex::encode(encode_num(0)));
+    fn test_parse_unknown_op_code() {
+        assert!(matches!(
+            parse_raw_op_codes(0xac),
+            Ok(OpCodeFunctions::OpChecksig(0xac))
+        ));
+        assert!(matches!(
+            parse_raw_op_codes(0x100),
+            Err(Error::UnknownOpcode(0x100))
+        ));
     }
 
     #[test]
-    fn test_decode_num() {
-        assert_eq!(1, decode_num(hex::decode("01").unwrap()));
-        assert_eq!(2, decode_num(hex::decode("02").unwrap()));
-        assert_eq!(999, decode_num(hex::decode("e703").unwrap()));
-        assert_eq!(0, decode_num(hex::decode("").unwrap()));
+    fn test_op_code_table() {
+        for byte in 0..=0xff_u32 {
+            let op = parse_raw_op_codes(byte).unwrap();
+            assert_eq!(*op.as_ref(), byte);
+        }
+        for (byte, name) in [
+            (0x00, "0"),
+            (0x01, "OP_UNKNOWN"),
+            (0x4e, "OP_PUSHDATA4"),
+            (0x4f, "-1"),
+            (0x52, "2"),
+            (0x60, "16"),
+            (0x6a, "OP_RETURN"),
+            (0x7e, "OP_CAT"),
+            (0x89, "OP_RESERVED1"),
+            (0xae, "OP_CHECKMULTISIG"),
+            (0xb1, "OP_CHECKLOCKTIMEVERIFY"),
+            (0xb2, "OP_CHECKSEQUENCEVERIFY"),
+            (0xb9, "OP_NOP10"),
+            (0xba, "OP_CHECKSIGADD"),
+            (0xbb, "OP_UNKNOWN"),
+            (0xfe, "OP_UNKNOWN"),
+            (0xff, "OP_INVALIDOPCODE"),
+        ] {
+            assert_eq!(get_op_names(&parse_raw_op_codes(byte).unwrap()), name);
+        }
+        assert!(matches!(
+            parse_raw_op_codes(0x52),
+            Ok(OpCodeFunctions::Op2(0x52))
+        ));
+        assert!(parse_raw_op_codes(0x50).unwrap().is_op_success());
+        assert!(parse_raw_op_codes(0xbb).unwrap().is_op_success());
+        assert!(!parse_raw_op_codes(0xba).unwrap().is_op_success());
+        assert!(parse_raw_op_codes(0x95).unwrap().is_disabled());
+        assert!(!parse_raw_op_codes(0x93).unwrap().is_disabled());
     }
 }
@@ -1,6 +1,7 @@
 use num_bigint::{BigInt, RandBigInt};
 
 use crate::{
+    network::Network,
     signature::{Signature, SignatureHash},
     utils, PointWrapper, S256Point, G, N,
 };
@@ -58,19 +59,14 @@ impl PrivateKey {
         Signature::new(r, s)
     }
 
-    pub fn wif(self, compressed: Option<bool>, testnet: Option<bool>) -> String {
+    pub fn wif(self, compressed: Option<bool>, network: Option<Network>) -> String {
         let secret_bytes = self.secret.to_bytes_be().1.to_vec();
         let len = secret_bytes.len();
         let to_fill = 32 - len;
         let z = vec![0x0; to_fill]; // Vec::with_capacity(to_fill);
         let final_bytes = &[z, secret_bytes].concat()[0..32];
-        let prefix: &[u8];
+        let prefix: &[u8] = &[network.unwrap_or(Network::Mainnet).wif_prefix()];
         let suffix: &[u8];
-        if testnet.unwrap_or(false) {
-            prefix = b"\xef";
-        } else {
-            prefix = b"\x80";
-        }
         if compressed.unwrap_or(true) {
             suffix = b"\x01";
         } else {
@@ -95,24 +91,27 @@ impl PrivateKey {
 mod secp256k1_private_key_tests {
     use num_bigint::BigInt;
 
-    use crate::private_key::{PrivateKey, PrivateKeySecret};
+    use crate::{
+        network::Network,
+        private_key::{PrivateKey, PrivateKeySecret},
+    };
 
     #[test]
     fn s256_private_key_wif() {
         assert_eq!(
-            PrivateKey::new(&PrivateKeySecret(BigInt::from(5003))).wif(Some(true), Some(true)),
+            PrivateKey::new(&PrivateKeySecret(BigInt::from(5003))).wif(Some(true), Some(Network::Testnet)),
             "cMahea7zqjxrtgAbB7LSGbcQUr1uX1ojuat9jZodMN8rFTv2sfUK"
         );
         assert_eq!(
             PrivateKey::new(&PrivateKeySecret(BigInt::from(2021).pow(5)))
-                .wif(Some(false), Some(true)),
+                .wif(Some(false), Some(Network::Testnet)),
             "91avARGdfge8E4tZfYLoxeJ5sGBdNJQH4kvjpWAxgzczjbCwxic"
         );
         assert_eq!(
             PrivateKey::new(&PrivateKeySecret(
                 BigInt::parse_bytes(b"54321deadbeef", 16).unwrap()
             ))
-            .wif(Some(true), Some(false)),
+            .wif(Some(true), Some(Network::Mainnet)),
             "KwDiBf89QgGbjEhKnhXJuH7LrciVrZi3qYjgiuQJv1h8Ytr2S53a"
         );
     }
@@ -3,7 +3,10 @@ use std::ops::Mul;
 use num_bigint::BigInt;
 
 use crate::{
+    bech32,
+    error::Error,
     finite_field::FiniteElement,
+    network::Network,
     s256_field::S256Field,
     signature::{Signature, SignatureHash},
     utils, PointWrapper, G, N, P,
@@ -22,45 +25,42 @@ impl S256Point {
             point: PointWrapper::new(x.field, y.field, a, b),
         }
     }
-    pub fn parse(sec_bin: &[u8]) -> Self {
-        if sec_bin[0] == 4 {
-            let x_parsed = BigInt::from_bytes_be(num_bigint::Sign::Plus, &sec_bin[1..33]);
+    pub fn parse(sec_bin: &[u8]) -> Result<Self, Error> {
+        match (sec_bin.first(), sec_bin.len()) {
+            (Some(4), 65) | (Some(2), 33) | (Some(3), 33) => {}
+            (None, _) => return Err(Error::BadPoint("empty SEC encoding")),
+            _ => return Err(Error::BadPoint("invalid SEC prefix or length")),
+        }
+        let x_parsed = BigInt::from_bytes_be(num_bigint::Sign::Plus, &sec_bin[1..33]);
+        if x_parsed >= *P {
+            return Err(Error::BadPoint("x coordinate is not a field element"));
+        }
+        let x = S256Field::new(x_parsed);
+        let b = S256Field::new(BigInt::from(7));
+        let alpha = x.field.pow(BigInt::from(3)) + b.field;
+        let y = if sec_bin[0] == 4 {
             let y_parsed = BigInt::from_bytes_be(num_bigint::Sign::Plus, &sec_bin[33..65]);
-            let x = S256Field::new(x_parsed);
-            let y = S256Field::new(y_parsed);
-            S256Point::new(x, y)
+            if y_parsed >= *P {
+                return Err(Error::BadPoint("y coordinate is not a field element"));
+            }
+            S256Field::new(y_parsed)
         } else {
             let is_even = sec_bin[0] == 2;
-            let x_parsed = BigInt::from_bytes_be(num_bigint::Sign::Plus, &sec_bin[1..]);
-            let x = S256Field::new(x_parsed);
-            let b = S256Field::new(BigInt::from(7));
-            let alpha = x.field.pow(BigInt::from(3)) + b.field;
-            let alpha = S256Field { field: alpha };
-            let beta = alpha.sqrt();
-            if beta
-                .clone()
-                .field
-                .num
-                .modpow(&BigInt::from(1), &BigInt::from(2))
-                == BigInt::from(0)
-            {
-                let even_beta = beta.clone();
-                let odd_beta = S256Field::new(P.to_owned() - beta.clone().field.num);
-                if is_even {
-                    S256Point::new(x, even_beta)
-                } else {
-                    S256Point::new(x, odd_beta)
-                }
+            let beta = S256Field {
+                field: alpha.clone(),
+            }
+            .sqrt();
+            if (beta.field.num.clone() % 2 == BigInt::from(0)) == is_even {
+                beta
             } else {
-                let even_beta = S256Field::new(P.to_owned() - beta.clone().field.num);
-                let odd_beta = beta.clone();
-                if is_even {
-                    S256Point::new(x, even_beta)
-                } else {
-                    S256Point::new(x, odd_beta)
-                }
+                S256Field::new(P.to_owned() - beta.field.num)
             }
+        };
+        // the compressed form has no square root when x is off the curve
+        if y.field.pow(BigInt::from(2)) != alpha {
+            return Err(Error::BadPoint("point is not on the curve"));
         }
+        Ok(S256Point::new(x, y))
     }
 
     pub fn verify(&self, z: &SignatureHash, sig: Signature) -> bool {
@@ -87,17 +87,17 @@ impl S256Point {
                 if compressed.unwrap_or(true) {
                     if y.num.modpow(&BigInt::from(1), &BigInt::from(2)) == BigInt::from(0) {
                         let marker = &b"\x02"[0..1];
-                        let x = &x.num.to_bytes_be().1.to_vec()[0..32];
+                        let x = &utils::int_to_big_endian(&x.num, 32)[..];
                         [marker, x].concat()
                     } else {
                         let marker = &b"\x03"[0..1];
-                        let x = &x.num.to_bytes_be().1.to_vec()[0..32];
+                        let x = &utils::int_to_big_endian(&x.num, 32)[..];
                         [marker, x].concat()
                     }
                 } else {
                     let marker = &b"\x04"[0..1];
-                    let x = &x.num.to_bytes_be().1.to_vec()[0..32];
-                    let y = &y.num.to_bytes_be().1.to_vec()[0..32];
+                    let x = &utils::int_to_big_endian(&x.num, 32)[..];
+                    let y = &utils::int_to_big_endian(&y.num, 32)[..];
                     let res = [marker, x, y];
                     let res = res.concat();
                     res
@@ -111,18 +111,57 @@ impl S256Point {
         utils::hash160(&a)
     }
 
-    pub fn address(self, compressed: Option<bool>, testnet: Option<bool>) -> String {
-        let h160 = self.hash160(compressed);
-        if testnet.unwrap_or(false) {
-            let prefix = &b"\x6f"[0..1];
-            let ad = &[prefix, &h160].concat()[..];
-            utils::encode_base58_checksum(ad)
-        } else {
-            let prefix = &b"\x00"[0..1];
-            let ad = &[prefix, &h160].concat()[..];
-            utils::encode_base58_checksum(ad)
+    pub fn xonly(&self) -> Vec<u8> {
+        match &self.point {
+            PointWrapper::Inf => panic!("Public point can not be point to infinity"),
+            PointWrapper::Point { x, .. } => utils::int_to_big_endian(&x.num, 32),
+        }
+    }
+
+    // BIP86 output key: the internal key is lifted to even y and tweaked with an empty script tree.
+    pub fn taproot_output_key(&self) -> S256Point {
+        let internal = match &self.point {
+            PointWrapper::Inf => panic!("Public point can not be point to infinity"),
+            PointWrapper::Point { x, y, .. } => {
+                if y.num.modpow(&BigInt::from(1), &BigInt::from(2)) == BigInt::from(0) {
+                    self.clone()
+                } else {
+                    S256Point::new(
+                        S256Field::new(x.num.clone()),
+                        S256Field::new(P.to_owned() - y.num.clone()),
+                    )
+                }
+            }
+        };
+        let tweak = BigInt::from_bytes_be(
+            num_bigint::Sign::Plus,
+            &utils::tagged_hash("TapTweak", &internal.xonly()),
+        );
+        S256Point {
+            point: tweak * &G.to_owned() + internal.point,
         }
     }
+
+    pub fn p2sh_p2wpkh_address(self, network: Network) -> String {
+        let redeem_script = utils::p2wpkh_script(self.hash160(Some(true)));
+        let h160 = utils::hash160(&redeem_script.raw_serialize());
+        utils::encode_base58_checksum(&[&[network.p2sh_prefix()], &h160[..]].concat())
+    }
+
+    pub fn p2wpkh_address(self, network: Network) -> String {
+        bech32::encode_segwit_address(network.bech32_hrp(), 0, &self.hash160(Some(true))).unwrap()
+    }
+
+    pub fn p2tr_address(self, network: Network) -> String {
+        bech32::encode_segwit_address(network.bech32_hrp(), 1, &self.taproot_output_key().xonly())
+            .unwrap()
+    }
+
+    pub fn address(self, compressed: Option<bool>, network: Option<Network>) -> String {
+        let h160 = self.hash160(compressed);
+        let prefix = network.unwrap_or(Network::Mainnet).p2pkh_prefix();
+        utils::encode_base58_checksum(&[&[prefix], &h160[..]].concat())
+    }
 }
 
 impl Mul<&S256Point> for BigInt {
@@ -150,7 +189,8 @@ mod secp256k1_point_tests {
     use num_bigint::BigInt;
 
     use crate::{
-        private_key::PrivateKey, signature::Signature, PointWrapper, S256Field, S256Point, G, N,
+        error::Error, network::Network, private_key::PrivateKey, signature::Signature,
+        PointWrapper, S256Field, S256Point, G, N,
     };
 
     #[test]
@@ -267,7 +307,7 @@ mod secp256k1_point_tests {
             PrivateKey::new(&PrivateKey::generate_simple_secret(BigInt::from(5000)))
                 .point
                 .point,
-            S256Point::parse(&p_uncompressed_bytes).point
+            S256Point::parse(&p_uncompressed_bytes).unwrap().point
         );
         let p_compressed_bytes =
             hex!("0357a4f368868a8a6d572991e484e664810ff14c05c0fa023275251151fe0e53d1");
@@ -275,16 +315,30 @@ mod secp256k1_point_tests {
             PrivateKey::new(&PrivateKey::generate_simple_secret(BigInt::from(5001)))
                 .point
                 .point,
-            S256Point::parse(&p_compressed_bytes).point
+            S256Point::parse(&p_compressed_bytes).unwrap().point
         );
     }
 
+    #[test]
+    fn test_s256point_parse_bad_sec() {
+        for sec in [
+            &b""[..],
+            &hex!("0557a4f368868a8a6d572991e484e664810ff14c05c0fa023275251151fe0e53d1")[..],
+            &hex!("0357a4f368868a8a6d572991e484e664810ff14c05c0fa023275251151fe0e53")[..],
+            // x = 5 has no point on the curve
+            &hex!("020000000000000000000000000000000000000000000000000000000000000005")[..],
+            &hex!("04ffe558e388852f0120e46af2d1b370f85854a8eb0841811ece0e3e03d282d57c315dc72890a4f10a1481c031b03b351b0dc79901ca18a00cf009dbdb157a1d11")[..],
+        ] {
+            assert!(matches!(S256Point::parse(sec), Err(Error::BadPoint(_))));
+        }
+    }
+
     #[test]
     fn test_256point_address() {
         assert_eq!(
             PrivateKey::new(&PrivateKey::generate_simple_secret(BigInt::from(5002)))
                 .point
-                .address(Some(false), Some(true)),
+                .address(Some(false), Some(Network::Testnet)),
             "mmTPbXQFxboEtNRkwfh6K51jvdtHLxGeMA"
         );
         assert_eq!(
@@ -292,7 +346,7 @@ mod secp256k1_point_tests {
                 BigInt::from(2020).pow(5)
             ))
             .point
-            .address(Some(true), Some(true)),
+            .address(Some(true), Some(Network::Testnet)),
             "mopVkxp8UhXqRYbCYJsbeE1h1fiF64jcoH"
         );
         assert_eq!(
@@ -300,14 +354,14 @@ mod secp256k1_point_tests {
                 BigInt::parse_bytes(b"12345deadbeef", 16).unwrap()
             ))
             .point
-            .address(Some(true), Some(false)),
+            .address(Some(true), Some(Network::Mainnet)),
             "1F1Pn2y6pDb68E5nYJJeba4TLg2U7B6KF1"
         );
         let passphrase = "jimmy@programmingblockchain.com my secret";
         let priva = PrivateKey::new(&PrivateKey::generate_secret(passphrase));
         assert_eq!(
             "mft9LRNtaBNtpkknB8xgm17UvPedZ4ecYL",
-            priva.point.address(Some(true), Some(true))
+            priva.point.address(Some(true), Some(Network::Testnet))
         )
     }
 }
@@ -1,140 +1,397 @@
-use core::panic;
-use std::{fmt::Display, io::Read, ops::Add, vec};
+use std::{
+    fmt::Display,
+    io::Read,
+    ops::{Add, BitOr},
+};
 
-use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use byteorder::{ByteOrder, LittleEndian};
 
 use crate::{
+    bech32,
+    error::Error,
+    interpreter::Interpreter,
+    network::Network,
     op::{self, OpCodeFunctions},
+    script_builder::ScriptBuilder,
+    script_error::ScriptError,
+    script_num::{ScriptNum, DEFAULT_MAX_NUM_SIZE},
     signature::SignatureHash,
+    signature_checker::{NoSignatureChecker, SignatureChecker, SignatureHashChecker},
+    tx::Tx,
     utils,
 };
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Command {
     Element(Vec<u8>),
     Operation(OpCodeFunctions),
 }
 
-#[derive(Debug, Clone)]
+// Kept as the bytes it was parsed from, like Bitcoin Core's CScript, so it
+// serializes back unchanged and a push running past the end only fails once
+// the script is executed.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
 pub struct Script {
-    pub cmds: Vec<Command>,
+    raw: Vec<u8>,
+}
+
+// A decoded command with the opcode it was written with and its byte range
+// in the script.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instruction {
+    pub command: Command,
+    pub op_code: u8,
+    pub start: usize,
+    pub end: usize,
+}
+
+// Decodes the instructions one at a time like Core's GetOp, an undecodable
+// push ends the iteration with an error.
+pub struct Instructions<'a> {
+    raw: &'a [u8],
+    pos: usize,
+}
+
+impl Iterator for Instructions<'_> {
+    type Item = Result<Instruction, ScriptError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let result = decode_instruction(self.raw, self.pos)?;
+        self.pos = match &result {
+            Ok(instruction) => instruction.end,
+            Err(_) => self.raw.len(),
+        };
+        Some(result)
+    }
 }
 
 impl Script {
+    // Encodes the commands, elements keep the smallest push opcode for their
+    // length even when OP_0 or OP_1..OP_16 could push the same value.
     pub fn new(cmds: Option<Vec<Command>>) -> Self {
-        match cmds {
-            None => Script { cmds: Vec::new() },
-            Some(cmds) => Script { cmds },
-        }
-    }
-
-    pub fn parse<R: Read>(stream: &mut R) -> Self {
-        let length = utils::read_varint(stream);
-        let mut cmds: Vec<Command> = Vec::new();
-        let mut count = 0_u32;
-        let length_buf = length.to_signed_bytes_be();
-        let length = BigEndian::read_int(&length_buf, length_buf.len()) as u32;
-        while count < length {
-            let mut current = vec![0; 1];
-            stream.read_exact(&mut current).unwrap();
-            count += 1;
-            let current_byte = BigEndian::read_int(&current, 1) as u32;
-            if current_byte >= 1 && current_byte <= 75 {
-                let mut temp = vec![0; current_byte.try_into().unwrap()];
-                stream.read_exact(&mut temp).unwrap();
-                let elem = Command::Element(temp);
-                cmds.push(elem);
-                count += current_byte;
-            } else if current_byte == 76 {
-                let mut temp = vec![0; 1];
-                stream.read_exact(&mut temp).unwrap();
-                let data_length = LittleEndian::read_int(&temp, temp.len()) as u32;
-                let mut temp = vec![0; data_length.try_into().unwrap()];
-                stream.read_exact(&mut temp).unwrap();
-                let elem = Command::Element(temp);
-                cmds.push(elem);
-                count += data_length + 1;
-            } else if current_byte == 77 {
-                let mut temp = vec![0; 2];
-                stream.read_exact(&mut temp).unwrap();
-                let data_length = LittleEndian::read_int(&temp, temp.len()) as u32;
-                let mut temp = vec![0; data_length.clone().try_into().unwrap()];
-                stream.read_exact(&mut temp).unwrap();
-                let elem = Command::Element(temp);
-                cmds.push(elem);
-                count += data_length + 2;
-            } else {
-                let mut num = vec![0, 0, 0];
-                num.append(&mut current.clone());
-                let op_code = op::parse_raw_op_codes(BigEndian::read_u32(&num));
-                let op = Command::Operation(op_code);
-                cmds.push(op);
+        let mut raw = Vec::new();
+        for cmd in cmds.unwrap_or_default() {
+            match cmd {
+                Command::Element(element) => encode_push(&mut raw, &element),
+                Command::Operation(op) => raw.push(*op.as_ref() as u8),
             }
         }
-        if count != length {
-            panic!("parsing script failed")
+        Script { raw }
+    }
+
+    pub fn from_bytes(raw: Vec<u8>) -> Self {
+        Script { raw }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.raw
+    }
+
+    pub fn parse<R: Read>(stream: &mut R) -> Result<Self, Error> {
+        let length = utils::read_varint(stream)?;
+        // read through take so a bogus length can't allocate the memory up front
+        let mut raw = Vec::new();
+        stream.take(length).read_to_end(&mut raw)?;
+        if raw.len() as u64 != length {
+            return Err(Error::Truncated);
+        }
+        Ok(Script { raw })
+    }
+
+    pub fn instructions(&self) -> Instructions<'_> {
+        Instructions {
+            raw: &self.raw,
+            pos: 0,
         }
+    }
 
-        Script { cmds }
+    pub fn cmds(&self) -> Result<Vec<Command>, ScriptError> {
+        self.instructions()
+            .map(|instruction| instruction.map(|instruction| instruction.command))
+            .collect()
+    }
+
+    // Parses Bitcoin Core style ASM, the format Display prints:
+    //
+    //     OP_DUP OP_HASH160 <hex> OP_EQUALVERIFY OP_CHECKSIG
+    //
+    // Opcodes may be written with or without the OP_ prefix, decimal numbers
+    // and hex data become minimal pushes, 'text' pushes its bytes and 0x<hex>
+    // is copied into the script as is, like in Core's script_tests.json.
+    pub fn from_asm(asm: &str) -> Result<Self, Error> {
+        let mut raw = Vec::new();
+        for token in asm.split_whitespace() {
+            raw.extend(asm_token_bytes(token)?);
+        }
+        Ok(Script { raw })
     }
 
     pub fn evaluate(self, z: SignatureHash) -> bool {
-        let mut cmds_copy = self.cmds.clone();
+        self.evaluate_with_flags(z, VerifyFlags::NONE)
+    }
+
+    // Checks every signature against z, whatever its hashtype.
+    pub fn evaluate_with_flags(self, z: SignatureHash, flags: VerifyFlags) -> bool {
         let mut stack: Vec<Vec<u8>> = Vec::new();
-        let mut altstack: Vec<Vec<u8>> = Vec::new();
-        while cmds_copy.len() > 0 {
-            let cmd = cmds_copy.remove(0);
-            match cmd {
-                Command::Element(elem) => stack.push(elem.to_vec()),
-                Command::Operation(op_code) => {
-                    let result = op::operation(
-                        op_code.clone(),
-                        &mut stack,
-                        &mut cmds_copy,
-                        &mut altstack,
-                        &z,
-                    );
-                    if !result {
-                        panic!("bad op")
-                    }
+        let checker = SignatureHashChecker::new(z);
+        if self.execute(&mut stack, &checker, flags).is_err() {
+            return false;
+        }
+        match stack.pop() {
+            Some(element) => op::cast_to_bool(&element),
+            None => false,
+        }
+    }
+
+    // Runs scriptSig and scriptPubKey separately like Bitcoin Core's
+    // VerifyScript, then the redeem script for BIP16 pay-to-script-hash.
+    pub fn verify_script(
+        script_sig: &Script,
+        script_pubkey: &Script,
+        checker: &dyn SignatureChecker,
+        flags: VerifyFlags,
+    ) -> Result<(), ScriptError> {
+        if flags.contains(VerifyFlags::SIGPUSHONLY) && !script_sig.is_push_only() {
+            return Err(ScriptError::SigPushOnly);
+        }
+        let mut stack: Vec<Vec<u8>> = Vec::new();
+        script_sig.execute(&mut stack, checker, flags)?;
+        let mut stack_copy = stack.clone();
+        script_pubkey.execute(&mut stack, checker, flags)?;
+        match stack.last() {
+            Some(element) if op::cast_to_bool(element) => {}
+            _ => return Err(ScriptError::EvalFalse),
+        }
+        // a witness program leaves its version and program on the stack and
+        // would pass as anyone-can-spend, so refuse it until the witness runs
+        if flags.contains(VerifyFlags::WITNESS) && script_pubkey.witness_program().is_some() {
+            if !script_sig.raw.is_empty() {
+                return Err(ScriptError::WitnessMalleated);
+            }
+            return Err(ScriptError::WitnessUnsupported);
+        }
+
+        if flags.contains(VerifyFlags::P2SH) && script_pubkey.is_p2sh() {
+            if !script_sig.is_push_only() {
+                return Err(ScriptError::SigPushOnly);
+            }
+            // the scriptPubKey already checked that the top of the stack the
+            // scriptSig left hashes to it, OP_0 and OP_N included
+            std::mem::swap(&mut stack, &mut stack_copy);
+            let redeem_script = Script::from_bytes(stack.pop().ok_or(ScriptError::EvalFalse)?);
+            redeem_script.execute(&mut stack, checker, flags)?;
+            match stack.last() {
+                Some(element) if op::cast_to_bool(element) => {}
+                _ => return Err(ScriptError::EvalFalse),
+            }
+            // P2SH-wrapped witness programs, the scriptSig may only push the
+            // redeem script
+            if flags.contains(VerifyFlags::WITNESS) && redeem_script.witness_program().is_some() {
+                let push = Script::new(Some(vec![Command::Element(redeem_script.raw.clone())]));
+                if script_sig.raw != push.raw {
+                    return Err(ScriptError::WitnessMalleatedP2sh);
                 }
+                return Err(ScriptError::WitnessUnsupported);
             }
         }
-        if stack.len() == 0 {
-            return false;
+
+        // only meaningful together with P2SH, a redeem script could not
+        // leave a single element otherwise
+        if flags.contains(VerifyFlags::CLEANSTACK) && stack.len() != 1 {
+            return Err(ScriptError::CleanStack);
         }
-        if stack.pop().unwrap() == b"" {
-            return false;
+        Ok(())
+    }
+
+    // Executes the script on top of an existing stack, enforcing the
+    // consensus limits on script size, element size, op count and stack size.
+    pub fn execute(
+        &self,
+        stack: &mut Vec<Vec<u8>>,
+        checker: &dyn SignatureChecker,
+        flags: VerifyFlags,
+    ) -> Result<(), ScriptError> {
+        let mut interpreter = Interpreter::new(self, std::mem::take(stack), checker, flags);
+        let result = interpreter.run();
+        *stack = interpreter.into_stack();
+        result
+    }
+
+    pub(crate) fn serialized_len(&self) -> usize {
+        self.raw.len()
+    }
+
+    // None when a push runs past the end of the script.
+    fn decoded(&self) -> Option<Vec<Instruction>> {
+        self.instructions().collect::<Result<_, _>>().ok()
+    }
+
+    // The bytes a push instruction put on the stack, borrowed from the script.
+    fn pushed(&self, instruction: &Instruction) -> Option<&[u8]> {
+        match &instruction.command {
+            Command::Element(data) => {
+                Some(&self.raw[instruction.end - data.len()..instruction.end])
+            }
+            Command::Operation(_) => None,
         }
+    }
 
-        return true;
+    // OP_HASH160 <20 bytes> OP_EQUAL with the hash pushed directly. Like
+    // Core's IsPayToScriptHash only these exact 23 bytes trigger BIP16.
+    pub fn is_p2sh(&self) -> bool {
+        self.raw.len() == 23 && self.raw[0] == 0xa9 && self.raw[1] == 0x14 && self.raw[22] == 0x87
     }
 
-    fn raw_serialize(&self) -> Vec<u8> {
-        let mut result: Vec<u8> = Vec::new();
-        for cmd in &self.cmds {
-            match cmd {
-                Command::Element(element) => {
-                    let length = element.len();
-                    if length < 75 {
-                        result.append(&mut utils::usize_to_little_endian(length, 1))
-                    } else if length > 75 && length < 0x100 {
-                        result.append(&mut utils::usize_to_little_endian(76, 1));
-                        result.append(&mut utils::usize_to_little_endian(length, 1))
-                    } else if length >= 0x100 && length <= 520 {
-                        result.append(&mut utils::usize_to_little_endian(77, 1));
-                        result.append(&mut utils::usize_to_little_endian(length, 2))
-                    } else {
-                        panic!("too long an cmd")
-                    }
-                    result.append(&mut element.clone())
-                }
-                Command::Operation(op) => {
-                    result.append(&mut utils::u32_to_little_endian(op.as_ref().clone(), 1))
-                }
+    // Checks the templates in the order of Bitcoin Core's Solver and, like
+    // Core, matches P2SH, P2PK, P2PKH and witness programs on their exact
+    // bytes, so a push written with a longer opcode is nonstandard. Core's
+    // pay-to-anchor type isn't told apart and shows up as WitnessUnknown.
+    pub fn script_type(&self) -> ScriptType {
+        if self.is_p2sh() {
+            return ScriptType::P2sh;
+        }
+        if let Some((version, program)) = self.witness_program() {
+            return match (version, program.len()) {
+                (0, 20) => ScriptType::P2wpkh,
+                (0, 32) => ScriptType::P2wsh,
+                (0, _) => ScriptType::NonStandard,
+                (1, 32) => ScriptType::P2tr,
+                _ => ScriptType::WitnessUnknown,
             };
         }
-        result
+        if self.is_null_data() {
+            return ScriptType::NullData;
+        }
+        if self.pubkey().is_some() {
+            return ScriptType::P2pk;
+        }
+        if self.is_p2pkh() {
+            return ScriptType::P2pkh;
+        }
+        if self.multisig().is_some() {
+            return ScriptType::Multisig;
+        }
+        ScriptType::NonStandard
+    }
+
+    // OP_DUP OP_HASH160 <20 bytes> OP_EQUALVERIFY OP_CHECKSIG, the 25 bytes
+    // Core's MatchPayToPubkeyHash accepts.
+    pub fn is_p2pkh(&self) -> bool {
+        matches!(
+            self.raw.as_slice(),
+            [0x76, 0xa9, 0x14, .., 0x88, 0xac] if self.raw.len() == 25
+        )
+    }
+
+    pub fn is_p2wpkh(&self) -> bool {
+        self.script_type() == ScriptType::P2wpkh
+    }
+
+    pub fn is_p2wsh(&self) -> bool {
+        self.script_type() == ScriptType::P2wsh
+    }
+
+    pub fn is_p2tr(&self) -> bool {
+        self.script_type() == ScriptType::P2tr
+    }
+
+    // OP_RETURN followed by nothing but pushes.
+    pub fn is_null_data(&self) -> bool {
+        match self.raw.split_first() {
+            Some((0x6a, rest)) => Script::from_bytes(rest.to_vec()).is_push_only(),
+            _ => false,
+        }
+    }
+
+    // The SEC key of <key> OP_CHECKSIG, with the key pushed directly like
+    // Core's MatchPayToPubkey expects.
+    pub fn pubkey(&self) -> Option<&[u8]> {
+        match self.raw.as_slice() {
+            [length @ (33 | 65), key @ .., 0xac] if key.len() == *length as usize => {
+                Some(key).filter(|key| is_valid_pubkey_size(key))
+            }
+            _ => None,
+        }
+    }
+
+    // The key hash of a P2PKH or P2WPKH script.
+    pub fn pubkey_hash(&self) -> Option<&[u8]> {
+        match self.script_type() {
+            ScriptType::P2pkh => Some(&self.raw[3..23]),
+            ScriptType::P2wpkh => self.witness_program().map(|(_, program)| program),
+            _ => None,
+        }
+    }
+
+    // The hash160 of the redeem script of a P2SH script, or the sha256 of the
+    // witness script of a P2WSH script.
+    pub fn script_hash(&self) -> Option<&[u8]> {
+        match self.script_type() {
+            ScriptType::P2sh => Some(&self.raw[2..22]),
+            ScriptType::P2wsh => self.witness_program().map(|(_, program)| program),
+            _ => None,
+        }
+    }
+
+    // Version and program of a BIP141 witness program: a version opcode
+    // followed by a direct push of 2 to 40 bytes, as in Core's
+    // IsWitnessProgram.
+    pub fn witness_program(&self) -> Option<(u8, &[u8])> {
+        if !(4..=42).contains(&self.raw.len()) || self.raw[1] as usize + 2 != self.raw.len() {
+            return None;
+        }
+        let program = &self.raw[2..];
+        match self.raw[0] {
+            0x00 => Some((0, program)),
+            byte @ 0x51..=0x60 => Some((byte - 0x50, program)),
+            _ => None,
+        }
+    }
+
+    // The required signature count and the keys of a bare
+    // OP_m <keys> OP_n OP_CHECKMULTISIG script.
+    pub fn multisig(&self) -> Option<(usize, Vec<&[u8]>)> {
+        let decoded = self.decoded()?;
+        let (first, rest) = decoded.split_first()?;
+        let (last, rest) = rest.split_last()?;
+        let (n_op, keys) = rest.split_last()?;
+        if last.op_code != 0xae {
+            return None;
+        }
+        let m = small_int(first.op_code)?;
+        let n = small_int(n_op.op_code)?;
+        let keys = keys
+            .iter()
+            .map(|key| self.pushed(key).filter(|key| is_valid_pubkey_size(key)))
+            .collect::<Option<Vec<&[u8]>>>()?;
+        if m < 1 || m > n || keys.len() != n {
+            return None;
+        }
+        Some((m, keys))
+    }
+
+    // Only data pushes and the small integer opcodes up to OP_16, an
+    // undecodable push is not push only.
+    pub fn is_push_only(&self) -> bool {
+        self.instructions().all(
+            |instruction| matches!(instruction, Ok(instruction) if instruction.op_code <= 0x60),
+        )
+    }
+
+    // What a push-only P2SH scriptSig leaves on top of the stack, which is
+    // what BIP16 runs, also when it was pushed with OP_0 or OP_N.
+    pub fn redeem_script(&self) -> Option<Script> {
+        if !self.is_push_only() {
+            return None;
+        }
+        let mut stack = Vec::new();
+        self.execute(&mut stack, &NoSignatureChecker, VerifyFlags::NONE)
+            .ok()?;
+        stack.pop().map(Script::from_bytes)
+    }
+
+    pub fn raw_serialize(&self) -> Vec<u8> {
+        self.raw.clone()
     }
 
     pub fn serialize(&self) -> Vec<u8> {
@@ -142,22 +399,426 @@ impl Script {
         let total = result.len();
         [utils::encode_varint(total), result].concat()
     }
+
+    // Address paying to this script as a P2WSH witness script.
+    pub fn p2wsh_address(&self, network: Network) -> String {
+        let program = utils::sha256(&self.raw_serialize());
+        bech32::encode_segwit_address(network.bech32_hrp(), 0, &program).unwrap()
+    }
+}
+
+// OP_1..OP_16, as used for the counts of a multisig script.
+fn small_int(op_code: u8) -> Option<usize> {
+    match op_code {
+        0x51..=0x60 => Some((op_code - 0x50) as usize),
+        _ => None,
+    }
+}
+
+// Decodes the instruction at pos, None at the end of the script.
+pub(crate) fn decode_instruction(
+    raw: &[u8],
+    pos: usize,
+) -> Option<Result<Instruction, ScriptError>> {
+    let op_code = *raw.get(pos)?;
+    let mut end = pos + 1;
+    let data_length = match op_code {
+        0x01..=0x4b => op_code as usize,
+        0x4c..=0x4e => {
+            let width = [1, 2, 4][(op_code - 0x4c) as usize];
+            if raw.len() - end < width {
+                return Some(Err(ScriptError::BadOpcode));
+            }
+            end += width;
+            LittleEndian::read_uint(&raw[end - width..end], width) as usize
+        }
+        _ => {
+            // every other byte is an opcode
+            let op = op::parse_raw_op_codes(op_code as u32).unwrap();
+            return Some(Ok(Instruction {
+                command: Command::Operation(op),
+                op_code,
+                start: pos,
+                end,
+            }));
+        }
+    };
+    if raw.len() - end < data_length {
+        return Some(Err(ScriptError::BadOpcode));
+    }
+    Some(Ok(Instruction {
+        command: Command::Element(raw[end..end + data_length].to_vec()),
+        op_code,
+        start: pos,
+        end: end + data_length,
+    }))
+}
+
+// Pushes data with the smallest push opcode for its length.
+fn encode_push(raw: &mut Vec<u8>, element: &[u8]) {
+    let length = element.len();
+    match length {
+        0..=0x4b => raw.push(length as u8),
+        0x4c..=0xff => {
+            raw.push(0x4c);
+            raw.push(length as u8);
+        }
+        0x100..=0xffff => {
+            raw.push(0x4d);
+            raw.append(&mut utils::usize_to_little_endian(length, 2));
+        }
+        _ => {
+            raw.push(0x4e);
+            raw.append(&mut utils::usize_to_little_endian(length, 4));
+        }
+    }
+    raw.extend_from_slice(element)
+}
+
+// Length matching the SEC prefix, like Core's CPubKey::ValidSize.
+fn is_valid_pubkey_size(key: &[u8]) -> bool {
+    matches!(
+        (key.first(), key.len()),
+        (Some(2 | 3), 33) | (Some(4 | 6 | 7), 65)
+    )
+}
+
+// Output script templates, named as in Bitcoin Core's GetTxnOutputType.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScriptType {
+    P2pk,
+    P2pkh,
+    P2sh,
+    P2wpkh,
+    P2wsh,
+    P2tr,
+    Multisig,
+    NullData,
+    WitnessUnknown,
+    NonStandard,
+}
+
+impl Display for ScriptType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ScriptType::P2pk => "pubkey",
+            ScriptType::P2pkh => "pubkeyhash",
+            ScriptType::P2sh => "scripthash",
+            ScriptType::P2wpkh => "witness_v0_keyhash",
+            ScriptType::P2wsh => "witness_v0_scripthash",
+            ScriptType::P2tr => "witness_v1_taproot",
+            ScriptType::Multisig => "multisig",
+            ScriptType::NullData => "nulldata",
+            ScriptType::WitnessUnknown => "witness_unknown",
+            ScriptType::NonStandard => "nonstandard",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// How Display writes an instruction. Like Core's ScriptToAsmStr short pushes
+// read as numbers and longer ones as hex, but only where from_asm gives back
+// the same bytes: a push that isn't minimal, hex that is all digits and
+// unknown opcodes are written as 0x<bytes>.
+fn asm_token(command: &Command, bytes: &[u8]) -> String {
+    let reads_back = |token: &str| asm_token_bytes(token).ok().as_deref() == Some(bytes);
+    let token = match command {
+        Command::Element(element) if element.len() <= DEFAULT_MAX_NUM_SIZE => {
+            let num = ScriptNum::decode(element, false, DEFAULT_MAX_NUM_SIZE).unwrap();
+            num.value().to_string()
+        }
+        Command::Element(element) => hex::encode(element),
+        Command::Operation(op) => op::get_op_names(op).to_string(),
+    };
+    if reads_back(&token) {
+        return token;
+    }
+    match command {
+        Command::Element(element) if reads_back(&hex::encode(element)) => hex::encode(element),
+        _ => format!("0x{}", hex::encode(bytes)),
+    }
+}
+
+// Decimal numbers, which Core's ParseScript limits to -0xffffffff..0xffffffff.
+// A leading zero makes the token hex, like the longer pushes Display prints.
+fn is_asm_number(token: &str) -> bool {
+    let digits = token.strip_prefix('-').unwrap_or(token);
+    !digits.is_empty()
+        && digits.bytes().all(|b| b.is_ascii_digit())
+        && !(digits.len() > 1 && digits.starts_with('0'))
+}
+
+fn parse_asm_op_code(token: &str) -> Option<OpCodeFunctions> {
+    let name = match token {
+        "OP_FALSE" | "OP_0" | "FALSE" => "0",
+        "OP_TRUE" | "TRUE" => "1",
+        "OP_1NEGATE" | "1NEGATE" => "-1",
+        "OP_NOP2" | "NOP2" => "OP_CHECKLOCKTIMEVERIFY",
+        "OP_NOP3" | "NOP3" => "OP_CHECKSEQUENCEVERIFY",
+        _ => match token.strip_prefix("OP_") {
+            Some(num) if is_asm_number(num) => num,
+            _ => token,
+        },
+    };
+    op::op_code_from_name(name).or_else(|| op::op_code_from_name(&format!("OP_{}", name)))
+}
+
+// The bytes a single ASM token stands for.
+fn asm_token_bytes(token: &str) -> Result<Vec<u8>, Error> {
+    let mut raw = Vec::new();
+    if is_asm_number(token) {
+        match token.parse::<i64>() {
+            Ok(num) if num.abs() <= 0xffffffff => {
+                push_data(&mut raw, &ScriptNum::new(num).encode())
+            }
+            _ => return Err(Error::BadAsm(token.to_string())),
+        }
+    } else if let Some(hex) = token.strip_prefix("0x") {
+        match hex::decode(hex) {
+            Ok(bytes) if !bytes.is_empty() => raw.extend(bytes),
+            _ => return Err(Error::BadAsm(token.to_string())),
+        }
+    } else if token.len() >= 2 && token.starts_with('\'') && token.ends_with('\'') {
+        push_data(&mut raw, &token.as_bytes()[1..token.len() - 1]);
+    } else if let Some(op_code) = parse_asm_op_code(token) {
+        raw.push(*op_code.as_ref() as u8);
+    } else {
+        match hex::decode(token) {
+            Ok(data) => push_data(&mut raw, &data),
+            Err(_) => return Err(Error::BadAsm(token.to_string())),
+        }
+    }
+    Ok(raw)
+}
+
+fn push_data(raw: &mut Vec<u8>, data: &[u8]) {
+    raw.extend(
+        ScriptBuilder::new()
+            .push_slice(data)
+            .into_script()
+            .raw_serialize(),
+    );
+}
+
+// Consensus limits from Bitcoin Core's script.h.
+pub const MAX_SCRIPT_ELEMENT_SIZE: usize = 520;
+pub const MAX_OPS_PER_SCRIPT: usize = 201;
+pub const MAX_PUBKEYS_PER_MULTISIG: usize = 20;
+pub const MAX_SCRIPT_SIZE: usize = 10_000;
+// Combined size of the stack and the altstack.
+pub const MAX_STACK_SIZE: usize = 1000;
+
+// Script verification flags, bit values follow Bitcoin Core's SCRIPT_VERIFY_*.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct VerifyFlags(u32);
+
+impl VerifyFlags {
+    pub const NONE: VerifyFlags = VerifyFlags(0);
+    // BIP16: evaluate the redeem script of pay-to-script-hash outputs
+    pub const P2SH: VerifyFlags = VerifyFlags(1 << 0);
+    // strict signature and public key encodings, defined hash types
+    pub const STRICTENC: VerifyFlags = VerifyFlags(1 << 1);
+    // BIP66: strict DER signatures
+    pub const DERSIG: VerifyFlags = VerifyFlags(1 << 2);
+    // BIP62: S in the lower half of the curve order
+    pub const LOW_S: VerifyFlags = VerifyFlags(1 << 3);
+    // BIP147: the extra element consumed by OP_CHECKMULTISIG must be empty
+    pub const NULLDUMMY: VerifyFlags = VerifyFlags(1 << 4);
+    // scriptSig may only contain pushes
+    pub const SIGPUSHONLY: VerifyFlags = VerifyFlags(1 << 5);
+    // pushes and script numbers must be minimally encoded
+    pub const MINIMALDATA: VerifyFlags = VerifyFlags(1 << 6);
+    pub const DISCOURAGE_UPGRADABLE_NOPS: VerifyFlags = VerifyFlags(1 << 7);
+    // exactly one element must be left on the stack
+    pub const CLEANSTACK: VerifyFlags = VerifyFlags(1 << 8);
+    // BIP65 and BIP112, without them both opcodes behave as OP_NOP2/OP_NOP3
+    pub const CHECKLOCKTIMEVERIFY: VerifyFlags = VerifyFlags(1 << 9);
+    pub const CHECKSEQUENCEVERIFY: VerifyFlags = VerifyFlags(1 << 10);
+    // BIP141 segregated witness
+    pub const WITNESS: VerifyFlags = VerifyFlags(1 << 11);
+    pub const DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM: VerifyFlags = VerifyFlags(1 << 12);
+    pub const MINIMALIF: VerifyFlags = VerifyFlags(1 << 13);
+    // failed signature checks must use empty signatures
+    pub const NULLFAIL: VerifyFlags = VerifyFlags(1 << 14);
+    pub const WITNESS_PUBKEYTYPE: VerifyFlags = VerifyFlags(1 << 15);
+    pub const CONST_SCRIPTCODE: VerifyFlags = VerifyFlags(1 << 16);
+    // BIP341 and BIP342
+    pub const TAPROOT: VerifyFlags = VerifyFlags(1 << 17);
+    pub const DISCOURAGE_UPGRADABLE_TAPROOT_VERSION: VerifyFlags = VerifyFlags(1 << 18);
+    pub const DISCOURAGE_OP_SUCCESS: VerifyFlags = VerifyFlags(1 << 19);
+    pub const DISCOURAGE_UPGRADABLE_PUBKEYTYPE: VerifyFlags = VerifyFlags(1 << 20);
+
+    // The soft forks every block has to follow today.
+    pub const MANDATORY: VerifyFlags = VerifyFlags(
+        Self::P2SH.0
+            | Self::DERSIG.0
+            | Self::NULLDUMMY.0
+            | Self::CHECKLOCKTIMEVERIFY.0
+            | Self::CHECKSEQUENCEVERIFY.0
+            | Self::WITNESS.0
+            | Self::TAPROOT.0,
+    );
+    // Policy rules applied to transactions entering the mempool on top of
+    // the consensus ones.
+    pub const STANDARD: VerifyFlags = VerifyFlags(
+        Self::MANDATORY.0
+            | Self::STRICTENC.0
+            | Self::MINIMALDATA.0
+            | Self::DISCOURAGE_UPGRADABLE_NOPS.0
+            | Self::CLEANSTACK.0
+            | Self::MINIMALIF.0
+            | Self::NULLFAIL.0
+            | Self::LOW_S.0
+            | Self::DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM.0
+            | Self::WITNESS_PUBKEYTYPE.0
+            | Self::CONST_SCRIPTCODE.0
+            | Self::DISCOURAGE_UPGRADABLE_TAPROOT_VERSION.0
+            | Self::DISCOURAGE_OP_SUCCESS.0
+            | Self::DISCOURAGE_UPGRADABLE_PUBKEYTYPE.0,
+    );
+
+    pub fn contains(&self, other: VerifyFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn bits(&self) -> u32 {
+        self.0
+    }
+}
+
+impl BitOr for VerifyFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        VerifyFlags(self.0 | rhs.0)
+    }
+}
+
+// Below this value nLockTime is a block height, above it a unix timestamp.
+const LOCKTIME_THRESHOLD: i64 = 500_000_000;
+const SEQUENCE_FINAL: u32 = 0xffffffff;
+// BIP68 relative lock-time fields of nSequence.
+const SEQUENCE_LOCKTIME_DISABLE_FLAG: i64 = 1 << 31;
+const SEQUENCE_LOCKTIME_TYPE_FLAG: i64 = 1 << 22;
+const SEQUENCE_LOCKTIME_MASK: i64 = 0x0000ffff;
+
+// The spending transaction and the index of the input being verified.
+#[derive(Debug, Clone, Copy)]
+pub struct TxContext<'a> {
+    pub tx: &'a Tx,
+    pub input_index: usize,
+}
+
+impl<'a> TxContext<'a> {
+    pub fn new(tx: &'a Tx, input_index: usize) -> Self {
+        TxContext { tx, input_index }
+    }
+
+    fn sequence(&self) -> u32 {
+        match &self.tx.tx_ins[self.input_index].sequence {
+            Some(sequence) => u32::try_from(sequence).unwrap_or(SEQUENCE_FINAL),
+            None => SEQUENCE_FINAL,
+        }
+    }
+
+    // BIP65: the transaction nLockTime must be of the same kind and at least
+    // the required value, and the input must not opt out of it.
+    pub fn check_lock_time(&self, lock_time: i64) -> bool {
+        let tx_lock_time = u32::try_from(&self.tx.locktime).unwrap_or(0) as i64;
+        if (tx_lock_time < LOCKTIME_THRESHOLD) != (lock_time < LOCKTIME_THRESHOLD) {
+            return false;
+        }
+        if lock_time > tx_lock_time {
+            return false;
+        }
+        self.sequence() != SEQUENCE_FINAL
+    }
+
+    // BIP112: compares against the relative lock-time of the input's nSequence.
+    pub fn check_sequence(&self, sequence: i64) -> bool {
+        let tx_sequence = self.sequence() as i64;
+        if u32::try_from(&self.tx.version).unwrap_or(0) < 2 {
+            return false;
+        }
+        if tx_sequence & SEQUENCE_LOCKTIME_DISABLE_FLAG != 0 {
+            return false;
+        }
+        let tx_sequence_masked =
+            tx_sequence & (SEQUENCE_LOCKTIME_TYPE_FLAG | SEQUENCE_LOCKTIME_MASK);
+        let sequence_masked = sequence & (SEQUENCE_LOCKTIME_TYPE_FLAG | SEQUENCE_LOCKTIME_MASK);
+        if (tx_sequence_masked < SEQUENCE_LOCKTIME_TYPE_FLAG)
+            != (sequence_masked < SEQUENCE_LOCKTIME_TYPE_FLAG)
+        {
+            return false;
+        }
+        sequence_masked <= tx_sequence_masked
+    }
+}
+
+// Tracks the OP_IF nesting like Bitcoin Core's ConditionStack: only the depth
+// and the position of the first false entry are needed to answer all_true().
+#[derive(Debug, Clone, Default)]
+pub struct ConditionStack {
+    size: usize,
+    first_false_pos: Option<usize>,
+}
+
+impl ConditionStack {
+    pub fn new() -> Self {
+        ConditionStack::default()
+    }
+
+    pub fn empty(&self) -> bool {
+        self.size == 0
+    }
+
+    pub fn depth(&self) -> usize {
+        self.size
+    }
+
+    pub fn all_true(&self) -> bool {
+        self.first_false_pos.is_none()
+    }
+
+    pub fn push_back(&mut self, value: bool) {
+        if self.first_false_pos.is_none() && !value {
+            self.first_false_pos = Some(self.size);
+        }
+        self.size += 1;
+    }
+
+    pub fn pop_back(&mut self) {
+        self.size -= 1;
+        if self.first_false_pos == Some(self.size) {
+            self.first_false_pos = None;
+        }
+    }
+
+    // Only the top entry can flip, anything below a false entry stays false.
+    pub fn toggle_top(&mut self) {
+        match self.first_false_pos {
+            None => self.first_false_pos = Some(self.size - 1),
+            Some(pos) if pos == self.size - 1 => self.first_false_pos = None,
+            Some(_) => {}
+        }
+    }
 }
 
 impl Display for Script {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let mut result: Vec<String> = Vec::new();
-        for cmd in &self.cmds {
-            match cmd {
-                Command::Element(element) => {
-                    let name = hex::encode(element);
-                    result.push(name);
-                }
-                Command::Operation(op) => {
-                    let name = op::get_op_names(op);
-                    result.push(name.to_string());
+        let mut pos = 0;
+        for instruction in self.instructions() {
+            let instruction = match instruction {
+                Ok(instruction) => instruction,
+                Err(_) => {
+                    result.push(format!("0x{}", hex::encode(&self.raw[pos..])));
+                    break;
                 }
-            }
+            };
+            pos = instruction.end;
+            let bytes = &self.raw[instruction.start..instruction.end];
+            result.push(asm_token(&instruction.command, bytes));
         }
         let fopcodes = result.join(" ");
         writeln!(f, "{}", fopcodes)
@@ -169,7 +830,7 @@ impl Add for Script {
 
     fn add(self, rhs: Self) -> Self::Output {
         Script {
-            cmds: [self.cmds, rhs.cmds].concat(),
+            raw: [self.raw, rhs.raw].concat(),
         }
     }
 }
@@ -178,20 +839,35 @@ impl Add for Script {
 mod script_tests {
     use std::io::Cursor;
 
-    use crate::{op, signature::Signature};
+    use num_bigint::BigInt;
+
+    use crate::{
+        error::Error,
+        network::Network,
+        op::{self, OpCodeFunctions},
+        private_key::PrivateKey,
+        script_builder::ScriptBuilder,
+        script_error::ScriptError,
+        signature::{Signature, SIGHASH_ALL},
+        signature_checker::{
+            NoSignatureChecker, SignatureHashChecker, TransactionSignatureChecker,
+        },
+        tx::{Tx, TxIn},
+        utils,
+    };
 
-    use super::{Command, Script};
+    use super::{Command, Script, ScriptType, VerifyFlags};
 
     #[test]
     fn test_parse_script() {
         let s = hex::decode("6a47304402207899531a52d59a6de200179928ca900254a36b8dff8bb75f5f5d71b1cdc26125022008b422690b8461cb52c3cc30330b23d574351872b7c361e9aae3649071c1a7160121035d5c93d9ac96881f19ba1f686f15f009ded7c62efe85a872e6a19b43c15a2937").unwrap();
         let mut cursor = Cursor::new(s);
-        let s = Script::parse(&mut cursor);
-        match &s.cmds[0] {
+        let s = Script::parse(&mut cursor).unwrap();
+        match &s.cmds().unwrap()[0] {
             super::Command::Element(elm) => assert_eq!(hex::encode(elm), "304402207899531a52d59a6de200179928ca900254a36b8dff8bb75f5f5d71b1cdc26125022008b422690b8461cb52c3cc30330b23d574351872b7c361e9aae3649071c1a71601"),
             super::Command::Operation(_) => assert!(false),
         }
-        match &s.cmds[1] {
+        match &s.cmds().unwrap()[1] {
             super::Command::Element(elem) => assert_eq!(
                 hex::encode(elem),
                 "035d5c93d9ac96881f19ba1f686f15f009ded7c62efe85a872e6a19b43c15a2937"
@@ -199,6 +875,1173 @@ mod script_tests {
             super::Command::Operation(_) => assert!(false),
         }
     }
+    #[test]
+    fn test_parse_bad_script() {
+        // declared length longer than the data
+        let mut cursor = Cursor::new(hex::decode("05030102").unwrap());
+        assert!(matches!(Script::parse(&mut cursor), Err(Error::Truncated)));
+        let mut cursor = Cursor::new(hex::decode("ffffffffffffffffff00").unwrap());
+        assert!(matches!(Script::parse(&mut cursor), Err(Error::Truncated)));
+        // pushes running past the end are kept as they are and only fail
+        // once decoded, a 4 GiB OP_PUSHDATA4 is never allocated
+        for raw in ["030102", "4effffffff", "4d01", "4c", "51024c"] {
+            let raw = hex::decode(raw).unwrap();
+            let mut cursor = Cursor::new([utils::encode_varint(raw.len()), raw.clone()].concat());
+            let script = Script::parse(&mut cursor).unwrap();
+            assert_eq!(script.raw_serialize(), raw);
+            assert_eq!(script.cmds(), Err(ScriptError::BadOpcode));
+            assert!(!script.is_push_only());
+            assert_eq!(script.script_type(), ScriptType::NonStandard);
+            assert_eq!(
+                script.execute(&mut vec![], &NoSignatureChecker, VerifyFlags::NONE),
+                Err(ScriptError::BadOpcode)
+            );
+        }
+        // the bad push is only reached when the script gets that far
+        let script = Script::from_bytes(hex::decode("006a4c").unwrap());
+        assert_eq!(
+            script.execute(&mut vec![], &NoSignatureChecker, VerifyFlags::NONE),
+            Err(ScriptError::OpReturn)
+        );
+    }
+
+    #[test]
+    fn test_raw_round_trip() {
+        // non-minimal push opcodes survive parsing and serializing
+        for raw in [
+            "4c01ff",
+            "4d0100ff",
+            "4e03000000010203",
+            "4c00",
+            "0105",
+            "4c14751e76e8199196d454941c45d1b3a323f1433bd6",
+            "76a94c14751e76e8199196d454941c45d1b3a323f1433bd688ac",
+            "a9144c",
+        ] {
+            let raw = hex::decode(raw).unwrap();
+            let serialized = [utils::encode_varint(raw.len()), raw.clone()].concat();
+            let script = Script::parse(&mut Cursor::new(serialized.clone())).unwrap();
+            assert_eq!(script.raw_serialize(), raw);
+            assert_eq!(script.serialize(), serialized);
+            assert_eq!(script.serialized_len(), raw.len());
+        }
+        let script = Script::from_bytes(hex::decode("4c01ff").unwrap());
+        assert_eq!(script.cmds().unwrap(), vec![Command::Element(vec![0xff])]);
+        assert_ne!(
+            script,
+            Script::new(Some(vec![Command::Element(vec![0xff])]))
+        );
+        let instruction = script.instructions().next().unwrap().unwrap();
+        assert_eq!(
+            (instruction.op_code, instruction.start, instruction.end),
+            (0x4c, 0, 3)
+        );
+    }
+
+    #[test]
+    fn test_op_codes_round_trip() {
+        // every byte that isn't a push parses into a single operation
+        let raw: Vec<u8> = [vec![0x00], (0x4f..=0xff).collect()].concat();
+        let mut cursor = Cursor::new([utils::encode_varint(raw.len()), raw.clone()].concat());
+        let script = Script::parse(&mut cursor).unwrap();
+        assert_eq!(script.cmds().unwrap().len(), raw.len());
+        assert_eq!(script.raw_serialize(), raw);
+
+        let mut cursor = Cursor::new(hex::decode("0a4e0300000001020352ae").unwrap());
+        let script = Script::parse(&mut cursor).unwrap();
+        assert!(matches!(&script.cmds().unwrap()[0], Command::Element(elem) if elem == &[1, 2, 3]));
+        // the non-minimal push keeps its bytes instead of reading as 197121
+        assert_eq!(
+            script.to_string(),
+            "0x4e03000000010203 2 OP_CHECKMULTISIG\n"
+        );
+        assert_eq!(Script::from_asm(&script.to_string()).unwrap(), script);
+    }
+
+    #[test]
+    fn test_from_asm() {
+        let script = Script::from_asm(
+            "OP_DUP OP_HASH160 d52ad7ca9b3d096a38e752c2018e6fbc40cdf26f OP_EQUALVERIFY OP_CHECKSIG",
+        )
+        .unwrap();
+        assert_eq!(
+            hex::encode(script.raw_serialize()),
+            "76a914d52ad7ca9b3d096a38e752c2018e6fbc40cdf26f88ac"
+        );
+        // numbers and single bytes use the small integer opcodes when they can
+        let script = Script::from_asm("0 -1 1 16 17 -17 1000 05 OP_CHECKSEQUENCEVERIFY").unwrap();
+        assert_eq!(
+            hex::encode(script.raw_serialize()),
+            "004f51600111019102e80355b2"
+        );
+        // names without the prefix and Core's aliases
+        let script =
+            Script::from_asm("DUP OP_TRUE OP_FALSE OP_0 OP_16 OP_1NEGATE NOP2 OP_NOP3").unwrap();
+        assert_eq!(hex::encode(script.raw_serialize()), "76510000604fb1b2");
+        // raw bytes are copied as is, even when they aren't a minimal push
+        let script = Script::from_asm("0x4c 0x01 0x07 'abc'").unwrap();
+        assert_eq!(
+            script.cmds().unwrap(),
+            vec![Command::Element(vec![7]), Command::Element(b"abc".to_vec())]
+        );
+
+        for length in [75, 76, 255, 256] {
+            let script = Script::from_asm(&"ab".repeat(length)).unwrap();
+            assert_eq!(
+                script.cmds().unwrap(),
+                vec![Command::Element(vec![0xab; length])]
+            );
+        }
+
+        // Core's script_tests.json uses numbers up to 0xffffffff
+        let script = Script::from_asm("4294967294 EQUAL -2147483648").unwrap();
+        assert_eq!(
+            hex::encode(script.raw_serialize()),
+            "05feffffff0087050000008080"
+        );
+        assert_eq!(
+            hex::encode(Script::from_asm("2147483648").unwrap().raw_serialize()),
+            "050000008000"
+        );
+
+        for bad in ["OP_FOO", "abc", "0x", "0xzz", "4294967296", "-4294967296"] {
+            assert!(Script::from_asm(bad).is_err(), "{}", bad);
+        }
+        // like Core's ParseScript, raw bytes aren't checked for complete pushes
+        let script = Script::from_asm("1 0x4c").unwrap();
+        assert_eq!(script.raw_serialize(), vec![0x51, 0x4c]);
+        assert_eq!(script.cmds(), Err(ScriptError::BadOpcode));
+    }
+
+    #[test]
+    fn test_asm_round_trip() {
+        for asm in [
+            "OP_DUP OP_HASH160 d52ad7ca9b3d096a38e752c2018e6fbc40cdf26f OP_EQUALVERIFY OP_CHECKSIG",
+            "2 022626e955ea6ea6d98850c994f9107b036b1334f18ca8830bfff1295d21cfdb70 03b287eaf122eea69030a0e9feed096bed8045c8b98bec453e1ffac7fbdbd4bb71 2 OP_CHECKMULTISIG",
+            "500000 OP_CHECKLOCKTIMEVERIFY OP_DROP -1000 2147483647 OP_IF 0 OP_ELSE 1 OP_ENDIF",
+            "OP_RETURN 48656c6c6f2c20776f726c6421",
+            "",
+        ] {
+            let script = Script::from_asm(asm).unwrap();
+            assert_eq!(script.to_string(), format!("{}\n", asm));
+            assert_eq!(Script::from_asm(&script.to_string()).unwrap(), script);
+        }
+
+        let s = hex::decode("6a47304402207899531a52d59a6de200179928ca900254a36b8dff8bb75f5f5d71b1cdc26125022008b422690b8461cb52c3cc30330b23d574351872b7c361e9aae3649071c1a7160121035d5c93d9ac96881f19ba1f686f15f009ded7c62efe85a872e6a19b43c15a2937").unwrap();
+        let script = Script::parse(&mut Cursor::new(s)).unwrap();
+        assert_eq!(Script::from_asm(&script.to_string()).unwrap(), script);
+    }
+
+    #[test]
+    fn test_asm_round_trip_pushes() {
+        // every way of pushing the data, minimal or not
+        let encodings = |data: &[u8]| {
+            let mut all = Vec::new();
+            if data.len() <= 0x4b {
+                all.push([&[data.len() as u8][..], data].concat());
+            }
+            if data.len() <= 0xff {
+                all.push([&[0x4c, data.len() as u8][..], data].concat());
+            }
+            all.push([&[0x4d][..], &(data.len() as u16).to_le_bytes(), data].concat());
+            all.push([&[0x4e][..], &(data.len() as u32).to_le_bytes(), data].concat());
+            all
+        };
+        let digits = [0x12, 0x34, 0x56, 0x78, 0x90];
+        for length in [0, 1, 2, 3, 4, 5, 9, 20, 33, 75, 76, 255, 256, 520] {
+            let payloads = [
+                (0..length)
+                    .map(|i| digits[i % digits.len()])
+                    .collect::<Vec<u8>>(),
+                vec![0; length],
+                vec![0x80; length],
+                (0..length).map(|i| (i * 37 + 1) as u8).collect(),
+            ];
+            for data in payloads {
+                for push in encodings(&data) {
+                    for raw in [push.clone(), [&[0xac][..], &push, &[0x87]].concat()] {
+                        let script = Script::from_bytes(raw);
+                        let asm = script.to_string();
+                        assert_eq!(Script::from_asm(&asm).unwrap(), script, "{}", asm);
+                    }
+                }
+            }
+        }
+        for raw in [
+            // small numbers, minimal and not
+            "00",
+            "4f",
+            "51",
+            "60",
+            "0181",
+            "0101",
+            "0110",
+            "020100",
+            "4c0101",
+            "0480000000",
+            // all-digit pushes
+            "76a914123456789012345678901234567890123456789088ac",
+            "051234567890",
+            "09300602010102010101",
+            // unknown opcodes and a push running past the end
+            "bb",
+            "ff",
+            "51ba",
+            "5102ab",
+        ] {
+            let script = Script::from_bytes(hex::decode(raw).unwrap());
+            let asm = script.to_string();
+            assert_eq!(Script::from_asm(&asm).unwrap(), script, "{}", asm);
+        }
+        // only minimal pushes print as numbers
+        let asm = |raw: &str| Script::from_bytes(hex::decode(raw).unwrap()).to_string();
+        assert_eq!(asm("51"), "1\n");
+        assert_eq!(asm("0101"), "0x0101\n");
+        assert_eq!(asm("4c0101"), "0x4c0101\n");
+        assert_eq!(asm("020100"), "0100\n");
+        assert_eq!(asm("051234567890"), "0x051234567890\n");
+        assert_eq!(asm("0500000000ff"), "00000000ff\n");
+    }
+
+    #[test]
+    fn test_script_type() {
+        let key = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+        let uncompressed = "0479be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798483ada7726a3c4655da4fbfc0e1108a8fd17b448a68554199c47d08ffb10d4b8";
+        let h160 = "751e76e8199196d454941c45d1b3a323f1433bd6";
+        let sha256 = "1863143c14c5166804bd19203356da136c985678cd4d27a1b8c6329604903262";
+        for (asm, script_type) in [
+            (format!("{} OP_CHECKSIG", key), ScriptType::P2pk),
+            (format!("{} OP_CHECKSIG", uncompressed), ScriptType::P2pk),
+            (
+                format!("OP_DUP OP_HASH160 {} OP_EQUALVERIFY OP_CHECKSIG", h160),
+                ScriptType::P2pkh,
+            ),
+            (format!("OP_HASH160 {} OP_EQUAL", h160), ScriptType::P2sh),
+            (format!("0 {}", h160), ScriptType::P2wpkh),
+            (format!("0 {}", sha256), ScriptType::P2wsh),
+            (format!("1 {}", sha256), ScriptType::P2tr),
+            (format!("1 {}", h160), ScriptType::WitnessUnknown),
+            (format!("16 {}", h160), ScriptType::WitnessUnknown),
+            (
+                format!("1 {} {} 2 OP_CHECKMULTISIG", key, uncompressed),
+                ScriptType::Multisig,
+            ),
+            ("OP_RETURN 48656c6c6f 1".to_string(), ScriptType::NullData),
+            ("OP_RETURN".to_string(), ScriptType::NullData),
+            // v0 programs must be 20 or 32 bytes
+            (format!("0 {}00", h160), ScriptType::NonStandard),
+            (format!("0 {}", "ab".repeat(41)), ScriptType::NonStandard),
+            (format!("{}ab OP_CHECKSIG", key), ScriptType::NonStandard),
+            (
+                format!("04{} OP_CHECKSIG", &key[2..]),
+                ScriptType::NonStandard,
+            ),
+            (
+                format!("3 {} {} 2 OP_CHECKMULTISIG", key, uncompressed),
+                ScriptType::NonStandard,
+            ),
+            (
+                format!("1 {} 2 OP_CHECKMULTISIG", key),
+                ScriptType::NonStandard,
+            ),
+            ("OP_RETURN OP_DUP".to_string(), ScriptType::NonStandard),
+            // the templates only match direct pushes, like Core's Solver
+            (format!("0 0x4c14 0x{}", h160), ScriptType::NonStandard),
+            (format!("1 0x4c20 0x{}", sha256), ScriptType::NonStandard),
+            (
+                format!("0x4c21 0x{} OP_CHECKSIG", key),
+                ScriptType::NonStandard,
+            ),
+            (
+                format!("0x4d4100 0x{} OP_CHECKSIG", uncompressed),
+                ScriptType::NonStandard,
+            ),
+            (
+                format!(
+                    "OP_DUP OP_HASH160 0x4c14 0x{} OP_EQUALVERIFY OP_CHECKSIG",
+                    h160
+                ),
+                ScriptType::NonStandard,
+            ),
+            (
+                format!("OP_HASH160 0x4c14 0x{} OP_EQUAL", h160),
+                ScriptType::NonStandard,
+            ),
+            (String::new(), ScriptType::NonStandard),
+        ] {
+            let script = Script::from_asm(&asm).unwrap();
+            assert_eq!(script.script_type(), script_type, "{}", asm);
+        }
+    }
+
+    #[test]
+    fn test_script_accessors() {
+        let key = hex::decode("0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798")
+            .unwrap();
+        let h160 = hex::decode("751e76e8199196d454941c45d1b3a323f1433bd6").unwrap();
+        let sha256 = utils::sha256(b"witness script");
+
+        let p2pk = Script::from_asm(&format!("{} OP_CHECKSIG", hex::encode(&key))).unwrap();
+        assert_eq!(p2pk.pubkey(), Some(&key[..]));
+        assert_eq!(p2pk.pubkey_hash(), None);
+
+        let p2pkh = utils::p2pkh_script(h160.clone());
+        assert!(p2pkh.is_p2pkh());
+        assert_eq!(p2pkh.pubkey_hash(), Some(&h160[..]));
+        assert_eq!(p2pkh.script_hash(), None);
+        assert_eq!(p2pkh.witness_program(), None);
+
+        let p2sh = utils::p2sh_script(h160.clone());
+        assert_eq!(p2sh.script_hash(), Some(&h160[..]));
+        assert_eq!(p2sh.pubkey_hash(), None);
+
+        let p2wpkh = utils::p2wpkh_script(h160.clone());
+        assert!(p2wpkh.is_p2wpkh());
+        assert_eq!(p2wpkh.pubkey_hash(), Some(&h160[..]));
+        assert_eq!(p2wpkh.witness_program(), Some((0, &h160[..])));
+
+        let p2wsh = utils::p2wsh_script(sha256.clone());
+        assert!(p2wsh.is_p2wsh());
+        assert_eq!(p2wsh.script_hash(), Some(&sha256[..]));
+
+        let p2tr = utils::p2tr_script(sha256.clone());
+        assert!(p2tr.is_p2tr());
+        assert_eq!(p2tr.witness_program(), Some((1, &sha256[..])));
+        assert_eq!(p2tr.script_hash(), None);
+
+        let multisig = ScriptBuilder::new()
+            .push_int(2)
+            .push_slice(&key)
+            .push_slice(&key)
+            .push_slice(&key)
+            .push_int(3)
+            .push_opcode(OpCodeFunctions::OpCheckmultisig(0xae))
+            .into_script();
+        assert_eq!(multisig.script_type(), ScriptType::Multisig);
+        assert_eq!(multisig.multisig(), Some((2, vec![&key[..]; 3])));
+        assert_eq!(p2pk.multisig(), None);
+    }
+
+    fn evaluate_hex(raw: &str) -> bool {
+        let raw = hex::decode(raw).unwrap();
+        let mut cursor = Cursor::new([utils::encode_varint(raw.len()), raw].concat());
+        let script = Script::parse(&mut cursor).unwrap();
+        script.evaluate(Signature::signature_hash_from_hex("00"))
+    }
+
+    #[test]
+    fn test_evaluate_conditionals() {
+        // 1 IF 2 ELSE 3 ENDIF 2 EQUAL
+        assert!(evaluate_hex("5163526753685287"));
+        // 0 IF 2 ELSE 3 ENDIF 3 EQUAL
+        assert!(evaluate_hex("0063526753685387"));
+        // 0 NOTIF 2 ELSE 3 ENDIF 2 EQUAL
+        assert!(evaluate_hex("0064526753685287"));
+        // 0 IF 1 IF RETURN ELSE RETURN ENDIF ELSE 1 ENDIF
+        assert!(evaluate_hex("006351636a676a68675168"));
+        // 1 IF 0 IF RETURN ENDIF 1 ENDIF
+        assert!(evaluate_hex("516300636a685168"));
+        // 0 IF RETURN ELSE 1 ELSE RETURN ENDIF
+        assert!(evaluate_hex("00636a6751676a68"));
+        // 1 IF ELSE ELSE 1 ENDIF
+        assert!(evaluate_hex("516367675168"));
+        // unbalanced or stray conditionals fail
+        assert!(!evaluate_hex("516351"));
+        assert!(!evaluate_hex("5167"));
+        assert!(!evaluate_hex("5168"));
+        assert!(!evaluate_hex("63"));
+        // 0 IF VERIF ENDIF 1 fails even though VERIF is not executed
+        assert!(!evaluate_hex("0063656851"));
+        // negative zero is false
+        assert!(!evaluate_hex("0180"));
+    }
+
+    #[test]
+    fn test_evaluate_stack_ops() {
+        // 1 2 TOALTSTACK 1 EQUALVERIFY FROMALTSTACK 2 EQUAL
+        assert!(evaluate_hex("51526b51886c5287"));
+        // FROMALTSTACK on an empty altstack fails
+        assert!(!evaluate_hex("516c"));
+        // 1 2 3 2 PICK 1 EQUALVERIFY DEPTH 3 EQUAL
+        assert!(evaluate_hex("51525352795188745387"));
+        // 1 2 3 2 ROLL 1 EQUALVERIFY DEPTH 2 EQUAL
+        assert!(evaluate_hex("515253527a5188745287"));
+        // 1 2 PICK reaches past the bottom of the stack
+        assert!(!evaluate_hex("515279"));
+        // 1 2 3 ROT 1 EQUAL
+        assert!(evaluate_hex("5152537b5187"));
+        // 1 2 OVER 1 EQUAL
+        assert!(evaluate_hex("5152785187"));
+        // 1 2 TUCK DROP DROP 2 EQUAL
+        assert!(evaluate_hex("51527d75755287"));
+        // 0 IFDUP DEPTH 1 EQUAL
+        assert!(evaluate_hex("0073745187"));
+        // 1 IFDUP DEPTH 2 EQUAL
+        assert!(evaluate_hex("5173745287"));
+        // 1 2 3 4 2SWAP 2 EQUALVERIFY 1 EQUALVERIFY 4 EQUALVERIFY 3 EQUAL
+        assert!(evaluate_hex("51525354725288518854885387"));
+        // 1 2 3 4 5 6 2ROT 2 EQUALVERIFY 1 EQUAL
+        assert!(evaluate_hex("5152535455567152885187"));
+        // 1 2 3 4 2OVER 2 EQUALVERIFY 1 EQUALVERIFY 2DROP 2DROP DEPTH 0 EQUAL
+        assert!(evaluate_hex("5152535470528851886d6d740087"));
+        // 1 2 3 3DUP DEPTH 6 EQUALVERIFY 3 EQUAL
+        assert!(evaluate_hex("5152536f7456885387"));
+        // 1 2 NIP 2 EQUALVERIFY DEPTH 0 EQUAL
+        assert!(evaluate_hex("5152775288740087"));
+        // 0x0102 SIZE 2 EQUAL
+        assert!(evaluate_hex("020102825287"));
+    }
+
+    #[test]
+    fn test_evaluate_arithmetic() {
+        // 2 3 ADD 5 EQUAL
+        assert!(evaluate_hex("5253935587"));
+        // 2 3 SUB -1 EQUAL
+        assert!(evaluate_hex("5253944f87"));
+        // 2 1ADD 3 EQUAL, 2 1SUB 1 EQUAL
+        assert!(evaluate_hex("528b5387"));
+        assert!(evaluate_hex("528c5187"));
+        // 5 NEGATE 0x85 EQUAL, -1 ABS 1 EQUAL
+        assert!(evaluate_hex("558f018587"));
+        assert!(evaluate_hex("4f905187"));
+        // 0 NOT, 1 NOT 0 EQUAL, 5 0NOTEQUAL 1 EQUAL
+        assert!(evaluate_hex("0091"));
+        assert!(evaluate_hex("51910087"));
+        assert!(evaluate_hex("55925187"));
+        // 0 1 BOOLAND NOT, 0 1 BOOLOR
+        assert!(evaluate_hex("00519a91"));
+        assert!(evaluate_hex("00519b"));
+        // 2 2 NUMEQUALVERIFY 1, 2 3 NUMEQUALVERIFY 1, 2 3 NUMNOTEQUAL
+        assert!(evaluate_hex("52529d51"));
+        assert!(!evaluate_hex("52539d51"));
+        assert!(evaluate_hex("52539e"));
+        // 3 2 LESSTHAN NOT, 3 2 GREATERTHAN, 2 2 LESSTHANOREQUAL, 2 3 GREATERTHANOREQUAL NOT
+        assert!(evaluate_hex("53529f91"));
+        assert!(evaluate_hex("5352a0"));
+        assert!(evaluate_hex("5252a1"));
+        assert!(evaluate_hex("5253a291"));
+        // 3 2 MIN 2 EQUAL, 3 2 MAX 3 EQUAL
+        assert!(evaluate_hex("5352a35287"));
+        assert!(evaluate_hex("5352a45387"));
+        // 3 2 5 WITHIN, 5 2 5 WITHIN NOT
+        assert!(evaluate_hex("535255a5"));
+        assert!(evaluate_hex("555255a591"));
+        // 0x7fffffff 1ADD 0x0000008000 EQUAL: results may exceed 4 bytes
+        assert!(evaluate_hex("04ffffff7f8b05000000800087"));
+        // but can't be used as operands again
+        assert!(!evaluate_hex("04ffffff7f8b8b"));
+        assert!(!evaluate_hex("05000000800091"));
+    }
+
+    #[test]
+    fn test_evaluate_minimal_numbers() {
+        let evaluate = |raw: &str, flags| {
+            let raw = hex::decode(raw).unwrap();
+            let mut cursor = Cursor::new([utils::encode_varint(raw.len()), raw].concat());
+            let script = Script::parse(&mut cursor).unwrap();
+            script.evaluate_with_flags(Signature::signature_hash_from_hex("00"), flags)
+        };
+        // 0x80 0 NUMEQUAL: negative zero
+        assert!(evaluate("0180009c", VerifyFlags::NONE));
+        assert!(!evaluate("0180009c", VerifyFlags::MINIMALDATA));
+        // 0x0100 1 NUMEQUAL
+        assert!(evaluate("020100519c", VerifyFlags::NONE));
+        assert!(!evaluate("020100519c", VerifyFlags::MINIMALDATA));
+
+        // data pushed with a longer push opcode than needed
+        for raw in [
+            "4c01ff",
+            "4d0100ff",
+            "4e01000000ff",
+            "4c0051",
+            &format!("4d4c00{}", "ff".repeat(76)),
+            &format!("4e00010000{}", "ff".repeat(256)),
+        ] {
+            assert!(evaluate(raw, VerifyFlags::NONE), "{}", raw);
+            assert!(!evaluate(raw, VerifyFlags::MINIMALDATA), "{}", raw);
+        }
+        for raw in [
+            "01ff",
+            "0051",
+            &format!("4c4c{}", "ff".repeat(76)),
+            &format!("4d0001{}", "ff".repeat(256)),
+        ] {
+            assert!(evaluate(raw, VerifyFlags::MINIMALDATA), "{}", raw);
+        }
+    }
+
+    #[test]
+    fn test_evaluate_disabled_opcodes() {
+        // 2 3 MUL 6 EQUAL
+        assert!(!evaluate_hex("5253955687"));
+        // 0 IF MUL ENDIF 1
+        assert!(!evaluate_hex("0063956851"));
+        // 0 IF RESERVED ENDIF 1: only disabled opcodes fail when skipped
+        assert!(evaluate_hex("0063506851"));
+    }
+
+    #[test]
+    fn test_evaluate_multisig_2_of_3() {
+        let z = || Signature::signature_hash("2-of-3 multisig");
+        let keys: Vec<PrivateKey> = (1..=3)
+            .map(|n| PrivateKey::new(&PrivateKey::generate_simple_secret(BigInt::from(n * 1000))))
+            .collect();
+        let sign =
+            |key: &PrivateKey| [key.sign(&z(), None).der(), vec![SIGHASH_ALL as u8]].concat();
+        let sig1 = sign(&keys[0]);
+        let sig3 = sign(&keys[2]);
+        let operation = |byte| Command::Operation(op::parse_raw_op_codes(byte).unwrap());
+        let mut cmds = vec![operation(0x52)];
+        for key in &keys {
+            cmds.push(Command::Element(key.point.clone().sec(Some(true))));
+        }
+        cmds.append(&mut vec![operation(0x53), operation(0xae)]);
+        let script_pubkey = Script::new(Some(cmds));
+        let spend = |elements: Vec<&Vec<u8>>| {
+            let cmds = elements
+                .into_iter()
+                .cloned()
+                .map(Command::Element)
+                .collect();
+            Script::new(Some(cmds)) + script_pubkey.clone()
+        };
+
+        assert!(spend(vec![&vec![], &sig1, &sig3]).evaluate(z()));
+        // signatures must follow the order of their keys
+        assert!(!spend(vec![&vec![], &sig3, &sig1]).evaluate(z()));
+        // without the dummy element the stack is one short
+        assert!(!spend(vec![&sig1, &sig3]).evaluate(z()));
+        // any dummy is accepted unless NULLDUMMY is enforced
+        assert!(spend(vec![&vec![1], &sig1, &sig3]).evaluate(z()));
+        assert!(
+            !spend(vec![&vec![1], &sig1, &sig3]).evaluate_with_flags(z(), VerifyFlags::NULLDUMMY)
+        );
+
+        let mut verify_cmds = script_pubkey.cmds().unwrap();
+        verify_cmds.pop();
+        verify_cmds.append(&mut vec![operation(0xaf), operation(0x51)]);
+        let script = Script::new(Some(vec![
+            Command::Element(vec![]),
+            Command::Element(sig1.clone()),
+            Command::Element(sig3.clone()),
+        ])) + Script::new(Some(verify_cmds));
+        assert!(script.evaluate_with_flags(z(), VerifyFlags::NULLDUMMY));
+    }
+
+    #[test]
+    fn test_verify_p2sh() {
+        let z = || Signature::signature_hash_from_hex("00");
+        let operation = |byte| Command::Operation(op::parse_raw_op_codes(byte).unwrap());
+        // OP_2 OP_EQUAL
+        let redeem_script = Script::new(Some(vec![operation(0x52), operation(0x87)]));
+        let raw_redeem = redeem_script.raw_serialize();
+        let script_pubkey = utils::p2sh_script(utils::hash160(&raw_redeem));
+        assert!(script_pubkey.is_p2sh());
+        let spend = |cmds: Vec<Command>| Script::new(Some(cmds));
+
+        let script_sig = spend(vec![operation(0x52), Command::Element(raw_redeem.clone())]);
+        assert!(script_sig.is_push_only());
+        assert_eq!(
+            Script::verify_script(
+                &script_sig,
+                &script_pubkey,
+                &SignatureHashChecker::new(z()),
+                VerifyFlags::P2SH
+            ),
+            Ok(())
+        );
+        // the redeem script only runs under BIP16
+        let script_sig = spend(vec![operation(0x53), Command::Element(raw_redeem.clone())]);
+        assert_eq!(
+            Script::verify_script(
+                &script_sig,
+                &script_pubkey,
+                &SignatureHashChecker::new(z()),
+                VerifyFlags::NONE
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            Script::verify_script(
+                &script_sig,
+                &script_pubkey,
+                &SignatureHashChecker::new(z()),
+                VerifyFlags::P2SH
+            ),
+            Err(ScriptError::EvalFalse)
+        );
+        // scriptSig must be push only
+        let script_sig = spend(vec![
+            operation(0x52),
+            operation(0x61),
+            Command::Element(raw_redeem.clone()),
+        ]);
+        assert!(!script_sig.is_push_only());
+        assert_eq!(
+            Script::verify_script(
+                &script_sig,
+                &script_pubkey,
+                &SignatureHashChecker::new(z()),
+                VerifyFlags::P2SH
+            ),
+            Err(ScriptError::SigPushOnly)
+        );
+        // the last push has to hash to the scriptPubKey
+        let script_sig = spend(vec![operation(0x52), Command::Element(vec![0x52, 0x88])]);
+        assert_eq!(
+            Script::verify_script(
+                &script_sig,
+                &script_pubkey,
+                &SignatureHashChecker::new(z()),
+                VerifyFlags::P2SH
+            ),
+            Err(ScriptError::EvalFalse)
+        );
+        // the hash pushed with OP_PUSHDATA1 is a plain script, BIP16 doesn't
+        // run the 0x00 redeem script that would leave false behind
+        let h160 = utils::hash160(&[0x00]);
+        let script_pubkey = Script::from_bytes([&[0xa9, 0x4c, 0x14][..], &h160, &[0x87]].concat());
+        assert!(!script_pubkey.is_p2sh());
+        assert_eq!(script_pubkey.script_type(), ScriptType::NonStandard);
+        let script_sig = spend(vec![operation(0x51), Command::Element(vec![0x00])]);
+        assert_eq!(
+            Script::verify_script(
+                &script_sig,
+                &script_pubkey,
+                &SignatureHashChecker::new(z()),
+                VerifyFlags::P2SH
+            ),
+            Ok(())
+        );
+        // the redeem script is the top of the stack, here the empty script
+        // OP_0 pushed, which leaves the 1 below it
+        let script_pubkey = utils::p2sh_script(utils::hash160(&[]));
+        let script_sig = Script::from_bytes(vec![0x51, 0x00]);
+        assert_eq!(script_sig.redeem_script(), Some(Script::new(None)));
+        assert_eq!(
+            Script::verify_script(
+                &script_sig,
+                &script_pubkey,
+                &SignatureHashChecker::new(z()),
+                VerifyFlags::P2SH
+            ),
+            Ok(())
+        );
+        // OP_1 pushes 0x01, run as a push with nothing after it
+        let script_pubkey = utils::p2sh_script(utils::hash160(&[0x01]));
+        let script_sig = Script::from_bytes(vec![0x51]);
+        assert_eq!(
+            script_sig.redeem_script(),
+            Some(Script::from_bytes(vec![0x01]))
+        );
+        assert_eq!(
+            Script::verify_script(
+                &script_sig,
+                &script_pubkey,
+                &SignatureHashChecker::new(z()),
+                VerifyFlags::P2SH
+            ),
+            Err(ScriptError::BadOpcode)
+        );
+    }
+
+    #[test]
+    fn test_verify_witness_program() {
+        let z = || Signature::signature_hash_from_hex("00");
+        let verify = |script_sig: &Script, script_pubkey: &Script, flags| {
+            Script::verify_script(
+                script_sig,
+                script_pubkey,
+                &SignatureHashChecker::new(z()),
+                flags,
+            )
+        };
+        let empty = Script::new(None);
+        let script_pubkey = utils::p2wpkh_script(vec![0x11; 20]);
+        // without WITNESS the program is left on the stack and passes
+        assert_eq!(verify(&empty, &script_pubkey, VerifyFlags::P2SH), Ok(()));
+        assert_eq!(
+            verify(&empty, &script_pubkey, VerifyFlags::MANDATORY),
+            Err(ScriptError::WitnessUnsupported)
+        );
+        let script_sig = Script::new(Some(vec![Command::Element(vec![0x01])]));
+        assert_eq!(
+            verify(&script_sig, &script_pubkey, VerifyFlags::MANDATORY),
+            Err(ScriptError::WitnessMalleated)
+        );
+
+        // P2SH-P2WPKH
+        let redeem_script = utils::p2wpkh_script(vec![0x11; 20]).raw_serialize();
+        let script_pubkey = utils::p2sh_script(utils::hash160(&redeem_script));
+        let script_sig = Script::new(Some(vec![Command::Element(redeem_script.clone())]));
+        assert_eq!(
+            verify(&script_sig, &script_pubkey, VerifyFlags::P2SH),
+            Ok(())
+        );
+        assert_eq!(
+            verify(&script_sig, &script_pubkey, VerifyFlags::MANDATORY),
+            Err(ScriptError::WitnessUnsupported)
+        );
+        let script_sig = Script::new(Some(vec![
+            Command::Element(vec![0x01]),
+            Command::Element(redeem_script),
+        ]));
+        assert_eq!(
+            verify(&script_sig, &script_pubkey, VerifyFlags::MANDATORY),
+            Err(ScriptError::WitnessMalleatedP2sh)
+        );
+    }
+
+    #[test]
+    fn test_check_lock_time_verify() {
+        let spending_tx = |locktime: u32, sequence: u32| {
+            let tx_in = TxIn::new(
+                vec![0; 32],
+                BigInt::from(0),
+                None,
+                Some(BigInt::from(sequence)),
+            );
+            Tx::new(
+                BigInt::from(1),
+                vec![tx_in],
+                vec![],
+                BigInt::from(locktime),
+                Network::Mainnet,
+            )
+        };
+        // <lock_time> OP_CHECKLOCKTIMEVERIFY
+        let script_pubkey = |lock_time: Vec<u8>| {
+            Script::new(Some(vec![
+                Command::Element(lock_time),
+                Command::Operation(op::parse_raw_op_codes(0xb1).unwrap()),
+            ]))
+        };
+        let verify = |tx: &Tx, lock_time: Vec<u8>, flags| {
+            Script::verify_script(
+                &Script::new(None),
+                &script_pubkey(lock_time),
+                &TransactionSignatureChecker::new(tx, 0),
+                flags,
+            )
+        };
+        let flags = VerifyFlags::CHECKLOCKTIMEVERIFY;
+        let tx = spending_tx(700_000, 0xfffffffe);
+        // height 600000
+        assert_eq!(verify(&tx, vec![0xc0, 0x27, 0x09], flags), Ok(()));
+        // height 800000 is not reached yet
+        assert_eq!(
+            verify(&tx, vec![0x00, 0x35, 0x0c], flags),
+            Err(ScriptError::UnsatisfiedLocktime)
+        );
+        // a timestamp can't be compared with a height
+        assert_eq!(
+            verify(&tx, vec![0x00, 0x65, 0xcd, 0x1d], flags),
+            Err(ScriptError::UnsatisfiedLocktime)
+        );
+        // negative lock times fail
+        assert_eq!(
+            verify(&tx, vec![0x81], flags),
+            Err(ScriptError::NegativeLocktime)
+        );
+        // a final input disables nLockTime
+        assert_eq!(
+            verify(
+                &spending_tx(700_000, 0xffffffff),
+                vec![0xc0, 0x27, 0x09],
+                flags
+            ),
+            Err(ScriptError::UnsatisfiedLocktime)
+        );
+        // timestamps above 2^31 need the fifth byte
+        let tx = spending_tx(0xf0000000, 0);
+        assert_eq!(
+            verify(&tx, vec![0x00, 0x00, 0x00, 0xe0, 0x00], flags),
+            Ok(())
+        );
+        assert_eq!(verify(&tx, vec![0x00, 0x65, 0xcd, 0x1d], flags), Ok(()));
+        // without the flag it is OP_NOP2
+        assert_eq!(
+            verify(&tx, vec![0x00, 0x35, 0x0c], VerifyFlags::NONE),
+            Ok(())
+        );
+        // and with it there has to be a transaction to check against
+        assert_eq!(
+            Script::verify_script(
+                &Script::new(None),
+                &script_pubkey(vec![0x01]),
+                &NoSignatureChecker,
+                flags
+            ),
+            Err(ScriptError::UnsatisfiedLocktime)
+        );
+    }
+
+    #[test]
+    fn test_check_sequence_verify() {
+        let spending_tx = |version: u32, sequence: u32| {
+            let tx_in = TxIn::new(
+                vec![0; 32],
+                BigInt::from(0),
+                None,
+                Some(BigInt::from(sequence)),
+            );
+            Tx::new(
+                BigInt::from(version),
+                vec![tx_in],
+                vec![],
+                BigInt::from(0),
+                Network::Mainnet,
+            )
+        };
+        // <sequence> OP_CHECKSEQUENCEVERIFY
+        let verify = |tx: &Tx, sequence: Vec<u8>, flags| {
+            let script_pubkey = Script::new(Some(vec![
+                Command::Element(sequence),
+                Command::Operation(op::parse_raw_op_codes(0xb2).unwrap()),
+            ]));
+            Script::verify_script(
+                &Script::new(None),
+                &script_pubkey,
+                &TransactionSignatureChecker::new(tx, 0),
+                flags,
+            )
+        };
+        let flags = VerifyFlags::CHECKSEQUENCEVERIFY;
+        // 144 blocks
+        let tx = spending_tx(2, 144);
+        assert_eq!(verify(&tx, vec![0x64], flags), Ok(()));
+        assert_eq!(verify(&tx, vec![0x90, 0x00], flags), Ok(()));
+        assert_eq!(
+            verify(&tx, vec![0x91, 0x00], flags),
+            Err(ScriptError::UnsatisfiedLocktime)
+        );
+        // a time based lock doesn't match a height based one
+        assert_eq!(
+            verify(&tx, vec![0x01, 0x00, 0x40], flags),
+            Err(ScriptError::UnsatisfiedLocktime)
+        );
+        assert_eq!(
+            verify(
+                &spending_tx(2, (1 << 22) | 10),
+                vec![0x01, 0x00, 0x40],
+                flags
+            ),
+            Ok(())
+        );
+        // relative lock times need version 2
+        assert_eq!(
+            verify(&spending_tx(1, 144), vec![0x64], flags),
+            Err(ScriptError::UnsatisfiedLocktime)
+        );
+        // the input opted out of relative lock times
+        assert_eq!(
+            verify(&spending_tx(2, (1 << 31) | 144), vec![0x64], flags),
+            Err(ScriptError::UnsatisfiedLocktime)
+        );
+        // the disable flag in the argument turns the opcode into a NOP
+        assert_eq!(
+            verify(
+                &spending_tx(1, 0xffffffff),
+                vec![0x00, 0x00, 0x00, 0x80, 0x00],
+                flags
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            verify(&tx, vec![0x81], flags),
+            Err(ScriptError::NegativeLocktime)
+        );
+        // without the flag it is OP_NOP3
+        assert_eq!(
+            verify(&spending_tx(1, 0), vec![0x64], VerifyFlags::NONE),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_resource_limits() {
+        let execute = |cmds: Vec<Command>| {
+            let mut stack = Vec::new();
+            let z = Signature::signature_hash_from_hex("00");
+            Script::new(Some(cmds)).execute(
+                &mut stack,
+                &SignatureHashChecker::new(z),
+                VerifyFlags::NONE,
+            )
+        };
+        let operation = |byte| Command::Operation(op::parse_raw_op_codes(byte).unwrap());
+        let nops = |count| vec![operation(0x61); count];
+
+        assert_eq!(execute(vec![Command::Element(vec![1; 520])]), Ok(()));
+        assert_eq!(
+            execute(vec![Command::Element(vec![1; 521])]),
+            Err(ScriptError::PushSize)
+        );
+        assert_eq!(execute(nops(201)), Ok(()));
+        assert_eq!(execute(nops(202)), Err(ScriptError::OpCount));
+        // unexecuted opcodes count too: 0 IF NOP... ENDIF
+        let cmds = [
+            vec![operation(0x00), operation(0x63)],
+            nops(199),
+            vec![operation(0x68)],
+        ];
+        assert_eq!(execute(cmds.concat()), Ok(()));
+        let cmds = [
+            vec![operation(0x00), operation(0x63)],
+            nops(200),
+            vec![operation(0x68)],
+        ];
+        assert_eq!(execute(cmds.concat()), Err(ScriptError::OpCount));
+        // each key of an executed CHECKMULTISIG counts as well: 0 0 <20 keys> 20 CHECKMULTISIG
+        let multisig = |nop_count| {
+            let mut cmds = vec![operation(0x00), operation(0x00)];
+            cmds.extend(vec![Command::Element(vec![2; 33]); 20]);
+            cmds.push(Command::Element(vec![20]));
+            cmds.push(operation(0xae));
+            [cmds, nops(nop_count)].concat()
+        };
+        assert_eq!(execute(multisig(180)), Ok(()));
+        assert_eq!(execute(multisig(181)), Err(ScriptError::OpCount));
+        // 1000 elements across both stacks
+        assert_eq!(execute(vec![operation(0x51); 1000]), Ok(()));
+        assert_eq!(
+            execute([vec![operation(0x51); 1000], vec![operation(0x76)]].concat()),
+            Err(ScriptError::StackSize)
+        );
+        assert_eq!(
+            execute(
+                [
+                    vec![operation(0x51); 1000],
+                    vec![operation(0x6b), operation(0x51)]
+                ]
+                .concat()
+            ),
+            Err(ScriptError::StackSize)
+        );
+        assert_eq!(
+            execute(vec![Command::Element(vec![1; 500]); 20]),
+            Err(ScriptError::ScriptSize)
+        );
+    }
+
+    #[test]
+    fn test_code_separator() {
+        let private_key =
+            PrivateKey::new(&PrivateKey::generate_simple_secret(BigInt::from(8675309)));
+        let sec = hex::encode(private_key.point.clone().sec(Some(true)));
+        let tx = Tx::new(
+            BigInt::from(1),
+            vec![TxIn::new(vec![0; 32], BigInt::from(0), None, None)],
+            vec![],
+            BigInt::from(0),
+            Network::Mainnet,
+        );
+        let sign = |script_code: &str| {
+            let script_code = Script::from_asm(script_code).unwrap();
+            let z = tx.sig_hash(0, Some(script_code), SIGHASH_ALL).unwrap();
+            let sig = private_key.sign(&Signature::signature_hash_from_int(z), None);
+            Script::new(Some(vec![Command::Element(
+                [sig.der(), vec![SIGHASH_ALL as u8]].concat(),
+            )]))
+        };
+        let verify = |script_sig: &Script, script_pubkey: &str, flags| {
+            Script::verify_script(
+                script_sig,
+                &Script::from_asm(script_pubkey).unwrap(),
+                &TransactionSignatureChecker::new(&tx, 0),
+                flags,
+            )
+        };
+
+        // only what follows the last executed OP_CODESEPARATOR is signed
+        let script_pubkey = format!(
+            "1 OP_DROP OP_CODESEPARATOR 2 OP_DROP OP_CODESEPARATOR {} OP_CHECKSIG",
+            sec
+        );
+        let script_sig = sign(&format!("{} OP_CHECKSIG", sec));
+        assert_eq!(
+            verify(&script_sig, &script_pubkey, VerifyFlags::MANDATORY),
+            Ok(())
+        );
+        let script_sig = sign(&script_pubkey);
+        assert_eq!(
+            verify(&script_sig, &script_pubkey, VerifyFlags::MANDATORY),
+            Err(ScriptError::EvalFalse)
+        );
+        // unexecuted separators don't move the start
+        let script_pubkey = format!("0 OP_IF OP_CODESEPARATOR OP_ENDIF {} OP_CHECKSIG", sec);
+        let script_sig = sign(&script_pubkey);
+        assert_eq!(
+            verify(&script_sig, &script_pubkey, VerifyFlags::MANDATORY),
+            Ok(())
+        );
+        assert_eq!(
+            verify(&script_sig, &script_pubkey, VerifyFlags::CONST_SCRIPTCODE),
+            Err(ScriptError::OpCodeseparator)
+        );
+        // and OP_CODESEPARATOR itself is never part of the hash
+        assert_eq!(
+            tx.sig_hash(
+                0,
+                Some(Script::from_asm("OP_CODESEPARATOR 1").unwrap()),
+                SIGHASH_ALL
+            )
+            .unwrap(),
+            tx.sig_hash(0, Some(Script::from_asm("1").unwrap()), SIGHASH_ALL)
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_find_and_delete() {
+        let private_key =
+            PrivateKey::new(&PrivateKey::generate_simple_secret(BigInt::from(8675309)));
+        let sec = hex::encode(private_key.point.clone().sec(Some(true)));
+        let tx = Tx::new(
+            BigInt::from(1),
+            vec![TxIn::new(vec![0; 32], BigInt::from(0), None, None)],
+            vec![],
+            BigInt::from(0),
+            Network::Mainnet,
+        );
+        // the signature is signed without the copy of itself in the script
+        let script_code = Script::from_asm(&format!("OP_DROP {} OP_CHECKSIG", sec)).unwrap();
+        let z = tx.sig_hash(0, Some(script_code), SIGHASH_ALL).unwrap();
+        let sig = private_key.sign(&Signature::signature_hash_from_int(z), None);
+        let sig = hex::encode([sig.der(), vec![SIGHASH_ALL as u8]].concat());
+        let script_sig = Script::from_asm(&sig).unwrap();
+        let script_pubkey =
+            Script::from_asm(&format!("{} OP_DROP {} OP_CHECKSIG", sig, sec)).unwrap();
+        let verify = |flags| {
+            Script::verify_script(
+                &script_sig,
+                &script_pubkey,
+                &TransactionSignatureChecker::new(&tx, 0),
+                flags,
+            )
+        };
+        assert_eq!(verify(VerifyFlags::MANDATORY), Ok(()));
+        assert_eq!(
+            verify(VerifyFlags::CONST_SCRIPTCODE),
+            Err(ScriptError::SigFindAndDelete)
+        );
+    }
+
+    #[test]
+    fn test_verify_flags() {
+        let z = || Signature::signature_hash_from_hex("00");
+        let operation = |byte| Command::Operation(op::parse_raw_op_codes(byte).unwrap());
+        let verify = |script_sig: Vec<Command>, script_pubkey: Vec<Command>, flags| {
+            Script::verify_script(
+                &Script::new(Some(script_sig)),
+                &Script::new(Some(script_pubkey)),
+                &SignatureHashChecker::new(z()),
+                flags,
+            )
+        };
+        // 1 NOP | 1
+        let script_sig = vec![operation(0x51), operation(0x61)];
+        assert_eq!(
+            verify(script_sig.clone(), vec![operation(0x51)], VerifyFlags::NONE),
+            Ok(())
+        );
+        assert_eq!(
+            verify(script_sig, vec![operation(0x51)], VerifyFlags::SIGPUSHONLY),
+            Err(ScriptError::SigPushOnly)
+        );
+        // 1 1 | 1
+        let script_sig = vec![operation(0x51), operation(0x51)];
+        assert_eq!(
+            verify(script_sig.clone(), vec![operation(0x51)], VerifyFlags::NONE),
+            Ok(())
+        );
+        assert_eq!(
+            verify(
+                script_sig,
+                vec![operation(0x51)],
+                VerifyFlags::P2SH | VerifyFlags::CLEANSTACK
+            ),
+            Err(ScriptError::CleanStack)
+        );
+        // | NOP4 1
+        let script_pubkey = vec![operation(0xb3), operation(0x51)];
+        assert_eq!(
+            verify(vec![], script_pubkey.clone(), VerifyFlags::NONE),
+            Ok(())
+        );
+        assert_eq!(
+            verify(
+                vec![],
+                script_pubkey,
+                VerifyFlags::DISCOURAGE_UPGRADABLE_NOPS
+            ),
+            Err(ScriptError::DiscourageUpgradableNops)
+        );
+        // 0x05 | 5 EQUAL
+        let script_sig = vec![Command::Element(vec![5])];
+        let script_pubkey = vec![operation(0x55), operation(0x87)];
+        assert_eq!(
+            verify(script_sig.clone(), script_pubkey.clone(), VerifyFlags::NONE),
+            Ok(())
+        );
+        assert_eq!(
+            verify(script_sig, script_pubkey, VerifyFlags::MINIMALDATA),
+            Err(ScriptError::MinimalData)
+        );
+
+        // <sig> | <pubkey> CHECKSIG NOT
+        let pubkey =
+            hex::decode("0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798")
+                .unwrap();
+        let checksig_not = |sig: &str, pubkey: Vec<u8>, flags| {
+            verify(
+                vec![Command::Element(hex::decode(sig).unwrap())],
+                vec![Command::Element(pubkey), operation(0xac), operation(0x91)],
+                flags,
+            )
+        };
+        let strict = VerifyFlags::DERSIG | VerifyFlags::STRICTENC | VerifyFlags::LOW_S;
+        // r = 1, s = 1 is well formed but doesn't verify
+        let bad_sig = "300602010102010101";
+        assert_eq!(checksig_not(bad_sig, pubkey.clone(), strict), Ok(()));
+        assert_eq!(
+            checksig_not(bad_sig, pubkey.clone(), strict | VerifyFlags::NULLFAIL),
+            Err(ScriptError::SigNullFail)
+        );
+        assert_eq!(
+            checksig_not("", pubkey.clone(), strict | VerifyFlags::NULLFAIL),
+            Ok(())
+        );
+        assert_eq!(
+            checksig_not("300602010102010105", pubkey.clone(), strict),
+            Err(ScriptError::SigHashtype)
+        );
+        assert_eq!(
+            checksig_not("3007020101020201000001", pubkey.clone(), strict),
+            Err(ScriptError::SigDer)
+        );
+        // s = n - 1
+        let high_s =
+            "3026020101022100fffffffffffffffffffffffffffffffebaaedce6af48a03bbfd25e8cd036414001";
+        assert_eq!(
+            checksig_not(high_s, pubkey.clone(), VerifyFlags::DERSIG),
+            Ok(())
+        );
+        assert_eq!(
+            checksig_not(high_s, pubkey.clone(), VerifyFlags::LOW_S),
+            Err(ScriptError::SigHighS)
+        );
+        assert_eq!(checksig_not("", vec![0x05; 33], VerifyFlags::NONE), Ok(()));
+        assert_eq!(
+            checksig_not("", vec![0x05; 33], VerifyFlags::STRICTENC),
+            Err(ScriptError::PubkeyType)
+        );
+
+        assert!(VerifyFlags::STANDARD.contains(VerifyFlags::MANDATORY));
+        assert!(VerifyFlags::MANDATORY.contains(VerifyFlags::P2SH | VerifyFlags::WITNESS));
+        assert!(!VerifyFlags::MANDATORY.contains(VerifyFlags::CLEANSTACK));
+    }
+
     #[test]
     fn test_evaluate_script() {
         let z = Signature::signature_hash_from_hex(
@@ -210,11 +2053,30 @@ mod script_tests {
         let sig_encode = hex::decode(sig).unwrap();
         let cmd = vec![
             Command::Element(sec_encode),
-            Command::Operation(op::parse_raw_op_codes(0xac)), //OpVerify
+            Command::Operation(OpCodeFunctions::op_checksig()),
         ];
         let script_pubkey = Script::new(Some(cmd));
         let script_sig = Script::new(Some(vec![Command::Element(sig_encode)]));
         let combined_script = script_sig + script_pubkey;
         assert!(combined_script.evaluate(z))
     }
+
+    #[test]
+    fn test_p2wsh_address() {
+        let pubkey =
+            hex::decode("0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798")
+                .unwrap();
+        let witness_script = Script::new(Some(vec![
+            Command::Element(pubkey),
+            Command::Operation(OpCodeFunctions::op_checksig()),
+        ]));
+        assert_eq!(
+            witness_script.p2wsh_address(Network::Testnet),
+            "tb1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3q0sl5k7"
+        );
+        assert_eq!(
+            witness_script.p2wsh_address(Network::Mainnet),
+            "bc1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3qccfmv3"
+        );
+    }
 }
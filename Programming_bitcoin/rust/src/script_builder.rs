@@ -0,0 +1,136 @@
+use crate::{
+    op::{self, OpCodeFunctions},
+    s256_point::S256Point,
+    script::{Command, Script},
+    script_num::ScriptNum,
+};
+
+// Builds scripts one push at a time, always picking the smallest encoding:
+// OP_0, OP_1NEGATE and OP_1..OP_16 for small values, a direct push up to 75
+// bytes and OP_PUSHDATA1/2/4 beyond that.
+//
+//     let script = ScriptBuilder::new()
+//         .push_opcode(OpCodeFunctions::op_dup())
+//         .push_slice(&h160)
+//         .into_script();
+#[derive(Debug, Clone, Default)]
+pub struct ScriptBuilder {
+    cmds: Vec<Command>,
+}
+
+impl ScriptBuilder {
+    pub fn new() -> Self {
+        ScriptBuilder { cmds: Vec::new() }
+    }
+
+    pub fn push_opcode(mut self, op_code: OpCodeFunctions) -> Self {
+        self.cmds.push(Command::Operation(op_code));
+        self
+    }
+
+    pub fn push_slice(mut self, data: &[u8]) -> Self {
+        let cmd = match data {
+            [] => Command::Operation(OpCodeFunctions::op0()),
+            [0x81] => Command::Operation(OpCodeFunctions::Op1negate(0x4f)),
+            [byte @ 1..=16] => {
+                Command::Operation(op::parse_raw_op_codes(0x50 + *byte as u32).unwrap())
+            }
+            _ => Command::Element(data.to_vec()),
+        };
+        self.cmds.push(cmd);
+        self
+    }
+
+    pub fn push_int(self, value: i64) -> Self {
+        self.push_script_num(ScriptNum::new(value))
+    }
+
+    pub fn push_script_num(self, num: ScriptNum) -> Self {
+        self.push_slice(&num.encode())
+    }
+
+    // Pushes the key in SEC format.
+    pub fn push_key(self, key: &S256Point, compressed: bool) -> Self {
+        self.push_slice(&key.clone().sec(Some(compressed)))
+    }
+
+    pub fn into_script(self) -> Script {
+        Script::new(Some(self.cmds))
+    }
+}
+
+#[cfg(test)]
+mod script_builder_tests {
+    use crate::{op::OpCodeFunctions, script::Script, script_num::ScriptNum, G};
+
+    use super::ScriptBuilder;
+
+    fn raw_hex(script: Script) -> String {
+        hex::encode(script.raw_serialize())
+    }
+
+    #[test]
+    fn test_push_int() {
+        let script = ScriptBuilder::new()
+            .push_int(0)
+            .push_int(-1)
+            .push_int(1)
+            .push_int(16)
+            .push_int(17)
+            .push_int(-2)
+            .push_int(128)
+            .push_int(-32768)
+            .push_script_num(ScriptNum::new(500_000))
+            .into_script();
+        assert_eq!(raw_hex(script), "004f516001110182028000030080800320a107");
+    }
+
+    #[test]
+    fn test_push_slice() {
+        let script = ScriptBuilder::new()
+            .push_slice(&[])
+            .push_slice(&[0x81])
+            .push_slice(&[0x05])
+            .push_slice(&[0x11])
+            .push_slice(&[0x00])
+            .into_script();
+        assert_eq!(raw_hex(script), "004f5501110100");
+
+        // every length class gets the smallest push opcode
+        for (length, prefix) in [
+            (2, "02"),
+            (75, "4b"),
+            (76, "4c4c"),
+            (255, "4cff"),
+            (256, "4d0001"),
+            (520, "4d0802"),
+            (0xffff, "4dffff"),
+            (0x10000, "4e00000100"),
+        ] {
+            let data = vec![0xab; length];
+            let script = ScriptBuilder::new().push_slice(&data).into_script();
+            let raw = script.raw_serialize();
+            assert_eq!(hex::encode(&raw[..prefix.len() / 2]), prefix);
+            assert_eq!(&raw[prefix.len() / 2..], &data[..]);
+            assert_eq!(raw.len(), script.serialized_len());
+        }
+    }
+
+    #[test]
+    fn test_push_key() {
+        let g = "79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+        let script = ScriptBuilder::new()
+            .push_key(&G, true)
+            .push_opcode(OpCodeFunctions::op_checksig())
+            .into_script();
+        assert_eq!(raw_hex(script), format!("2102{}ac", g));
+        let script = ScriptBuilder::new().push_key(&G, false).into_script();
+        assert_eq!(
+            raw_hex(script),
+            format!(
+                "4104{}483ada7726a3c4655da4fbfc0e1108a8fd17b448a68554199c47d08ffb10d4b8",
+                g
+            )
+        );
+    }
+}
@@ -0,0 +1,197 @@
+use std::ops::{Add, Neg, Sub};
+
+use crate::error::Error;
+
+// Operands of the arithmetic opcodes are limited to 4 bytes.
+pub const DEFAULT_MAX_NUM_SIZE: usize = 4;
+// CHECKLOCKTIMEVERIFY and CHECKSEQUENCEVERIFY accept 5 bytes to reach 2^39 - 1.
+pub const LOCKTIME_MAX_NUM_SIZE: usize = 5;
+
+// A number on the script stack, stored little-endian with a sign bit in the
+// most significant byte (CScriptNum in Bitcoin Core). The value is kept as an
+// i64 so results of arithmetic on 4-byte operands never overflow; they may be
+// pushed back but fail to decode as operands again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ScriptNum(i64);
+
+impl ScriptNum {
+    pub fn new(value: i64) -> Self {
+        ScriptNum(value)
+    }
+
+    pub fn value(&self) -> i64 {
+        self.0
+    }
+
+    // Saturates to the i32 range like CScriptNum::getint.
+    pub fn to_i32(&self) -> i32 {
+        self.0.clamp(i32::MIN as i64, i32::MAX as i64) as i32
+    }
+
+    pub fn decode(element: &[u8], require_minimal: bool, max_size: usize) -> Result<Self, Error> {
+        if element.len() > max_size {
+            return Err(Error::BadScriptNum("overflow"));
+        }
+        if require_minimal && !is_minimally_encoded(element) {
+            return Err(Error::BadScriptNum("non-minimally encoded"));
+        }
+        let last = match element.last() {
+            Some(last) => *last,
+            None => return Ok(ScriptNum(0)),
+        };
+        let mut result: i64 = 0;
+        for (i, byte) in element.iter().enumerate() {
+            result |= (*byte as i64) << (8 * i);
+        }
+        if last & 0x80 != 0 {
+            let sign_bit = 0x80_i64 << (8 * (element.len() - 1));
+            return Ok(ScriptNum(-(result & !sign_bit)));
+        }
+        Ok(ScriptNum(result))
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut result = Vec::new();
+        let negative = self.0 < 0;
+        let mut abs_value = self.0.unsigned_abs();
+        while abs_value > 0 {
+            result.push((abs_value & 0xff) as u8);
+            abs_value >>= 8;
+        }
+        // the top bit is the sign, add a byte if the magnitude already uses it
+        if let Some(last) = result.last_mut() {
+            if *last & 0x80 != 0 {
+                result.push(if negative { 0x80 } else { 0x00 });
+            } else if negative {
+                *last |= 0x80;
+            }
+        }
+        result
+    }
+}
+
+// No zero byte at the end unless the byte before it needs its top bit for the
+// magnitude, which also rules out negative zero.
+pub fn is_minimally_encoded(element: &[u8]) -> bool {
+    match element {
+        [] => true,
+        [.., last] if last & 0x7f != 0 => true,
+        [_] => false,
+        [.., before_last, _] => before_last & 0x80 != 0,
+    }
+}
+
+impl From<i64> for ScriptNum {
+    fn from(value: i64) -> Self {
+        ScriptNum(value)
+    }
+}
+
+impl Add for ScriptNum {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        ScriptNum(self.0 + rhs.0)
+    }
+}
+
+impl Sub for ScriptNum {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        ScriptNum(self.0 - rhs.0)
+    }
+}
+
+impl Neg for ScriptNum {
+    type Output = Self;
+
+    fn neg(self) -> Self::Output {
+        ScriptNum(-self.0)
+    }
+}
+
+#[cfg(test)]
+mod script_num_tests {
+    use crate::error::Error;
+
+    use super::{is_minimally_encoded, ScriptNum, DEFAULT_MAX_NUM_SIZE, LOCKTIME_MAX_NUM_SIZE};
+
+    #[test]
+    fn test_encode() {
+        for (value, encoded) in [
+            (0, ""),
+            (1, "01"),
+            (2, "02"),
+            (-1, "81"),
+            (127, "7f"),
+            (128, "8000"),
+            (-128, "8080"),
+            (255, "ff00"),
+            (-255, "ff80"),
+            (999, "e703"),
+            (0x7fffffff, "ffffff7f"),
+            (-0x7fffffff, "ffffffff"),
+            (0x80000000, "0000008000"),
+            (-0x80000000, "0000008080"),
+        ] {
+            assert_eq!(hex::encode(ScriptNum::new(value).encode()), encoded);
+            let decoded =
+                ScriptNum::decode(&hex::decode(encoded).unwrap(), true, LOCKTIME_MAX_NUM_SIZE);
+            assert_eq!(decoded.unwrap().value(), value);
+        }
+    }
+
+    #[test]
+    fn test_decode() {
+        let decode = |raw: &str, require_minimal| {
+            ScriptNum::decode(
+                &hex::decode(raw).unwrap(),
+                require_minimal,
+                DEFAULT_MAX_NUM_SIZE,
+            )
+        };
+        assert_eq!(decode("01", true).unwrap().value(), 1);
+        assert_eq!(decode("e703", true).unwrap().value(), 999);
+        assert_eq!(decode("", true).unwrap().value(), 0);
+        // padded and negative zero forms only decode without the minimal check
+        assert_eq!(decode("0100", false).unwrap().value(), 1);
+        assert_eq!(decode("0180", false).unwrap().value(), -1);
+        assert_eq!(decode("80", false).unwrap().value(), 0);
+        assert!(matches!(decode("0100", true), Err(Error::BadScriptNum(_))));
+        assert!(matches!(decode("80", true), Err(Error::BadScriptNum(_))));
+        assert!(matches!(
+            decode("0000008000", false),
+            Err(Error::BadScriptNum(_))
+        ));
+    }
+
+    #[test]
+    fn test_is_minimally_encoded() {
+        for (raw, minimal) in [
+            ("", true),
+            ("00", false),
+            ("80", false),
+            ("01", true),
+            ("0100", false),
+            ("8000", true),
+            ("8080", true),
+            ("0080", false),
+            ("ff7f", true),
+        ] {
+            assert_eq!(
+                is_minimally_encoded(&hex::decode(raw).unwrap()),
+                minimal,
+                "{}",
+                raw
+            );
+        }
+    }
+
+    #[test]
+    fn test_to_i32() {
+        assert_eq!(ScriptNum::new(0x1_0000_0000).to_i32(), i32::MAX);
+        assert_eq!(ScriptNum::new(-0x1_0000_0000).to_i32(), i32::MIN);
+        assert_eq!((ScriptNum::new(5) - ScriptNum::new(7)).to_i32(), -2);
+    }
+}
@@ -6,8 +6,14 @@ use std::{
 use byteorder::{BigEndian, ByteOrder};
 use num_bigint::BigInt;
 
-use crate::utils;
+use crate::{error::Error, utils};
 
+pub const SIGHASH_ALL: u32 = 1;
+pub const SIGHASH_NONE: u32 = 2;
+pub const SIGHASH_SINGLE: u32 = 3;
+pub const SIGHASH_ANYONECANPAY: u32 = 0x80;
+
+#[derive(Debug, Clone)]
 pub struct SignatureHash(BigInt);
 
 impl AsRef<BigInt> for SignatureHash {
@@ -26,47 +32,47 @@ impl Signature {
         Signature { r, s }
     }
 
-    pub fn parse<R: Read + Seek>(stream: &mut R) -> Self {
+    pub fn parse<R: Read + Seek>(stream: &mut R) -> Result<Self, Error> {
         let mut compound_buffer = [0; 1];
-        stream.read_exact(&mut compound_buffer).unwrap();
+        stream.read_exact(&mut compound_buffer)?;
         let compound = compound_buffer[0];
         if compound != 0x30 {
-            panic!("Bad signature")
+            return Err(Error::BadDer("missing sequence marker"));
         }
         let mut length_buffer = [0; 1];
-        stream.read_exact(&mut length_buffer).unwrap();
+        stream.read_exact(&mut length_buffer)?;
         let length = (BigEndian::read_u32(&[0, 0, 0, length_buffer[0]]) + 2) as u32;
         // let len = stream.stream_len().unwrap();
         let mut marker_buffer = [0; 1];
-        stream.read_exact(&mut marker_buffer).unwrap();
+        stream.read_exact(&mut marker_buffer)?;
         if marker_buffer[0] != 0x02 {
-            panic!("Bad signature")
+            return Err(Error::BadDer("missing integer marker for r"));
         }
         let mut rlength_buffer = [0; 1];
-        stream.read_exact(&mut rlength_buffer).unwrap();
+        stream.read_exact(&mut rlength_buffer)?;
         let rlenght = BigEndian::read_u32(&[0, 0, 0, rlength_buffer[0]]);
-        let mut r_buffer = vec![0; rlenght.try_into().unwrap()];
-        stream.read_exact(&mut r_buffer).unwrap();
+        let mut r_buffer = vec![0; rlenght as usize];
+        stream.read_exact(&mut r_buffer)?;
         let r = BigInt::from_bytes_be(num_bigint::Sign::Plus, &r_buffer);
         let mut marker_buffer = [0; 1];
-        stream.read_exact(&mut marker_buffer).unwrap();
+        stream.read_exact(&mut marker_buffer)?;
         if marker_buffer[0] != 0x02 {
-            panic!("Bad signature")
+            return Err(Error::BadDer("missing integer marker for s"));
         }
         let mut slength_buffer = [0; 1];
-        stream.read_exact(&mut slength_buffer).unwrap();
+        stream.read_exact(&mut slength_buffer)?;
         let slenght = BigEndian::read_u32(&[0, 0, 0, slength_buffer[0]]);
 
         // 4 -> marker + len
         // 2 -> compound and total len
         if slenght + rlenght + 4 + 2 != length {
-            panic!("Bad signature length")
+            return Err(Error::BadDer("length does not match its integers"));
         }
 
-        let mut s_buffer = vec![0; slenght.try_into().unwrap()];
-        stream.read_exact(&mut s_buffer).unwrap();
+        let mut s_buffer = vec![0; slenght as usize];
+        stream.read_exact(&mut s_buffer)?;
         let s = BigInt::from_bytes_be(num_bigint::Sign::Plus, &s_buffer);
-        Signature { r, s }
+        Ok(Signature { r, s })
     }
 
     pub fn der(&self) -> Vec<u8> {
@@ -141,6 +147,8 @@ mod signature_tests {
 
     use num_bigint::BigInt;
 
+    use crate::error::Error;
+
     use super::Signature;
 
     #[test]
@@ -175,8 +183,28 @@ mod signature_tests {
         let sig = "3045022037206a0610995c58074999cb9767b87af4c4978db68c06e8e6e81d282047a7c60221008ca63759c1157ebeaec0d03cecca119fc9a75bf8e6d0fa65c841c8e2738cdaec";
         let sig_encode = hex::decode(sig).unwrap();
         let mut cursor_sig = Cursor::new(sig_encode);
-        let sig_parsed = Signature::parse(&mut cursor_sig);
+        let sig_parsed = Signature::parse(&mut cursor_sig).unwrap();
         assert_eq!(sig_parsed.r, r);
         assert_eq!(sig_parsed.s, s);
     }
+
+    #[test]
+    fn test_parse_bad_signature() {
+        let sig = "3045022037206a0610995c58074999cb9767b87af4c4978db68c06e8e6e81d282047a7c60221008ca63759c1157ebeaec0d03cecca119fc9a75bf8e6d0fa65c841c8e2738cdaec";
+        let mut cursor_sig = Cursor::new(hex::decode(&sig[..80]).unwrap());
+        assert!(matches!(
+            Signature::parse(&mut cursor_sig),
+            Err(Error::Truncated)
+        ));
+        let mut cursor_sig = Cursor::new(hex::decode(sig.replacen("30", "31", 1)).unwrap());
+        assert!(matches!(
+            Signature::parse(&mut cursor_sig),
+            Err(Error::BadDer(_))
+        ));
+        let mut cursor_sig = Cursor::new(hex::decode(sig.replacen("3045", "3046", 1)).unwrap());
+        assert!(matches!(
+            Signature::parse(&mut cursor_sig),
+            Err(Error::BadDer(_))
+        ));
+    }
 }
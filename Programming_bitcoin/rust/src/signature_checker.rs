@@ -0,0 +1,292 @@
+use std::io::Cursor;
+
+use crate::{
+    s256_point::S256Point,
+    script::{Script, TxContext},
+    signature::{Signature, SignatureHash},
+    tx::Tx,
+};
+
+// The rules a signature is checked under. Only legacy scripts are executed
+// so far, witness v0 signatures commit to a BIP143 hash instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SigVersion {
+    Base,
+    WitnessV0,
+}
+
+// Everything the interpreter needs to know about the spending transaction,
+// like Bitcoin Core's BaseSignatureChecker. The interpreter takes care of
+// encodings and stack rules, the checker of what a signature commits to.
+// Without a transaction every check fails.
+pub trait SignatureChecker {
+    // sig still ends with its hashtype byte and script_code already went
+    // through OP_CODESEPARATOR and FindAndDelete.
+    fn check_sig(
+        &self,
+        sig: &[u8],
+        pubkey: &[u8],
+        script_code: &Script,
+        sig_version: SigVersion,
+    ) -> bool;
+
+    fn check_lock_time(&self, _lock_time: i64) -> bool {
+        false
+    }
+
+    fn check_sequence(&self, _sequence: i64) -> bool {
+        false
+    }
+}
+
+// Rejects every signature and lock time.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoSignatureChecker;
+
+impl SignatureChecker for NoSignatureChecker {
+    fn check_sig(&self, _: &[u8], _: &[u8], _: &Script, _: SigVersion) -> bool {
+        false
+    }
+}
+
+// Verifies every signature against one hash computed up front, whatever its
+// hashtype, for scripts evaluated without a transaction.
+#[derive(Debug, Clone)]
+pub struct SignatureHashChecker {
+    z: SignatureHash,
+}
+
+impl SignatureHashChecker {
+    pub fn new(z: SignatureHash) -> Self {
+        SignatureHashChecker { z }
+    }
+}
+
+impl SignatureChecker for SignatureHashChecker {
+    fn check_sig(&self, sig: &[u8], pubkey: &[u8], _: &Script, _: SigVersion) -> bool {
+        verify_signature(sig, pubkey, &self.z)
+    }
+}
+
+// Verifies signatures against the legacy signature hash of an input for the
+// hashtype in their last byte, and lock times against the transaction.
+#[derive(Debug, Clone, Copy)]
+pub struct TransactionSignatureChecker<'a> {
+    tx_context: TxContext<'a>,
+}
+
+impl<'a> TransactionSignatureChecker<'a> {
+    pub fn new(tx: &'a Tx, input_index: usize) -> Self {
+        TransactionSignatureChecker {
+            tx_context: TxContext::new(tx, input_index),
+        }
+    }
+}
+
+impl SignatureChecker for TransactionSignatureChecker<'_> {
+    fn check_sig(
+        &self,
+        sig: &[u8],
+        pubkey: &[u8],
+        script_code: &Script,
+        sig_version: SigVersion,
+    ) -> bool {
+        let hash_type = match sig.last() {
+            Some(hash_type) if sig_version == SigVersion::Base => *hash_type as u32,
+            _ => return false,
+        };
+        match self.tx_context.tx.sig_hash(
+            self.tx_context.input_index,
+            Some(script_code.clone()),
+            hash_type,
+        ) {
+            Ok(z) => verify_signature(sig, pubkey, &Signature::signature_hash_from_int(z)),
+            Err(_) => false,
+        }
+    }
+
+    fn check_lock_time(&self, lock_time: i64) -> bool {
+        self.tx_context.check_lock_time(lock_time)
+    }
+
+    fn check_sequence(&self, sequence: i64) -> bool {
+        self.tx_context.check_sequence(sequence)
+    }
+}
+
+// Accepts exactly the signature and key pairs it was given, and lock times
+// only when told to, so script logic can be tested without signing anything.
+#[derive(Debug, Clone, Default)]
+pub struct MockSignatureChecker {
+    signatures: Vec<(Vec<u8>, Vec<u8>)>,
+    lock_times: bool,
+}
+
+impl MockSignatureChecker {
+    pub fn new() -> Self {
+        MockSignatureChecker::default()
+    }
+
+    pub fn accept(mut self, sig: &[u8], pubkey: &[u8]) -> Self {
+        self.signatures.push((sig.to_vec(), pubkey.to_vec()));
+        self
+    }
+
+    pub fn accept_lock_times(mut self) -> Self {
+        self.lock_times = true;
+        self
+    }
+}
+
+impl SignatureChecker for MockSignatureChecker {
+    fn check_sig(&self, sig: &[u8], pubkey: &[u8], _: &Script, _: SigVersion) -> bool {
+        self.signatures
+            .iter()
+            .any(|(valid_sig, valid_pubkey)| valid_sig == sig && valid_pubkey == pubkey)
+    }
+
+    fn check_lock_time(&self, _: i64) -> bool {
+        self.lock_times
+    }
+
+    fn check_sequence(&self, _: i64) -> bool {
+        self.lock_times
+    }
+}
+
+// An undecodable key or signature simply fails the check.
+fn verify_signature(der_signature: &[u8], sec_pubkey: &[u8], z: &SignatureHash) -> bool {
+    let mut der_signature_cursor = Cursor::new(der_signature);
+    match (
+        S256Point::parse(sec_pubkey),
+        Signature::parse(&mut der_signature_cursor),
+    ) {
+        (Ok(point), Ok(sig)) => point.verify(z, sig),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod signature_checker_tests {
+    use num_bigint::BigInt;
+
+    use crate::{
+        network::Network,
+        script::{Command, Script, VerifyFlags},
+        script_error::ScriptError,
+        tx::{Tx, TxIn},
+    };
+
+    use super::{
+        MockSignatureChecker, NoSignatureChecker, SigVersion, SignatureChecker,
+        TransactionSignatureChecker,
+    };
+
+    const SIG: &str = "3006020101020101";
+    const PUBKEY_A: &str = "02aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa";
+    const PUBKEY_B: &str = "03bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb";
+
+    fn verify(
+        script_sig: &str,
+        script_pubkey: &str,
+        checker: &dyn SignatureChecker,
+    ) -> Result<(), ScriptError> {
+        Script::verify_script(
+            &Script::from_asm(script_sig).unwrap(),
+            &Script::from_asm(script_pubkey).unwrap(),
+            checker,
+            VerifyFlags::MANDATORY | VerifyFlags::CHECKLOCKTIMEVERIFY,
+        )
+    }
+
+    // A push of the hex data as Display writes it.
+    fn push(data: &str) -> String {
+        let script = Script::new(Some(vec![Command::Element(hex::decode(data).unwrap())]));
+        script.to_string().trim_end().to_string()
+    }
+
+    #[test]
+    fn test_mock_checker_multisig() {
+        let sig = format!("{}01", SIG);
+        // all digits, bare it would read as a number out of range
+        assert!(Script::from_asm(&sig).is_err());
+        assert_eq!(push(&sig), "0x09300602010102010101");
+        let script_pubkey = format!("1 {} {} 2 OP_CHECKMULTISIG", PUBKEY_A, PUBKEY_B);
+        let script_sig = format!("0 {}", push(&sig));
+        let checker = MockSignatureChecker::new()
+            .accept(&hex::decode(&sig).unwrap(), &hex::decode(PUBKEY_B).unwrap());
+        assert_eq!(verify(&script_sig, &script_pubkey, &checker), Ok(()));
+        // two of two needs the signatures in key order
+        let two_of_two = format!("2 {} {} 2 OP_CHECKMULTISIG", PUBKEY_A, PUBKEY_B);
+        let sig_a = format!("{}02", SIG);
+        let checker = checker.accept(
+            &hex::decode(&sig_a).unwrap(),
+            &hex::decode(PUBKEY_A).unwrap(),
+        );
+        assert_eq!(
+            verify(
+                &format!("0 {} {}", push(&sig_a), push(&sig)),
+                &two_of_two,
+                &checker
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            verify(
+                &format!("0 {} {}", push(&sig), push(&sig_a)),
+                &two_of_two,
+                &checker
+            ),
+            Err(ScriptError::EvalFalse)
+        );
+        let checker = MockSignatureChecker::new();
+        assert_eq!(
+            verify(&script_sig, &script_pubkey, &checker),
+            Err(ScriptError::EvalFalse)
+        );
+        assert_eq!(
+            verify(&script_sig, &script_pubkey, &NoSignatureChecker),
+            Err(ScriptError::EvalFalse)
+        );
+    }
+
+    #[test]
+    fn test_mock_checker_lock_time() {
+        let script_pubkey = "500000 OP_CHECKLOCKTIMEVERIFY";
+        assert_eq!(
+            verify(
+                "",
+                script_pubkey,
+                &MockSignatureChecker::new().accept_lock_times()
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            verify("", script_pubkey, &MockSignatureChecker::new()),
+            Err(ScriptError::UnsatisfiedLocktime)
+        );
+        assert_eq!(
+            verify("", script_pubkey, &NoSignatureChecker),
+            Err(ScriptError::UnsatisfiedLocktime)
+        );
+    }
+
+    #[test]
+    fn test_witness_v0_rejected() {
+        let tx_in = TxIn::new(vec![0; 32], BigInt::from(0), None, None);
+        let tx = Tx::new(
+            BigInt::from(1),
+            vec![tx_in],
+            vec![],
+            BigInt::from(0),
+            Network::Mainnet,
+        );
+        let checker = TransactionSignatureChecker::new(&tx, 0);
+        let sig = hex::decode(format!("{}01", SIG)).unwrap();
+        let pubkey = hex::decode(PUBKEY_A).unwrap();
+        assert!(!checker.check_sig(&sig, &pubkey, &Script::new(None), SigVersion::WitnessV0));
+        assert!(!checker.check_sig(&[], &pubkey, &Script::new(None), SigVersion::Base));
+        let mock = MockSignatureChecker::new().accept(&sig, &pubkey);
+        assert!(mock.check_sig(&sig, &pubkey, &Script::new(None), SigVersion::Base));
+    }
+}
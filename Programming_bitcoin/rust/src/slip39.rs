@@ -0,0 +1,749 @@
+use std::{collections::BTreeMap, fmt::Display};
+
+use anyhow::{bail, Result};
+use once_cell::sync::Lazy;
+use rand::{Rng, RngCore};
+
+use crate::utils;
+
+const RADIX_BITS: usize = 10;
+const ID_LENGTH_BITS: usize = 15;
+const MAX_SHARE_COUNT: u8 = 16;
+const CHECKSUM_WORDS: usize = 3;
+const MIN_MNEMONIC_WORDS: usize = 20;
+const MIN_SECRET_BYTES: usize = 16;
+const DIGEST_INDEX: u8 = 254;
+const SECRET_INDEX: u8 = 255;
+const BASE_ITERATION_COUNT: u32 = 10000;
+const ROUND_COUNT: u8 = 4;
+
+const RS1024_GENERATOR: [u32; 10] = [
+    0xe0e040, 0x1c1c080, 0x3838100, 0x7070200, 0xe0e0009, 0x1c0c2412, 0x38086c24, 0x3090fc48,
+    0x21b1f890, 0x3f3f120,
+];
+
+pub static SLIP39_WORDLIST: Lazy<Vec<&'static str>> = Lazy::new(|| {
+    include_str!("wordlists/slip39_english.txt")
+        .lines()
+        .collect()
+});
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Share {
+    pub identifier: u16,
+    pub extendable: bool,
+    pub iteration_exponent: u8,
+    pub group_index: u8,
+    pub group_threshold: u8,
+    pub group_count: u8,
+    pub member_index: u8,
+    pub member_threshold: u8,
+    pub value: Vec<u8>,
+}
+
+impl Share {
+    pub fn parse(mnemonic: &str) -> Result<Self> {
+        let mut indexes: Vec<u32> = Vec::new();
+        for word in mnemonic.split_whitespace() {
+            match SLIP39_WORDLIST.binary_search(&word.to_lowercase().as_str()) {
+                Ok(index) => indexes.push(index as u32),
+                Err(_) => bail!("unknown mnemonic word: {}", word),
+            }
+        }
+        if indexes.len() < MIN_MNEMONIC_WORDS {
+            bail!(
+                "mnemonic must have at least {} words, got {}",
+                MIN_MNEMONIC_WORDS,
+                indexes.len()
+            )
+        }
+        let id_exp = (indexes[0] << RADIX_BITS) | indexes[1];
+        let extendable = (id_exp >> 4) & 1 == 1;
+        if rs1024_polymod(&customization(extendable), &indexes) != 1 {
+            bail!("invalid mnemonic checksum")
+        }
+        let params = (indexes[2] << RADIX_BITS) | indexes[3];
+        let group_threshold = ((params >> 12) & 15) as u8 + 1;
+        let group_count = ((params >> 8) & 15) as u8 + 1;
+        if group_threshold > group_count {
+            bail!("group threshold cannot be greater than the group count")
+        }
+
+        let value_words = &indexes[4..indexes.len() - CHECKSUM_WORDS];
+        let padding = value_words.len() * RADIX_BITS % 16;
+        if padding > 8 {
+            bail!("invalid mnemonic length")
+        }
+        let mut bits: Vec<bool> = Vec::new();
+        for index in value_words {
+            for i in (0..RADIX_BITS).rev() {
+                bits.push((index >> i) & 1 == 1);
+            }
+        }
+        if bits[..padding].iter().any(|bit| *bit) {
+            bail!("invalid mnemonic padding")
+        }
+        let value: Vec<u8> = bits[padding..]
+            .chunks(8)
+            .map(|chunk| chunk.iter().fold(0_u8, |acc, bit| (acc << 1) | *bit as u8))
+            .collect();
+        if value.len() < MIN_SECRET_BYTES {
+            bail!("share value must be at least {} bytes", MIN_SECRET_BYTES)
+        }
+
+        Ok(Share {
+            identifier: (id_exp >> 5) as u16,
+            extendable,
+            iteration_exponent: (id_exp & 15) as u8,
+            group_index: (params >> 16) as u8,
+            group_threshold,
+            group_count,
+            member_index: ((params >> 4) & 15) as u8,
+            member_threshold: (params & 15) as u8 + 1,
+            value,
+        })
+    }
+
+    pub fn words(&self) -> Vec<String> {
+        let id_exp = ((self.identifier as u32) << 5)
+            | ((self.extendable as u32) << 4)
+            | self.iteration_exponent as u32;
+        let params = ((self.group_index as u32) << 16)
+            | ((self.group_threshold as u32 - 1) << 12)
+            | ((self.group_count as u32 - 1) << 8)
+            | ((self.member_index as u32) << 4)
+            | (self.member_threshold as u32 - 1);
+        let mut indexes = vec![
+            id_exp >> RADIX_BITS,
+            id_exp & 1023,
+            params >> RADIX_BITS,
+            params & 1023,
+        ];
+
+        let value_word_count = (self.value.len() * 8).div_ceil(RADIX_BITS);
+        let mut bits = vec![false; value_word_count * RADIX_BITS - self.value.len() * 8];
+        for byte in &self.value {
+            for i in (0..8).rev() {
+                bits.push((byte >> i) & 1 == 1);
+            }
+        }
+        indexes.extend(bits.chunks(RADIX_BITS).map(|chunk| {
+            chunk
+                .iter()
+                .fold(0_u32, |acc, bit| (acc << 1) | *bit as u32)
+        }));
+
+        let checksum = rs1024_create_checksum(&customization(self.extendable), &indexes);
+        indexes
+            .iter()
+            .chain(checksum.iter())
+            .map(|index| SLIP39_WORDLIST[*index as usize].to_string())
+            .collect()
+    }
+}
+
+impl Display for Share {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.words().join(" "))
+    }
+}
+
+// `groups` holds a (member_threshold, member_count) pair for every group.
+pub fn split_secret(
+    master_secret: &[u8],
+    passphrase: &str,
+    group_threshold: u8,
+    groups: &[(u8, u8)],
+    iteration_exponent: u8,
+    extendable: bool,
+) -> Result<Vec<Vec<Share>>> {
+    if master_secret.len() < MIN_SECRET_BYTES || !master_secret.len().is_multiple_of(2) {
+        bail!("master secret must be an even number of bytes, at least 16")
+    }
+    if groups.is_empty() || groups.len() > MAX_SHARE_COUNT as usize {
+        bail!("the number of groups must be between 1 and 16")
+    }
+    if group_threshold == 0 || group_threshold as usize > groups.len() {
+        bail!("group threshold must be between 1 and the number of groups")
+    }
+    if iteration_exponent > 15 {
+        bail!("iteration exponent must be at most 15")
+    }
+    for (member_threshold, member_count) in groups {
+        if *member_threshold == 0 || member_threshold > member_count {
+            bail!("member threshold must be between 1 and the member count")
+        }
+        if *member_count > MAX_SHARE_COUNT {
+            bail!("a group can have at most 16 members")
+        }
+        if *member_threshold == 1 && *member_count > 1 {
+            bail!("creating multiple member shares with member threshold 1 is not allowed")
+        }
+    }
+
+    let identifier: u16 = rand::thread_rng().gen_range(0..1 << ID_LENGTH_BITS);
+    let encrypted = encrypt(
+        master_secret,
+        passphrase,
+        iteration_exponent,
+        identifier,
+        extendable,
+    )?;
+    let group_shares = split(group_threshold, groups.len() as u8, &encrypted)?;
+
+    let mut result: Vec<Vec<Share>> = Vec::new();
+    for ((group_index, group_secret), (member_threshold, member_count)) in
+        group_shares.into_iter().zip(groups)
+    {
+        let members = split(*member_threshold, *member_count, &group_secret)?
+            .into_iter()
+            .map(|(member_index, value)| Share {
+                identifier,
+                extendable,
+                iteration_exponent,
+                group_index,
+                group_threshold,
+                group_count: groups.len() as u8,
+                member_index,
+                member_threshold: *member_threshold,
+                value,
+            })
+            .collect();
+        result.push(members);
+    }
+    Ok(result)
+}
+
+pub fn combine_shares(shares: &[Share], passphrase: &str) -> Result<Vec<u8>> {
+    let first = match shares.first() {
+        Some(first) => first,
+        None => bail!("no shares were provided"),
+    };
+    let mut groups: BTreeMap<u8, Vec<&Share>> = BTreeMap::new();
+    for share in shares {
+        if share.identifier != first.identifier
+            || share.extendable != first.extendable
+            || share.iteration_exponent != first.iteration_exponent
+        {
+            bail!("all shares must belong to the same secret")
+        }
+        if share.group_threshold != first.group_threshold || share.group_count != first.group_count
+        {
+            bail!("all shares must have the same group parameters")
+        }
+        if share.value.len() != first.value.len() {
+            bail!("all shares must have the same length")
+        }
+        groups.entry(share.group_index).or_default().push(share);
+    }
+    if groups.len() < first.group_threshold as usize {
+        bail!(
+            "insufficient number of groups: {} of {} required",
+            groups.len(),
+            first.group_threshold
+        )
+    }
+    if groups.len() > first.group_threshold as usize {
+        bail!("too many groups were provided")
+    }
+
+    let mut group_secrets: Vec<(u8, Vec<u8>)> = Vec::new();
+    for (group_index, members) in groups {
+        let member_threshold = members[0].member_threshold;
+        if members
+            .iter()
+            .any(|share| share.member_threshold != member_threshold)
+        {
+            bail!(
+                "all shares in group {} must have the same member threshold",
+                group_index
+            )
+        }
+        let mut member_shares: Vec<(u8, Vec<u8>)> = Vec::new();
+        for share in members {
+            if member_shares.iter().any(|(x, _)| *x == share.member_index) {
+                bail!(
+                    "duplicate member index {} in group {}",
+                    share.member_index,
+                    group_index
+                )
+            }
+            member_shares.push((share.member_index, share.value.clone()));
+        }
+        if member_shares.len() != member_threshold as usize {
+            bail!(
+                "group {} needs exactly {} shares, got {}",
+                group_index,
+                member_threshold,
+                member_shares.len()
+            )
+        }
+        group_secrets.push((group_index, recover(member_threshold, &member_shares)?));
+    }
+    let encrypted = recover(first.group_threshold, &group_secrets)?;
+    decrypt(
+        &encrypted,
+        passphrase,
+        first.iteration_exponent,
+        first.identifier,
+        first.extendable,
+    )
+}
+
+pub fn combine_mnemonics(mnemonics: &[&str], passphrase: &str) -> Result<Vec<u8>> {
+    let shares = mnemonics
+        .iter()
+        .map(|mnemonic| Share::parse(mnemonic))
+        .collect::<Result<Vec<Share>>>()?;
+    combine_shares(&shares, passphrase)
+}
+
+fn customization(extendable: bool) -> Vec<u32> {
+    let name: &[u8] = if extendable {
+        b"shamir_extendable"
+    } else {
+        b"shamir"
+    };
+    name.iter().map(|c| *c as u32).collect()
+}
+
+fn rs1024_polymod(customization: &[u32], values: &[u32]) -> u32 {
+    let mut chk: u32 = 1;
+    for value in customization.iter().chain(values) {
+        let top = chk >> 20;
+        chk = ((chk & 0xfffff) << 10) ^ value;
+        for (i, g) in RS1024_GENERATOR.iter().enumerate() {
+            if (top >> i) & 1 == 1 {
+                chk ^= g;
+            }
+        }
+    }
+    chk
+}
+
+fn rs1024_create_checksum(customization: &[u32], values: &[u32]) -> Vec<u32> {
+    let padded = [values, &[0; CHECKSUM_WORDS]].concat();
+    let polymod = rs1024_polymod(customization, &padded) ^ 1;
+    (0..CHECKSUM_WORDS)
+        .map(|i| (polymod >> (RADIX_BITS * (CHECKSUM_WORDS - 1 - i))) & 1023)
+        .collect()
+}
+
+// GF(256) with the Rijndael polynomial x^8 + x^4 + x^3 + x + 1, addition is xor.
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut result = 0;
+    while b > 0 {
+        if b & 1 == 1 {
+            result ^= a;
+        }
+        let carry = a & 0x80;
+        a <<= 1;
+        if carry != 0 {
+            a ^= 0x1b;
+        }
+        b >>= 1;
+    }
+    result
+}
+
+fn gf_inv(a: u8) -> u8 {
+    // a^254 is the inverse since a^255 = 1 for every non-zero element
+    let mut result = 1;
+    for _ in 0..254 {
+        result = gf_mul(result, a);
+    }
+    result
+}
+
+fn interpolate(shares: &[(u8, Vec<u8>)], x: u8) -> Result<Vec<u8>> {
+    if let Some((_, value)) = shares.iter().find(|(xi, _)| *xi == x) {
+        return Ok(value.clone());
+    }
+    let length = shares[0].1.len();
+    if shares.iter().any(|(_, value)| value.len() != length) {
+        bail!("all shares must have the same length")
+    }
+    let mut result = vec![0; length];
+    for (i, (xi, value)) in shares.iter().enumerate() {
+        let mut basis = 1;
+        for (j, (xj, _)) in shares.iter().enumerate() {
+            if i != j {
+                basis = gf_mul(basis, gf_mul(x ^ xj, gf_inv(xi ^ xj)));
+            }
+        }
+        for (r, v) in result.iter_mut().zip(value) {
+            *r ^= gf_mul(basis, *v);
+        }
+    }
+    Ok(result)
+}
+
+fn split(threshold: u8, count: u8, secret: &[u8]) -> Result<Vec<(u8, Vec<u8>)>> {
+    if threshold == 1 {
+        return Ok((0..count).map(|i| (i, secret.to_vec())).collect());
+    }
+    let mut rng = rand::thread_rng();
+    let random_share_count = threshold - 2;
+    let mut shares: Vec<(u8, Vec<u8>)> = (0..random_share_count)
+        .map(|i| {
+            let mut value = vec![0; secret.len()];
+            rng.fill_bytes(&mut value);
+            (i, value)
+        })
+        .collect();
+    let mut random_part = vec![0; secret.len() - 4];
+    rng.fill_bytes(&mut random_part);
+    let digest = [
+        &utils::hmac_sha256(&random_part, secret)[..4],
+        &random_part[..],
+    ]
+    .concat();
+    let mut base_shares = shares.clone();
+    base_shares.push((DIGEST_INDEX, digest));
+    base_shares.push((SECRET_INDEX, secret.to_vec()));
+    for i in random_share_count..count {
+        shares.push((i, interpolate(&base_shares, i)?));
+    }
+    Ok(shares)
+}
+
+fn recover(threshold: u8, shares: &[(u8, Vec<u8>)]) -> Result<Vec<u8>> {
+    if threshold == 1 {
+        return Ok(shares[0].1.clone());
+    }
+    let secret = interpolate(shares, SECRET_INDEX)?;
+    let digest = interpolate(shares, DIGEST_INDEX)?;
+    if utils::hmac_sha256(&digest[4..], &secret)[..4] != digest[..4] {
+        bail!("invalid digest of the shared secret")
+    }
+    Ok(secret)
+}
+
+fn feistel_round(
+    round: u8,
+    passphrase: &str,
+    iteration_exponent: u8,
+    salt: &[u8],
+    r: &[u8],
+) -> Vec<u8> {
+    utils::pbkdf2_hmac_sha256(
+        &[&[round], passphrase.as_bytes()].concat(),
+        &[salt, r].concat(),
+        (BASE_ITERATION_COUNT << iteration_exponent) / ROUND_COUNT as u32,
+        r.len(),
+    )
+}
+
+fn feistel(
+    secret: &[u8],
+    passphrase: &str,
+    iteration_exponent: u8,
+    identifier: u16,
+    extendable: bool,
+    rounds: Vec<u8>,
+) -> Result<Vec<u8>> {
+    if passphrase.bytes().any(|c| !(32..=126).contains(&c)) {
+        bail!("passphrase must only contain printable ASCII characters")
+    }
+    let salt = if extendable {
+        Vec::new()
+    } else {
+        [&b"shamir"[..], &identifier.to_be_bytes()].concat()
+    };
+    let (mut l, mut r) = (
+        secret[..secret.len() / 2].to_vec(),
+        secret[secret.len() / 2..].to_vec(),
+    );
+    for round in rounds {
+        let f = feistel_round(round, passphrase, iteration_exponent, &salt, &r);
+        let next_r: Vec<u8> = l.iter().zip(f).map(|(a, b)| a ^ b).collect();
+        l = r;
+        r = next_r;
+    }
+    Ok([r, l].concat())
+}
+
+fn encrypt(
+    secret: &[u8],
+    passphrase: &str,
+    iteration_exponent: u8,
+    identifier: u16,
+    extendable: bool,
+) -> Result<Vec<u8>> {
+    feistel(
+        secret,
+        passphrase,
+        iteration_exponent,
+        identifier,
+        extendable,
+        (0..ROUND_COUNT).collect(),
+    )
+}
+
+fn decrypt(
+    secret: &[u8],
+    passphrase: &str,
+    iteration_exponent: u8,
+    identifier: u16,
+    extendable: bool,
+) -> Result<Vec<u8>> {
+    feistel(
+        secret,
+        passphrase,
+        iteration_exponent,
+        identifier,
+        extendable,
+        (0..ROUND_COUNT).rev().collect(),
+    )
+}
+
+#[cfg(test)]
+mod slip39_tests {
+    use super::{combine_mnemonics, combine_shares, split_secret, Share};
+
+    // From https://github.com/trezor/python-shamir-mnemonic/blob/master/vectors.json,
+    // all with the passphrase TREZOR. An empty secret means the mnemonics must
+    // be rejected.
+    const TREZOR_VECTORS: &[(&str, &[&str], &str)] = &[
+        (
+            "Valid mnemonic without sharing (128 bits)",
+            &["duckling enlarge academic academic agency result length solution fridge kidney coal piece deal husband erode duke ajar critical decision keyboard"],
+            "bb54aac4b89dc868ba37d9cc21b2cece",
+        ),
+        (
+            "Mnemonic with invalid checksum (128 bits)",
+            &["duckling enlarge academic academic agency result length solution fridge kidney coal piece deal husband erode duke ajar critical decision kidney"],
+            "",
+        ),
+        (
+            "Mnemonic with invalid padding (128 bits)",
+            &["duckling enlarge academic academic email result length solution fridge kidney coal piece deal husband erode duke ajar music cargo fitness"],
+            "",
+        ),
+        (
+            "Basic sharing 2-of-3 (128 bits)",
+            &[
+                "shadow pistol academic always adequate wildlife fancy gross oasis cylinder mustang wrist rescue view short owner flip making coding armed",
+                "shadow pistol academic acid actress prayer class unknown daughter sweater depict flip twice unkind craft early superior advocate guest smoking",
+            ],
+            "b43ceb7e57a0ea8766221624d01b0864",
+        ),
+        (
+            "Basic sharing 2-of-3 (128 bits), one share only",
+            &["shadow pistol academic always adequate wildlife fancy gross oasis cylinder mustang wrist rescue view short owner flip making coding armed"],
+            "",
+        ),
+        (
+            "Mnemonics with different identifiers (128 bits)",
+            &[
+                "adequate smoking academic acid debut wine petition glen cluster slow rhyme slow simple epidemic rumor junk tracks treat olympic tolerate",
+                "adequate stay academic agency agency formal party ting frequent learn upstairs remember smear leaf damage anatomy ladle market hush corner",
+            ],
+            "",
+        ),
+        (
+            "Mnemonics with different iteration exponents (128 bits)",
+            &[
+                "peasant leaves academic acid desert exact olympic math alive axle trial tackle drug deny decent smear dominant desert bucket remind",
+                "peasant leader academic agency cultural blessing percent network envelope medal junk primary human pumps jacket fragment payroll ticket evoke voice",
+            ],
+            "",
+        ),
+        (
+            "Mnemonics with mismatching group thresholds (128 bits)",
+            &[
+                "liberty category beard echo animal fawn temple briefing math username various wolf aviation fancy visual holy thunder yelp helpful payment",
+                "liberty category beard email beyond should fancy romp founder easel pink holy hairy romp loyalty material victim owner toxic custody",
+                "liberty category academic easy being hazard crush diminish oral lizard reaction cluster force dilemma deploy force club veteran expect photo",
+            ],
+            "",
+        ),
+        (
+            "Mnemonics with mismatching group counts (128 bits)",
+            &[
+                "average senior academic leaf broken teacher expect surface hour capture obesity desire negative dynamic dominant pistol mineral mailman iris aide",
+                "average senior academic agency curious pants blimp spew clothes slice script dress wrap firm shaft regular slavery negative theater roster",
+            ],
+            "",
+        ),
+        (
+            "Mnemonics with duplicate member indices (128 bits)",
+            &[
+                "device stay academic always dive coal antenna adult black exceed stadium herald advance soldier busy dryer daughter evaluate minister laser",
+                "device stay academic always dwarf afraid robin gravity crunch adjust soul branch walnut coastal dream costume scholar mortgage mountain pumps",
+            ],
+            "",
+        ),
+        (
+            "Mnemonics with mismatching member thresholds (128 bits)",
+            &[
+                "hour painting academic academic device formal evoke guitar random modern justice filter withdraw trouble identify mailman insect general cover oven",
+                "hour painting academic agency artist again daisy capital beaver fiber much enjoy suitable symbolic identify photo editor romp float echo",
+            ],
+            "",
+        ),
+        (
+            "Mnemonics giving an invalid digest (128 bits)",
+            &[
+                "guilt walnut academic acid deliver remove equip listen vampire tactics nylon rhythm failure husband fatigue alive blind enemy teaspoon rebound",
+                "guilt walnut academic agency brave hamster hobo declare herd taste alpha slim criminal mild arcade formal romp branch pink ambition",
+            ],
+            "",
+        ),
+        (
+            "Insufficient number of groups (128 bits, case 1)",
+            &["eraser senior beard romp adorn nuclear spill corner cradle style ancient family general leader ambition exchange unusual garlic promise voice"],
+            "",
+        ),
+        (
+            "Insufficient number of groups (128 bits, case 2)",
+            &[
+                "eraser senior ceramic snake clay various huge numb argue hesitate auction category timber browser greatest hanger petition script leaf pickup",
+                "eraser senior ceramic shaft dynamic become junior wrist silver peasant force math alto coal amazing segment yelp velvet image paces",
+            ],
+            "",
+        ),
+        (
+            "Threshold number of groups, but insufficient number of members in one group (128 bits)",
+            &[
+                "eraser senior decision shadow artist work morning estate greatest pipeline plan ting petition forget hormone flexible general goat admit surface",
+                "eraser senior beard romp adorn nuclear spill corner cradle style ancient family general leader ambition exchange unusual garlic promise voice",
+            ],
+            "",
+        ),
+        (
+            "Threshold number of groups and members in each group (128 bits)",
+            &[
+                "eraser senior beard romp adorn nuclear spill corner cradle style ancient family general leader ambition exchange unusual garlic promise voice",
+                "eraser senior acrobat romp bishop medical gesture pumps secret alive ultimate quarter priest subject class dictate spew material endless market",
+            ],
+            "7c3397a292a5941682d7a4ae2d898d11",
+        ),
+        (
+            "Mnemonics with different identifiers (128 bits, case 2)",
+            &[
+                "smear husband academic acid deadline scene venture distance dive overall parking bracelet elevator justice echo burning oven chest duke nylon",
+                "smear isolate academic agency alpha mandate decorate burden recover guard exercise fatal force syndrome fumes thank guest drift dramatic mule",
+            ],
+            "",
+        ),
+        (
+            "Valid mnemonic without sharing (256 bits)",
+            &["theory painting academic academic armed sweater year military elder discuss acne wildlife boring employer fused large satoshi bundle carbon diagnose anatomy hamster leaves tracks paces beyond phantom capital marvel lips brave detect luck"],
+            "989baf9dcaad5b10ca33dfd8cc75e42477025dce88ae83e75a230086a0e00e92",
+        ),
+        (
+            "Mnemonic with invalid checksum (256 bits)",
+            &["theory painting academic academic armed sweater year military elder discuss acne wildlife boring employer fused large satoshi bundle carbon diagnose anatomy hamster leaves tracks paces beyond phantom capital marvel lips brave detect lunar"],
+            "",
+        ),
+        (
+            "Basic sharing 2-of-3 (256 bits)",
+            &[
+                "humidity disease academic always aluminum jewelry energy woman receiver strategy amuse duckling lying evidence network walnut tactics forget hairy rebound impulse brother survive clothes stadium mailman rival ocean reward venture always armed unwrap",
+                "humidity disease academic agency actress jacket gross physics cylinder solution fake mortgage benefit public busy prepare sharp friar change work slow purchase ruler again tricycle involve viral wireless mixture anatomy desert cargo upgrade",
+            ],
+            "c938b319067687e990e05e0da0ecce1278f75ff58d9853f19dcaeed5de104aae",
+        ),
+        (
+            "Basic sharing 2-of-3 (256 bits), one share only",
+            &["humidity disease academic always aluminum jewelry energy woman receiver strategy amuse duckling lying evidence network walnut tactics forget hairy rebound impulse brother survive clothes stadium mailman rival ocean reward venture always armed unwrap"],
+            "",
+        ),
+        (
+            "Valid extendable mnemonic without sharing (128 bits)",
+            &["testify swimming academic academic column loyalty smear include exotic bedroom exotic wrist lobe cover grief golden smart junior estimate learn"],
+            "1679b4516e0ee5954351d288a838f45e",
+        ),
+        (
+            "Extendable basic sharing 2-of-3 (128 bits)",
+            &[
+                "enemy favorite academic acid cowboy phrase havoc level response walnut budget painting inside trash adjust froth kitchen learn tidy punish",
+                "enemy favorite academic always academic sniff script carpet romp kind promise scatter center unfair training emphasis evening belong fake enforce",
+            ],
+            "48b1a4b80b8c209ad42c33672bdaa428",
+        ),
+        (
+            "Valid extendable mnemonic without sharing (256 bits)",
+            &["impulse calcium academic academic alcohol sugar lyrics pajamas column facility finance tension extend space birthday rainbow swimming purple syndrome facility trial warn duration snapshot shadow hormone rhyme public spine counter easy hawk album"],
+            "8340611602fe91af634a5f4608377b5235fa2d757c51d720c0c7656249a3035f",
+        ),
+        (
+            "Extendable basic sharing 2-of-3 (256 bits)",
+            &[
+                "western apart academic always artist resident briefing sugar woman oven coding club ajar merit pecan answer prisoner artist fraction amount desktop mild false necklace muscle photo wealthy alpha category unwrap spew losing making",
+                "western apart academic acid answer ancient auction flip image penalty oasis beaver multiple thunder problem switch alive heat inherit superior teaspoon explain blanket pencil numb lend punish endless aunt garlic humidity kidney observe",
+            ],
+            "8dc652d6d6cd370d8c963141f6d79ba440300f25c467302c1d966bff8f62300d",
+        ),
+    ];
+
+    #[test]
+    fn test_trezor_vectors() {
+        for (description, mnemonics, secret) in TREZOR_VECTORS {
+            let result = combine_mnemonics(mnemonics, "TREZOR");
+            if secret.is_empty() {
+                assert!(result.is_err(), "{}", description);
+            } else {
+                let master = result.unwrap_or_else(|err| panic!("{}: {}", description, err));
+                assert_eq!(hex::encode(master), *secret, "{}", description);
+            }
+        }
+    }
+
+    #[test]
+    fn test_share_roundtrip() {
+        let mnemonic = "shadow pistol academic acid actress prayer class unknown daughter sweater depict flip twice unkind craft early superior advocate guest smoking";
+        let share = Share::parse(mnemonic).unwrap();
+        assert_eq!(share.member_threshold, 2);
+        assert_eq!(share.iteration_exponent, 2);
+        assert_eq!(share.to_string(), mnemonic);
+    }
+
+    #[test]
+    fn test_split_and_combine_groups() {
+        let secret = hex::decode("0c94b5b1ca8e62bba3fd3f2f9d8fc48b").unwrap();
+        for extendable in [false, true] {
+            let groups = split_secret(
+                &secret,
+                "TREZOR",
+                2,
+                &[(1, 1), (2, 3), (3, 5)],
+                0,
+                extendable,
+            )
+            .unwrap();
+            assert_eq!(groups[2].len(), 5);
+
+            let shares = vec![
+                groups[0][0].clone(),
+                groups[2][4].clone(),
+                groups[2][0].clone(),
+                groups[2][2].clone(),
+            ];
+            let mnemonics: Vec<String> = shares.iter().map(|share| share.to_string()).collect();
+            let mnemonics: Vec<&str> = mnemonics.iter().map(String::as_str).collect();
+            assert_eq!(combine_mnemonics(&mnemonics, "TREZOR").unwrap(), secret);
+            assert_ne!(combine_shares(&shares, "").unwrap(), secret);
+
+            let shares = vec![
+                groups[1][1].clone(),
+                groups[1][2].clone(),
+                groups[0][0].clone(),
+            ];
+            assert_eq!(combine_shares(&shares, "TREZOR").unwrap(), secret);
+
+            let shares = vec![groups[1][1].clone(), groups[0][0].clone()];
+            assert!(combine_shares(&shares, "TREZOR").is_err());
+        }
+    }
+
+    #[test]
+    fn test_invalid_split_parameters() {
+        let secret = vec![0; 16];
+        assert!(split_secret(&secret[..15], "", 1, &[(1, 1)], 0, false).is_err());
+        assert!(split_secret(&secret, "", 2, &[(1, 1)], 0, false).is_err());
+        assert!(split_secret(&secret, "", 1, &[(1, 3)], 0, false).is_err());
+        assert!(split_secret(&secret, "", 1, &[(3, 2)], 0, false).is_err());
+    }
+}
@@ -1,16 +1,18 @@
-use std::{
-    fmt::Display,
-    io::{Read, Seek},
-};
+use std::{fmt::Display, io::Read};
 
 use byteorder::{BigEndian, ByteOrder};
 use num_bigint::BigInt;
 
 use crate::{
+    address::Address,
+    error::Error,
+    network::Network,
     op::OpCodeFunctions,
     private_key::PrivateKey,
-    script::{Command, Script},
-    signature::Signature,
+    script::{Command, Script, VerifyFlags},
+    script_error::ScriptError,
+    signature::{Signature, SIGHASH_ANYONECANPAY, SIGHASH_NONE, SIGHASH_SINGLE},
+    signature_checker::TransactionSignatureChecker,
     tx_fetcher::TxFetcher,
     utils,
 };
@@ -27,6 +29,8 @@ pub struct TxIn {
     pub prev_index: BigInt,
     pub script_sig: Option<Script>,
     pub sequence: Option<BigInt>,
+    // BIP141 witness stack, empty for legacy inputs
+    pub witness: Vec<Vec<u8>>,
 }
 
 #[derive(Debug, Clone)]
@@ -35,7 +39,7 @@ pub struct Tx {
     pub tx_ins: Vec<TxIn>,
     pub tx_outs: Vec<TxOut>,
     pub locktime: BigInt,
-    pub testnet: bool,
+    pub network: Network,
 }
 
 impl Tx {
@@ -44,62 +48,95 @@ impl Tx {
         tx_ins: Vec<TxIn>,
         tx_outs: Vec<TxOut>,
         locktime: BigInt,
-        testnet: bool,
+        network: Network,
     ) -> Self {
         Tx {
             version,
             tx_ins,
             tx_outs,
             locktime,
-            testnet,
+            network,
         }
     }
 
-    pub fn parse<R: Read + Seek>(stream: &mut R, testnet: bool) -> Self {
+    // Reads both the legacy and the BIP144 format. A segwit transaction has
+    // a 0x00 marker where the input count would be, followed by a 0x01 flag,
+    // and the witnesses of all inputs after the outputs.
+    pub fn parse<R: Read>(stream: &mut R, network: Network) -> Result<Self, Error> {
         let mut buffer = [0; 4];
-
-        let mut handle = stream.take(4);
-        handle.read(&mut buffer).unwrap();
+        stream.read_exact(&mut buffer)?;
         let version = utils::little_endian_to_int(&buffer);
-        let pos = stream.stream_position().unwrap();
-
-        // The next two bytes represents if the TX is segwit
-        // Not all TX's have this mark so if not, it is necessary to restart the position.
-        // TODO handle segwit correctly
-        let mut buffer = [0; 2];
-        let mut handle = stream.take(2);
-        handle.read(&mut buffer).unwrap();
-        if buffer == [0_u8, 1_u8] {
-        } else {
-            stream.seek(std::io::SeekFrom::Start(pos)).unwrap();
+
+        let mut num_inputs = utils::read_varint(stream)?;
+        let segwit = num_inputs == 0;
+        if segwit {
+            let mut flag = [0; 1];
+            stream.read_exact(&mut flag)?;
+            if flag[0] != 1 {
+                return Err(Error::BadWitness("unknown transaction flag"));
+            }
+            num_inputs = utils::read_varint(stream)?;
         }
-        let num_inputs_buf = utils::read_varint(stream).to_signed_bytes_be();
-        let num_inputs = BigEndian::read_uint(&num_inputs_buf, num_inputs_buf.len());
         let mut inputs: Vec<TxIn> = Vec::new();
         for _ in 0..num_inputs {
-            inputs.push(TxIn::parse(stream))
+            inputs.push(TxIn::parse(stream)?)
         }
-        let num_outputs_buf = utils::read_varint(stream).to_signed_bytes_be();
-        let num_outputs = BigEndian::read_uint(&num_outputs_buf, num_outputs_buf.len());
+        let num_outputs = utils::read_varint(stream)?;
         let mut outputs: Vec<TxOut> = Vec::new();
         for _ in 0..num_outputs {
-            outputs.push(TxOut::parse(stream))
+            outputs.push(TxOut::parse(stream)?)
+        }
+        if segwit {
+            for tx_in in inputs.iter_mut() {
+                tx_in.witness = parse_witness(stream)?;
+            }
+            // the marker must not be used without any witness data
+            if inputs.iter().all(|tx_in| tx_in.witness.is_empty()) {
+                return Err(Error::BadWitness("superfluous witness record"));
+            }
         }
         let mut locktime_buffer = [0; 4];
-        stream.read_exact(&mut locktime_buffer).unwrap();
+        stream.read_exact(&mut locktime_buffer)?;
         let locktime = utils::little_endian_to_int(&locktime_buffer);
-        Tx {
+        Ok(Tx {
             version,
             tx_ins: inputs,
             tx_outs: outputs,
             locktime,
-            testnet,
-        }
+            network,
+        })
     }
 
+    // BIP144 format whenever an input has a witness, legacy otherwise.
     pub fn serialize(&self) -> Vec<u8> {
+        if !self.has_witness() {
+            return self.serialize_without_witness();
+        }
+        let mut result = vec![
+            utils::int_to_little_endian(&self.version, 4),
+            // marker and flag
+            vec![0, 1],
+            self.serialize_body(),
+        ];
+        for tx_in in &self.tx_ins {
+            result.push(tx_in.serialize_witness());
+        }
+        result.push(utils::int_to_little_endian(&self.locktime, 4));
+        result.concat()
+    }
+
+    // The legacy format the txid commits to.
+    pub fn serialize_without_witness(&self) -> Vec<u8> {
+        [
+            utils::int_to_little_endian(&self.version, 4),
+            self.serialize_body(),
+            utils::int_to_little_endian(&self.locktime, 4),
+        ]
+        .concat()
+    }
+
+    fn serialize_body(&self) -> Vec<u8> {
         let mut result: Vec<Vec<u8>> = Vec::new();
-        result.push(utils::int_to_little_endian(&self.version, 4));
         result.push(utils::encode_varint(self.tx_ins.len()));
         for tx_in in &self.tx_ins {
             let intx = tx_in.serialize();
@@ -110,107 +147,199 @@ impl Tx {
             let outx = tx_out.serialize();
             result.push(outx)
         }
-        result.push(utils::int_to_little_endian(&self.locktime, 4));
         result.concat()
     }
 
-    pub fn fee(&self, testnet: bool) -> BigInt {
+    pub fn has_witness(&self) -> bool {
+        self.tx_ins.iter().any(|tx_in| !tx_in.witness.is_empty())
+    }
+
+    pub fn fee(&self, network: Network) -> Result<BigInt, Error> {
         let mut tx_ins_total = BigInt::from(0);
         for tx_in in &self.tx_ins {
-            tx_ins_total += tx_in.value(testnet);
+            tx_ins_total += tx_in.value(network)?;
         }
 
         let mut tx_outs_total = BigInt::from(0);
         for tx_out in &self.tx_outs {
             tx_outs_total += tx_out.amount.clone();
         }
-        tx_ins_total - tx_outs_total
+        Ok(tx_ins_total - tx_outs_total)
     }
 
-    pub fn sig_hash(&self, input_index: usize) -> BigInt {
+    // Legacy signature hash of an input for the given hashtype. The script
+    // code defaults to the previous scriptPubKey; for P2SH inputs pass the
+    // redeem script.
+    pub fn sig_hash(
+        &self,
+        input_index: usize,
+        script_code: Option<Script>,
+        hash_type: u32,
+    ) -> Result<BigInt, Error> {
+        let base_type = hash_type & 0x1f;
+        let anyone_can_pay = hash_type & SIGHASH_ANYONECANPAY != 0;
+        // Core signs the number 1 instead of failing when SIGHASH_SINGLE has
+        // no matching output, and the signature still has to verify
+        if input_index >= self.tx_ins.len()
+            || (base_type == SIGHASH_SINGLE && input_index >= self.tx_outs.len())
+        {
+            let mut one = vec![0; 32];
+            one[0] = 1;
+            return Ok(BigInt::from_bytes_be(num_bigint::Sign::Plus, &one));
+        }
+        let script_code = match script_code {
+            Some(script_code) => script_code,
+            None => self.tx_ins[input_index].script_pubkey(self.network)?,
+        };
+        // Core's SerializeScriptCode: OP_CODESEPARATORs are never part of
+        // what gets signed. A push running past the end is written up to its
+        // length field, while the length in front still counts every byte.
+        let raw = script_code.as_bytes();
+        let mut code = Vec::with_capacity(raw.len());
+        let mut separators = 0;
+        let mut pos = 0;
+        for instruction in script_code.instructions() {
+            let instruction = match instruction {
+                Ok(instruction) => instruction,
+                Err(_) => {
+                    code.extend_from_slice(&raw[pos..truncated_push_end(raw, pos)]);
+                    break;
+                }
+            };
+            if matches!(
+                instruction.command,
+                Command::Operation(OpCodeFunctions::OpCodeseparator(_))
+            ) {
+                separators += 1;
+            } else {
+                code.extend_from_slice(&raw[instruction.start..instruction.end]);
+            }
+            pos = instruction.end;
+        }
+        let mut script_code = utils::encode_varint(raw.len() - separators);
+        script_code.append(&mut code);
+
         let mut s = utils::int_to_little_endian(&self.version, 4);
-        s.append(&mut utils::encode_varint(self.tx_ins.len()));
-        for (i, tx_in) in self.tx_ins.iter().enumerate() {
+        let inputs = if anyone_can_pay {
+            input_index..input_index + 1
+        } else {
+            0..self.tx_ins.len()
+        };
+        s.append(&mut utils::encode_varint(inputs.len()));
+        for i in inputs {
+            let tx_in = &self.tx_ins[i];
+            let sequence =
+                if i != input_index && (base_type == SIGHASH_NONE || base_type == SIGHASH_SINGLE) {
+                    // lets the other inputs be replaced
+                    Some(BigInt::from(0))
+                } else {
+                    tx_in.sequence.clone()
+                };
+            let mut tx = TxIn::new(
+                tx_in.prev_tx.clone(),
+                tx_in.prev_index.clone(),
+                None,
+                sequence,
+            )
+            .serialize();
             if i == input_index {
-                let mut tx = TxIn::new(
-                    tx_in.prev_tx.clone(),
-                    tx_in.prev_index.clone(),
-                    Some(tx_in.script_pubkey(self.testnet)),
-                    tx_in.sequence.clone(),
-                )
-                .serialize();
-                s.append(&mut tx);
-            } else {
-                let mut tx = TxIn::new(
-                    tx_in.prev_tx.clone(),
-                    tx_in.prev_index.clone(),
-                    None,
-                    tx_in.sequence.clone(),
-                )
-                .serialize();
-                s.append(&mut tx);
+                // the empty script after the outpoint
+                tx.splice(36..37, script_code.iter().copied());
             }
+            s.append(&mut tx);
         }
 
-        s.append(&mut utils::encode_varint(self.tx_outs.len()));
-        for tx_out in &self.tx_outs {
-            s.append(&mut tx_out.clone().serialize())
+        match base_type {
+            SIGHASH_NONE => s.append(&mut utils::encode_varint(0)),
+            SIGHASH_SINGLE => {
+                s.append(&mut utils::encode_varint(input_index + 1));
+                // the outputs in front are blanked to an amount of -1 and
+                // an empty script
+                for _ in 0..input_index {
+                    s.extend_from_slice(&[0xff; 8]);
+                    s.push(0);
+                }
+                s.append(&mut self.tx_outs[input_index].serialize());
+            }
+            _ => {
+                s.append(&mut utils::encode_varint(self.tx_outs.len()));
+                for tx_out in &self.tx_outs {
+                    s.append(&mut tx_out.serialize())
+                }
+            }
         }
         s.append(&mut utils::int_to_little_endian(&self.locktime, 4));
-        s.append(&mut utils::u32_to_little_endian(
-            *OpCodeFunctions::op_sig_hash_all().as_ref(),
-            4,
-        ));
+        s.append(&mut utils::u32_to_little_endian(hash_type, 4));
         let hash = utils::hash256(&s);
-        return BigInt::from_bytes_be(num_bigint::Sign::Plus, &hash);
+        Ok(BigInt::from_bytes_be(num_bigint::Sign::Plus, &hash))
     }
 
-    pub fn verify_input(&self, input_index: usize) -> bool {
+    pub fn verify_input(&self, input_index: usize) -> Result<bool, Error> {
         let tx_in = &self.tx_ins[input_index];
-        let script_pubkey = tx_in.script_pubkey(self.testnet);
-        let sig_hash = self.sig_hash(input_index);
-        let z = Signature::signature_hash_from_int(sig_hash);
-        match tx_in.script_sig.clone() {
-            Some(script_sig) => {
-                let combined = script_sig + script_pubkey;
-                combined.evaluate(z)
+        let script_pubkey = tx_in.script_pubkey(self.network)?;
+        // a missing scriptSig is serialized as an empty one
+        let script_sig = tx_in
+            .script_sig
+            .clone()
+            .unwrap_or_else(|| Script::new(None));
+        match Script::verify_script(
+            &script_sig,
+            &script_pubkey,
+            &TransactionSignatureChecker::new(self, input_index),
+            VerifyFlags::MANDATORY,
+        ) {
+            Ok(()) => Ok(true),
+            // not knowing is different from the input being invalid
+            Err(ScriptError::WitnessUnsupported) => {
+                Err(Error::Unsupported("witness program execution"))
             }
-            None => false,
+            Err(_) => Ok(false),
         }
     }
 
-    pub fn verify(&self) -> bool {
-        if self.fee(self.testnet) < BigInt::from(0) {
-            return false;
+    pub fn verify(&self) -> Result<bool, Error> {
+        if self.fee(self.network)? < BigInt::from(0) {
+            return Ok(false);
         } else {
             for i in 0..self.tx_ins.len() {
-                if !self.verify_input(i) {
-                    return false;
+                if !self.verify_input(i)? {
+                    return Ok(false);
                 }
             }
         }
-        return true;
+        Ok(true)
     }
 
-    fn id(&self) -> String {
+    // The id the transaction is referred to by, which leaves out the
+    // witnesses so that they can't be malleated.
+    pub fn txid(&self) -> String {
         hex::encode(self.hash())
     }
 
+    // Commits to the witnesses too; the same as the txid for legacy
+    // transactions.
+    pub fn wtxid(&self) -> String {
+        let mut hash = utils::hash256(&self.serialize());
+        hash.reverse();
+        hex::encode(hash)
+    }
+
     fn hash(&self) -> Vec<u8> {
-        let mut a = utils::hash256(&self.serialize());
+        let mut a = utils::hash256(&self.serialize_without_witness());
         a.reverse();
         a
     }
 
-    pub fn sign_input(&mut self, input_index: usize, private_key: PrivateKey) -> bool {
-        let z = self.sig_hash(input_index);
+    pub fn sign_input(
+        &mut self,
+        input_index: usize,
+        private_key: PrivateKey,
+        hash_type: u32,
+    ) -> Result<bool, Error> {
+        let z = self.sig_hash(input_index, None, hash_type)?;
         let sign = private_key.sign(&Signature::signature_hash_from_int(z), None);
         let mut der_sighash = sign.der();
-        der_sighash.append(&mut utils::u32_to_little_endian(
-            *OpCodeFunctions::op_sig_hash_all().as_ref(),
-            1,
-        ));
-        // der_sighash.push(1);
+        der_sighash.append(&mut utils::u32_to_little_endian(hash_type, 1));
         let sec = private_key.point.sec(Some(true));
         let script_sig = Script::new(Some(vec![
             Command::Element(der_sighash),
@@ -229,12 +358,15 @@ impl Display for Tx {
         }
         let mut tx_outs = String::new();
         for tx_out in &self.tx_outs {
-            tx_outs += &format!("{}\n", tx_out)
+            match tx_out.address(self.network) {
+                Some(address) => tx_outs += &format!("{} -> {}\n", address, tx_out),
+                None => tx_outs += &format!("{}\n", tx_out),
+            }
         }
         writeln!(
             f,
             "tx: {}\nversion: {}\ntx_ins:\n{}tx_outs:\n{}locktime: {}",
-            self.id(),
+            self.txid(),
             self.version,
             tx_ins,
             tx_outs,
@@ -243,6 +375,22 @@ impl Display for Tx {
     }
 }
 
+// Where Core's GetOp stops in a push that runs past the end: after the
+// opcode and the length field, if the length field is complete.
+fn truncated_push_end(raw: &[u8], pos: usize) -> usize {
+    let length_size = match raw[pos] {
+        0x4c => 1,
+        0x4d => 2,
+        0x4e => 4,
+        _ => 0,
+    };
+    if raw.len() - pos - 1 < length_size {
+        pos + 1
+    } else {
+        pos + 1 + length_size
+    }
+}
+
 impl TxIn {
     pub fn new(
         prev_tx: Vec<u8>,
@@ -255,26 +403,28 @@ impl TxIn {
             prev_index,
             script_sig,
             sequence,
+            witness: Vec::new(),
         }
     }
 
-    pub fn parse<R: Read>(stream: &mut R) -> Self {
+    pub fn parse<R: Read>(stream: &mut R) -> Result<Self, Error> {
         let mut prev_tx_buffer = [0; 32];
-        stream.read_exact(&mut prev_tx_buffer).unwrap();
+        stream.read_exact(&mut prev_tx_buffer)?;
         prev_tx_buffer.reverse(); // because is little endian
         let mut prev_tx_index_buffer = [0; 4];
-        stream.read_exact(&mut prev_tx_index_buffer).unwrap();
+        stream.read_exact(&mut prev_tx_index_buffer)?;
         let prev_index = utils::little_endian_to_int(&prev_tx_index_buffer);
-        let script_sig = Script::parse(stream);
+        let script_sig = Script::parse(stream)?;
         let mut sequence_buffer = [0; 4];
-        stream.read_exact(&mut sequence_buffer).unwrap();
+        stream.read_exact(&mut sequence_buffer)?;
         let sequence = utils::little_endian_to_int(&sequence_buffer);
-        TxIn {
+        Ok(TxIn {
             prev_tx: prev_tx_buffer.to_vec(),
             prev_index,
             script_sig: Some(script_sig),
             sequence: Some(sequence),
-        }
+            witness: Vec::new(),
+        })
     }
 
     pub fn serialize(&self) -> Vec<u8> {
@@ -294,25 +444,36 @@ impl TxIn {
         result.concat()
     }
 
-    pub fn fetch_tx(&self, testnet: bool) -> Tx {
+    pub fn serialize_witness(&self) -> Vec<u8> {
+        let mut result = utils::encode_varint(self.witness.len());
+        for item in &self.witness {
+            result.append(&mut utils::encode_varint(item.len()));
+            result.extend_from_slice(item);
+        }
+        result
+    }
+
+    pub fn fetch_tx(&self, network: Network) -> Result<Tx, Error> {
         let mut tx_fetcher = TxFetcher::new();
-        tx_fetcher
-            .fetch(&hex::encode(self.prev_tx.clone()), testnet, false)
-            .clone()
+        tx_fetcher.fetch(&hex::encode(self.prev_tx.clone()), network, false)
     }
 
-    pub fn value(&self, testnet: bool) -> BigInt {
-        let tx = self.fetch_tx(testnet);
+    fn prev_output(&self, network: Network) -> Result<TxOut, Error> {
+        let tx = self.fetch_tx(network)?;
         let index_buf = self.prev_index.to_signed_bytes_be();
         let index = BigEndian::read_int(&index_buf, index_buf.len()) as usize;
-        tx.tx_outs[index].amount.clone()
+        match tx.tx_outs.get(index) {
+            Some(tx_out) => Ok(tx_out.clone()),
+            None => Err(Error::Fetch(format!("{} has no output {}", self, index))),
+        }
     }
 
-    pub fn script_pubkey(&self, testnet: bool) -> Script {
-        let tx = self.fetch_tx(testnet);
-        let index_buf = self.prev_index.to_signed_bytes_be();
-        let index = BigEndian::read_int(&index_buf, index_buf.len()) as usize;
-        tx.tx_outs[index].script_pubkey.clone()
+    pub fn value(&self, network: Network) -> Result<BigInt, Error> {
+        Ok(self.prev_output(network)?.amount)
+    }
+
+    pub fn script_pubkey(&self, network: Network) -> Result<Script, Error> {
+        Ok(self.prev_output(network)?.script_pubkey)
     }
 }
 
@@ -335,15 +496,15 @@ impl TxOut {
         }
     }
 
-    pub fn parse<R: Read>(stream: &mut R) -> Self {
+    pub fn parse<R: Read>(stream: &mut R) -> Result<Self, Error> {
         let mut amount_buffer = [0; 8];
-        stream.read_exact(&mut amount_buffer).unwrap();
+        stream.read_exact(&mut amount_buffer)?;
         let amount = utils::little_endian_to_int(&amount_buffer);
-        let script_pubkey = Script::parse(stream);
-        TxOut {
+        let script_pubkey = Script::parse(stream)?;
+        Ok(TxOut {
             amount,
             script_pubkey,
-        }
+        })
     }
 
     pub fn serialize(&self) -> Vec<u8> {
@@ -352,12 +513,38 @@ impl TxOut {
         result.push(self.script_pubkey.serialize());
         result.concat()
     }
+
+    pub fn address(&self, network: Network) -> Option<Address> {
+        Address::from_script(&self.script_pubkey, network)
+    }
 }
 
 impl Display for TxOut {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}:{}", self.amount, self.script_pubkey)
+        write!(
+            f,
+            "{}:{}:{}",
+            self.amount,
+            self.script_pubkey.script_type(),
+            self.script_pubkey
+        )
+    }
+}
+
+fn parse_witness<R: Read>(stream: &mut R) -> Result<Vec<Vec<u8>>, Error> {
+    let num_items = utils::read_varint(stream)?;
+    let mut witness = Vec::new();
+    for _ in 0..num_items {
+        let length = utils::read_varint(stream)?;
+        // read through take so a bogus length can't allocate the memory up front
+        let mut item = Vec::new();
+        stream.take(length).read_to_end(&mut item)?;
+        if item.len() as u64 != length {
+            return Err(Error::Truncated);
+        }
+        witness.push(item);
     }
+    Ok(witness)
 }
 
 #[cfg(test)]
@@ -367,11 +554,14 @@ mod tx_tests {
     use num_bigint::BigInt;
 
     use crate::{
-        op::OpCodeFunctions,
+        error::Error,
+        network::Network,
         private_key::PrivateKey,
         s256_point::S256Point,
-        script::{Command, Script},
-        signature::Signature,
+        script::{Command, Script, ScriptType, VerifyFlags},
+        script_error::ScriptError,
+        signature::{Signature, SIGHASH_ALL, SIGHASH_ANYONECANPAY, SIGHASH_NONE, SIGHASH_SINGLE},
+        signature_checker::TransactionSignatureChecker,
         tx::{TxIn, TxOut},
         utils,
     };
@@ -383,7 +573,7 @@ mod tx_tests {
         let tx = "0100000001813f79011acb80925dfe69b3def355fe914bd1d96a3f5f71bf8303c6a989c7d1000000006b483045022100ed81ff192e75a3fd2304004dcadb746fa5e24c5031ccfcf21320b0277457c98f02207a986d955c6e0cb35d446a89d3f56100f4d7f67801c31967743a9c8e10615bed01210349fc4e631e3624a545de3f89f5d8684c7b8138bd94bdd531d2e213bf016b278afeffffff02a135ef01000000001976a914bc3b654dca7e56b04dca18f2566cdaf02e8d9ada88ac99c39800000000001976a9141c4bc762dd5423e332166702cb75f40df79fea1288ac19430600";
         let tx_encode = hex::decode(tx).unwrap();
         let mut cursor_tx = Cursor::new(tx_encode);
-        let tx = Tx::parse(&mut cursor_tx, false);
+        let tx = Tx::parse(&mut cursor_tx, Network::Mainnet).unwrap();
         assert_eq!(tx.tx_ins.len(), 1);
         assert_eq!(tx.tx_ins[0].prev_index, BigInt::from(0));
         assert_eq!(
@@ -402,7 +592,7 @@ mod tx_tests {
         let tx = "0100000001813f79011acb80925dfe69b3def355fe914bd1d96a3f5f71bf8303c6a989c7d1000000006b483045022100ed81ff192e75a3fd2304004dcadb746fa5e24c5031ccfcf21320b0277457c98f02207a986d955c6e0cb35d446a89d3f56100f4d7f67801c31967743a9c8e10615bed01210349fc4e631e3624a545de3f89f5d8684c7b8138bd94bdd531d2e213bf016b278afeffffff02a135ef01000000001976a914bc3b654dca7e56b04dca18f2566cdaf02e8d9ada88ac99c39800000000001976a9141c4bc762dd5423e332166702cb75f40df79fea1288ac19430600";
         let tx_encode = hex::decode(tx).unwrap();
         let mut cursor_tx = Cursor::new(tx_encode);
-        let tx = Tx::parse(&mut cursor_tx, false);
+        let tx = Tx::parse(&mut cursor_tx, Network::Mainnet).unwrap();
         assert_eq!(tx.tx_outs.len(), 2);
         assert_eq!(tx.tx_outs[0].amount, BigInt::from(32454049));
         assert_eq!(
@@ -414,6 +604,17 @@ mod tx_tests {
             hex::encode(tx.tx_outs[1].script_pubkey.serialize()),
             "1976a9141c4bc762dd5423e332166702cb75f40df79fea1288ac"
         );
+        assert_eq!(
+            tx.tx_outs[0].address(Network::Mainnet).unwrap().to_string(),
+            "1JAHBxA51vwp5C2zpSB15VbxSZK3hVJs2H"
+        );
+        assert!(tx
+            .to_string()
+            .contains("1JAHBxA51vwp5C2zpSB15VbxSZK3hVJs2H -> 32454049:"));
+        assert_eq!(
+            tx.tx_outs[0].to_string(),
+            "32454049:pubkeyhash:OP_DUP OP_HASH160 bc3b654dca7e56b04dca18f2566cdaf02e8d9ada OP_EQUALVERIFY OP_CHECKSIG\n"
+        );
     }
 
     #[test]
@@ -421,25 +622,210 @@ mod tx_tests {
         let tx = "0100000001813f79011acb80925dfe69b3def355fe914bd1d96a3f5f71bf8303c6a989c7d1000000006b483045022100ed81ff192e75a3fd2304004dcadb746fa5e24c5031ccfcf21320b0277457c98f02207a986d955c6e0cb35d446a89d3f56100f4d7f67801c31967743a9c8e10615bed01210349fc4e631e3624a545de3f89f5d8684c7b8138bd94bdd531d2e213bf016b278afeffffff02a135ef01000000001976a914bc3b654dca7e56b04dca18f2566cdaf02e8d9ada88ac99c39800000000001976a9141c4bc762dd5423e332166702cb75f40df79fea1288ac19430600";
         let tx_encode = hex::decode(tx).unwrap();
         let mut cursor_tx = Cursor::new(tx_encode);
-        let tx = Tx::parse(&mut cursor_tx, false);
+        let tx = Tx::parse(&mut cursor_tx, Network::Mainnet).unwrap();
         assert_eq!(tx.locktime, BigInt::from(410393));
     }
 
+    #[test]
+    fn test_parse_truncated_tx() {
+        let tx = "0100000001813f79011acb80925dfe69b3def355fe914bd1d96a3f5f71bf8303c6a989c7d1000000006b483045022100ed81ff192e75a3fd2304004dcadb746fa5e24c5031ccfcf21320b0277457c98f02207a986d955c6e0cb35d446a89d3f56100f4d7f67801c31967743a9c8e10615bed01210349fc4e631e3624a545de3f89f5d8684c7b8138bd94bdd531d2e213bf016b278afeffffff02a135ef01000000001976a914bc3b654dca7e56b04dca18f2566cdaf02e8d9ada88ac99c39800000000001976a9141c4bc762dd5423e332166702cb75f40df79fea1288ac194306";
+        let mut cursor_tx = Cursor::new(hex::decode(tx).unwrap());
+        assert!(matches!(
+            Tx::parse(&mut cursor_tx, Network::Mainnet),
+            Err(Error::Truncated)
+        ));
+    }
+
     #[test]
     fn test_tx_serialize() {
         let tx = "010000000456919960ac691763688d3d3bcea9ad6ecaf875df5339e148a1fc61c6ed7a069e010000006a47304402204585bcdef85e6b1c6af5c2669d4830ff86e42dd205c0e089bc2a821657e951c002201024a10366077f87d6bce1f7100ad8cfa8a064b39d4e8fe4ea13a7b71aa8180f012102f0da57e85eec2934a82a585ea337ce2f4998b50ae699dd79f5880e253dafafb7feffffffeb8f51f4038dc17e6313cf831d4f02281c2a468bde0fafd37f1bf882729e7fd3000000006a47304402207899531a52d59a6de200179928ca900254a36b8dff8bb75f5f5d71b1cdc26125022008b422690b8461cb52c3cc30330b23d574351872b7c361e9aae3649071c1a7160121035d5c93d9ac96881f19ba1f686f15f009ded7c62efe85a872e6a19b43c15a2937feffffff567bf40595119d1bb8a3037c356efd56170b64cbcc160fb028fa10704b45d775000000006a47304402204c7c7818424c7f7911da6cddc59655a70af1cb5eaf17c69dadbfc74ffa0b662f02207599e08bc8023693ad4e9527dc42c34210f7a7d1d1ddfc8492b654a11e7620a0012102158b46fbdff65d0172b7989aec8850aa0dae49abfb84c81ae6e5b251a58ace5cfeffffffd63a5e6c16e620f86f375925b21cabaf736c779f88fd04dcad51d26690f7f345010000006a47304402200633ea0d3314bea0d95b3cd8dadb2ef79ea8331ffe1e61f762c0f6daea0fabde022029f23b3e9c30f080446150b23852028751635dcee2be669c2a1686a4b5edf304012103ffd6f4a67e94aba353a00882e563ff2722eb4cff0ad6006e86ee20dfe7520d55feffffff0251430f00000000001976a914ab0c0b2e98b1ab6dbf67d4750b0a56244948a87988ac005a6202000000001976a9143c82d7df364eb6c75be8c80df2b3eda8db57397088ac46430600";
         let tx_encode = hex::decode(tx).unwrap();
         let mut cursor_tx = Cursor::new(tx_encode.clone());
-        let tx = Tx::parse(&mut cursor_tx, false);
+        let tx = Tx::parse(&mut cursor_tx, Network::Mainnet).unwrap();
         assert_eq!(tx_encode, tx.serialize());
     }
 
+    // The signed native P2WPKH example from BIP143: the first input spends a
+    // legacy output and keeps an empty witness, the second a P2WPKH output.
+    const P2WPKH_TX: &str = "01000000000102fff7f7881a8099afa6940d42d1e7f6362bec38171ea3edf433541db4e4ad969f00000000494830450221008b9d1dc26ba6a9cb62127b02742fa9d754cd3bebf337f7a55d114c8e5cdd30be022040529b194ba3f9281a99f2b1c0a19c0489bc22ede944ccf4ecbab4cc618ef3ed01eeffffffef51e1b804cc89d182d279655c3aa89e815b1b309fe287d9b2b55d57b90ec68a0100000000ffffffff02202cb206000000001976a9148280b37df378db99f66f85c95a783a76ac7a6d5988ac9093510d000000001976a9143bde42dbee7e4dbe6a21b2d50ce2f0167faa815988ac000247304402203609e17b84f6a7d30c80bfa610b5b4542f32a8a0d5447a12fb1366d7f01cc44a0220573a954c4518331561406f90300e8f3358f51928d43c212a8caed02de67eebee0121025476c2e83188368da1ff3e292e7acafcdb3566bb0ad253f62fc70f07aeee635711000000";
+
+    // The signed P2SH-P2WPKH example from BIP143.
+    const P2SH_P2WPKH_TX: &str = "01000000000101db6b1b20aa0fd7b23880be2ecbd4a98130974cf4748fb66092ac4d3ceb1a5477010000001716001479091972186c449eb1ded22b78e40d009bdf0089feffffff02b8b4eb0b000000001976a914a457b684d7f0d539a46a45bbc043f35b59d0d96388ac0008af2f000000001976a914fd270b1ee6abcaea97fea7ad0402e8bd8ad6d77c88ac02473044022047ac8e878352d3ebbde1c94ce3a10d057c24175747116f8288e5d794d12d482f0220217f36a485cae903c713331d877c1f64677e3622ad4010726870540656fe9dcb012103ad1d8e89212f0b92c74d23bb710c00662ad1470198ac48c43f7d6f93a2a2687392040000";
+
+    // A mainnet transaction spending a P2WPKH output.
+    const MAINNET_P2WPKH_TX: &str = "02000000000101595895ea20179de87052b4046dfe6fd515860505d6511a9004cf12a1f93cac7c0100000000ffffffff01deb807000000000017a9140f3444e271620c736808aa7b33e370bd87cb5a078702483045022100fb60dad8df4af2841adc0346638c16d0b8035f5e3f3753b88db122e70c79f9370220756e6633b17fd2710e626347d28d60b0a2d6cbb41de51740644b9fb3ba7751040121028fa937ca8cba2197a37c007176ed8941055d3bcb8627d085e94553e62f057dcc00000000";
+
+    // A mainnet transaction spending a P2PKH output and two P2SH-P2WPKH
+    // outputs.
+    const MAINNET_P2SH_P2WPKH_TX: &str = "020000000001031cfbc8f54fbfa4a33a30068841371f80dbfe166211242213188428f437445c91000000006a47304402206fbcec8d2d2e740d824d3d36cc345b37d9f65d665a99f5bd5c9e8d42270a03a8022013959632492332200c2908459547bf8dbf97c65ab1a28dec377d6f1d41d3d63e012103d7279dfb90ce17fe139ba60a7c41ddf605b25e1c07a4ddcb9dfef4e7d6710f48feffffff476222484f5e35b3f0e43f65fc76e21d8be7818dd6a989c160b1e5039b7835fc00000000171600140914414d3c94af70ac7e25407b0689e0baa10c77feffffffa83d954a62568bbc99cc644c62eb7383d7c2a2563041a0aeb891a6a4055895570000000017160014795d04cc2d4f31480d9a3710993fbd80d04301dffeffffff06fef72f000000000017a91476fd7035cd26f1a32a5ab979e056713aac25796887a5000f00000000001976a914b8332d502a529571c6af4be66399cd33379071c588ac3fda0500000000001976a914fc1d692f8de10ae33295f090bea5fe49527d975c88ac522e1b00000000001976a914808406b54d1044c429ac54c0e189b0d8061667e088ac6eb68501000000001976a914dfab6085f3a8fb3e6710206a5a959313c5618f4d88acbba20000000000001976a914eb3026552d7e3f3073457d0bee5d4757de48160d88ac0002483045022100bee24b63212939d33d513e767bc79300051f7a0d433c3fcf1e0e3bf03b9eb1d70220588dc45a9ce3a939103b4459ce47500b64e23ab118dfc03c9caa7d6bfc32b9c601210354fd80328da0f9ae6eef2b3a81f74f9a6f66761fadf96f1d1d22b1fd6845876402483045022100e29c7e3a5efc10da6269e5fc20b6a1cb8beb92130cc52c67e46ef40aaa5cac5f0220644dd1b049727d991aece98a105563416e10a5ac4221abac7d16931842d5c322012103960b87412d6e169f30e12106bdf70122aabb9eb61f455518322a18b920a4dfa887d30700";
+
+    fn parse_hex(raw: &str) -> Result<Tx, Error> {
+        Tx::parse(
+            &mut Cursor::new(hex::decode(raw).unwrap()),
+            Network::Mainnet,
+        )
+    }
+
+    #[test]
+    fn test_parse_segwit_tx() {
+        let tx = parse_hex(P2WPKH_TX).unwrap();
+        assert!(tx.has_witness());
+        assert_eq!(tx.tx_ins.len(), 2);
+        assert_eq!(tx.tx_outs.len(), 2);
+        assert!(tx.tx_ins[0].witness.is_empty());
+        assert_eq!(
+            tx.tx_ins[1].witness,
+            vec![
+                hex::decode("304402203609e17b84f6a7d30c80bfa610b5b4542f32a8a0d5447a12fb1366d7f01cc44a0220573a954c4518331561406f90300e8f3358f51928d43c212a8caed02de67eebee01").unwrap(),
+                hex::decode("025476c2e83188368da1ff3e292e7acafcdb3566bb0ad253f62fc70f07aeee6357").unwrap(),
+            ]
+        );
+        assert_eq!(tx.locktime, BigInt::from(17));
+        assert_eq!(hex::encode(tx.serialize()), P2WPKH_TX);
+        assert_eq!(
+            tx.txid(),
+            "e8151a2af31c368a35053ddd4bdb285a8595c769a3ad83e0fa02314a602d4609"
+        );
+        assert_eq!(
+            tx.wtxid(),
+            "c36c38370907df2324d9ce9d149d191192f338b37665a82e78e76a12c909b762"
+        );
+
+        let tx = parse_hex(P2SH_P2WPKH_TX).unwrap();
+        assert_eq!(
+            hex::encode(tx.tx_ins[0].script_sig.clone().unwrap().raw_serialize()),
+            "16001479091972186c449eb1ded22b78e40d009bdf0089"
+        );
+        assert_eq!(tx.tx_ins[0].witness.len(), 2);
+        assert_eq!(hex::encode(tx.serialize()), P2SH_P2WPKH_TX);
+        assert_eq!(
+            tx.txid(),
+            "ef48d9d0f595052e0f8cdcf825f7a5e50b6a388a81f206f3f4846e5ecd7a0c23"
+        );
+        assert_eq!(
+            tx.wtxid(),
+            "680f483b2bf6c5dcbf111e69e885ba248a41a5e92070cfb0afec3cfc49a9fabb"
+        );
+    }
+
+    #[test]
+    fn test_parse_mainnet_segwit_tx() {
+        let tx = parse_hex(MAINNET_P2WPKH_TX).unwrap();
+        assert_eq!(tx.tx_ins.len(), 1);
+        assert_eq!(tx.tx_ins[0].script_sig, Some(Script::new(None)));
+        assert_eq!(tx.tx_ins[0].witness.len(), 2);
+        assert_eq!(hex::encode(tx.serialize()), MAINNET_P2WPKH_TX);
+        assert_eq!(
+            tx.txid(),
+            "f5864806e3565c34d1b41e716f72609d00b55ea5eac5b924c9719a842ef42206"
+        );
+        assert_eq!(
+            tx.wtxid(),
+            "80b7d8a82d5d5bf92905b06f2014dd699e03837ca172e3a59d51426ebbe3e7f5"
+        );
+
+        let tx = parse_hex(MAINNET_P2SH_P2WPKH_TX).unwrap();
+        assert_eq!(tx.tx_ins.len(), 3);
+        assert!(tx.tx_ins[0].witness.is_empty());
+        for tx_in in &tx.tx_ins[1..] {
+            let script_sig = tx_in.script_sig.clone().unwrap();
+            let redeem_script = script_sig.redeem_script().unwrap();
+            assert_eq!(redeem_script.script_type(), ScriptType::P2wpkh);
+            assert_eq!(tx_in.witness.len(), 2);
+        }
+        assert_eq!(tx.locktime, BigInt::from(512903));
+        assert_eq!(hex::encode(tx.serialize()), MAINNET_P2SH_P2WPKH_TX);
+        assert_eq!(
+            tx.txid(),
+            "0543411b2e7fbd8af4c46c593049dbac2d4007c0f5f12e4e5c71a07566e787c4"
+        );
+        assert_eq!(
+            tx.wtxid(),
+            "5e26f7ae78dd54714b9c78a8e1433132c47c49a32d94a03112ce5b14ce10e5ae"
+        );
+    }
+
+    #[test]
+    fn test_txid_without_witness() {
+        // stripping the marker, the flag and the witnesses leaves the legacy
+        // format the txid commits to
+        let tx = parse_hex(P2SH_P2WPKH_TX).unwrap();
+        let raw = hex::decode(P2SH_P2WPKH_TX).unwrap();
+        let witness_start = raw.len() - 4 - tx.tx_ins[0].serialize_witness().len();
+        let stripped = [&raw[..4], &raw[6..witness_start], &raw[raw.len() - 4..]].concat();
+        assert_eq!(tx.serialize_without_witness(), stripped);
+        let legacy = parse_hex(&hex::encode(&stripped)).unwrap();
+        assert!(!legacy.has_witness());
+        assert_eq!(legacy.txid(), tx.txid());
+        assert_eq!(legacy.wtxid(), tx.txid());
+
+        // a legacy transaction has the same txid and wtxid
+        let tx = parse_hex("0100000001813f79011acb80925dfe69b3def355fe914bd1d96a3f5f71bf8303c6a989c7d1000000006b483045022100ed81ff192e75a3fd2304004dcadb746fa5e24c5031ccfcf21320b0277457c98f02207a986d955c6e0cb35d446a89d3f56100f4d7f67801c31967743a9c8e10615bed01210349fc4e631e3624a545de3f89f5d8684c7b8138bd94bdd531d2e213bf016b278afeffffff02a135ef01000000001976a914bc3b654dca7e56b04dca18f2566cdaf02e8d9ada88ac99c39800000000001976a9141c4bc762dd5423e332166702cb75f40df79fea1288ac19430600").unwrap();
+        assert_eq!(
+            tx.txid(),
+            "452c629d67e41baec3ac6f04fe744b4b9617f8f859c63b3002f8684e7a4fee03"
+        );
+        assert_eq!(tx.wtxid(), tx.txid());
+        assert!(tx
+            .to_string()
+            .starts_with("tx: 452c629d67e41baec3ac6f04fe744b4b9617f8f859c63b3002f8684e7a4fee03\n"));
+    }
+
+    #[test]
+    fn test_serialize_witness() {
+        let mut tx_in = TxIn::new(vec![0x11; 32], BigInt::from(1), None, None);
+        let script_pubkey = Script::from_asm("0 751e76e8199196d454941c45d1b3a323f1433bd6").unwrap();
+        let tx_out = TxOut::new(BigInt::from(1000), script_pubkey);
+        let mut tx = Tx::new(
+            BigInt::from(2),
+            vec![tx_in.clone()],
+            vec![tx_out],
+            BigInt::from(0),
+            Network::Mainnet,
+        );
+        let legacy = tx.serialize();
+        assert_eq!(legacy, tx.serialize_without_witness());
+
+        tx_in.witness = vec![vec![], vec![0xab; 300]];
+        tx.tx_ins = vec![tx_in];
+        let raw = tx.serialize();
+        assert_eq!(&raw[4..6], &[0, 1]);
+        // two items, an empty one and one with a three byte length
+        assert!(hex::encode(tx.tx_ins[0].serialize_witness()).starts_with("0200fd2c01"));
+        assert_eq!(raw.len(), legacy.len() + 2 + 5 + 300);
+        let parsed = parse_hex(&hex::encode(&raw)).unwrap();
+        assert_eq!(parsed.tx_ins[0].witness, tx.tx_ins[0].witness);
+        assert_eq!(parsed.serialize(), raw);
+        assert_eq!(parsed.serialize_without_witness(), legacy);
+    }
+
+    #[test]
+    fn test_parse_bad_witness() {
+        // a flag other than 1
+        let bad_flag = format!("{}02{}", &P2WPKH_TX[..10], &P2WPKH_TX[12..]);
+        assert!(matches!(parse_hex(&bad_flag), Err(Error::BadWitness(_))));
+        // the marker without a single witness
+        let tx = parse_hex(P2SH_P2WPKH_TX).unwrap();
+        let mut raw = tx.serialize_without_witness();
+        let locktime = raw.split_off(raw.len() - 4);
+        raw.splice(4..4, [0, 1]);
+        raw.push(0);
+        raw.extend(locktime);
+        assert!(matches!(
+            parse_hex(&hex::encode(&raw)),
+            Err(Error::BadWitness(_))
+        ));
+        // a witness item longer than the data left
+        let truncated = &P2SH_P2WPKH_TX[..P2SH_P2WPKH_TX.len() - 80];
+        assert!(matches!(parse_hex(truncated), Err(Error::Truncated)));
+    }
+
     #[test]
     fn test_long_tx() {
         let tx = "010000000456919960ac691763688d3d3bcea9ad6ecaf875df5339e148a1fc61c6ed7a069e010000006a47304402204585bcdef85e6b1c6af5c2669d4830ff86e42dd205c0e089bc2a821657e951c002201024a10366077f87d6bce1f7100ad8cfa8a064b39d4e8fe4ea13a7b71aa8180f012102f0da57e85eec2934a82a585ea337ce2f4998b50ae699dd79f5880e253dafafb7feffffffeb8f51f4038dc17e6313cf831d4f02281c2a468bde0fafd37f1bf882729e7fd3000000006a47304402207899531a52d59a6de200179928ca900254a36b8dff8bb75f5f5d71b1cdc26125022008b422690b8461cb52c3cc30330b23d574351872b7c361e9aae3649071c1a7160121035d5c93d9ac96881f19ba1f686f15f009ded7c62efe85a872e6a19b43c15a2937feffffff567bf40595119d1bb8a3037c356efd56170b64cbcc160fb028fa10704b45d775000000006a47304402204c7c7818424c7f7911da6cddc59655a70af1cb5eaf17c69dadbfc74ffa0b662f02207599e08bc8023693ad4e9527dc42c34210f7a7d1d1ddfc8492b654a11e7620a0012102158b46fbdff65d0172b7989aec8850aa0dae49abfb84c81ae6e5b251a58ace5cfeffffffd63a5e6c16e620f86f375925b21cabaf736c779f88fd04dcad51d26690f7f345010000006a47304402200633ea0d3314bea0d95b3cd8dadb2ef79ea8331ffe1e61f762c0f6daea0fabde022029f23b3e9c30f080446150b23852028751635dcee2be669c2a1686a4b5edf304012103ffd6f4a67e94aba353a00882e563ff2722eb4cff0ad6006e86ee20dfe7520d55feffffff0251430f00000000001976a914ab0c0b2e98b1ab6dbf67d4750b0a56244948a87988ac005a6202000000001976a9143c82d7df364eb6c75be8c80df2b3eda8db57397088ac46430600";
         let tx_bytes = hex::decode(tx).unwrap();
         let mut reader_mem = Cursor::new(tx_bytes);
-        let tx_parsed = Tx::parse(&mut reader_mem, false);
+        let tx_parsed = Tx::parse(&mut reader_mem, Network::Mainnet).unwrap();
         //TODO add these tests when Script has a display impl
         // assert_eq!("304402207899531a52d59a6de200179928ca900254a36b8dff8bb75f5f5d71b1cdc26125022008b422690b8461cb52c3cc30330b23d574351872b7c361e9aae3649071c1a71601035d5c93d9ac96881f19ba1f686f15f009ded7c62efe85a872e6a19b43c15a2937", hex::encode(tx_parsed.tx_ins[1].script_sig));
         // assert_eq!("", hex::encode(tx_parsed.tx_outs[0].script_pubkey.serialize()));
@@ -451,14 +837,14 @@ mod tx_tests {
         let tx = "0100000001813f79011acb80925dfe69b3def355fe914bd1d96a3f5f71bf8303c6a989c7d1000000006b483045022100ed81ff192e75a3fd2304004dcadb746fa5e24c5031ccfcf21320b0277457c98f02207a986d955c6e0cb35d446a89d3f56100f4d7f67801c31967743a9c8e10615bed01210349fc4e631e3624a545de3f89f5d8684c7b8138bd94bdd531d2e213bf016b278afeffffff02a135ef01000000001976a914bc3b654dca7e56b04dca18f2566cdaf02e8d9ada88ac99c39800000000001976a9141c4bc762dd5423e332166702cb75f40df79fea1288ac19430600";
         let tx_encode = hex::decode(tx).unwrap();
         let mut cursor_tx = Cursor::new(tx_encode);
-        let tx = Tx::parse(&mut cursor_tx, false);
-        assert_eq!(tx.fee(false), BigInt::from(40000));
+        let tx = Tx::parse(&mut cursor_tx, Network::Mainnet).unwrap();
+        assert_eq!(tx.fee(Network::Mainnet).unwrap(), BigInt::from(40000));
 
         let tx = "010000000456919960ac691763688d3d3bcea9ad6ecaf875df5339e148a1fc61c6ed7a069e010000006a47304402204585bcdef85e6b1c6af5c2669d4830ff86e42dd205c0e089bc2a821657e951c002201024a10366077f87d6bce1f7100ad8cfa8a064b39d4e8fe4ea13a7b71aa8180f012102f0da57e85eec2934a82a585ea337ce2f4998b50ae699dd79f5880e253dafafb7feffffffeb8f51f4038dc17e6313cf831d4f02281c2a468bde0fafd37f1bf882729e7fd3000000006a47304402207899531a52d59a6de200179928ca900254a36b8dff8bb75f5f5d71b1cdc26125022008b422690b8461cb52c3cc30330b23d574351872b7c361e9aae3649071c1a7160121035d5c93d9ac96881f19ba1f686f15f009ded7c62efe85a872e6a19b43c15a2937feffffff567bf40595119d1bb8a3037c356efd56170b64cbcc160fb028fa10704b45d775000000006a47304402204c7c7818424c7f7911da6cddc59655a70af1cb5eaf17c69dadbfc74ffa0b662f02207599e08bc8023693ad4e9527dc42c34210f7a7d1d1ddfc8492b654a11e7620a0012102158b46fbdff65d0172b7989aec8850aa0dae49abfb84c81ae6e5b251a58ace5cfeffffffd63a5e6c16e620f86f375925b21cabaf736c779f88fd04dcad51d26690f7f345010000006a47304402200633ea0d3314bea0d95b3cd8dadb2ef79ea8331ffe1e61f762c0f6daea0fabde022029f23b3e9c30f080446150b23852028751635dcee2be669c2a1686a4b5edf304012103ffd6f4a67e94aba353a00882e563ff2722eb4cff0ad6006e86ee20dfe7520d55feffffff0251430f00000000001976a914ab0c0b2e98b1ab6dbf67d4750b0a56244948a87988ac005a6202000000001976a9143c82d7df364eb6c75be8c80df2b3eda8db57397088ac46430600";
         let tx_encode = hex::decode(tx).unwrap();
         let mut cursor_tx = Cursor::new(tx_encode);
-        let tx = Tx::parse(&mut cursor_tx, false);
-        assert_eq!(tx.fee(false), BigInt::from(140500));
+        let tx = Tx::parse(&mut cursor_tx, Network::Mainnet).unwrap();
+        assert_eq!(tx.fee(Network::Mainnet).unwrap(), BigInt::from(140500));
     }
 
     #[test]
@@ -466,8 +852,8 @@ mod tx_tests {
         let tx = "0100000001813f79011acb80925dfe69b3def355fe914bd1d96a3f5f71bf8303c6a989c7d1000000006b483045022100ed81ff192e75a3fd2304004dcadb746fa5e24c5031ccfcf21320b0277457c98f02207a986d955c6e0cb35d446a89d3f56100f4d7f67801c31967743a9c8e10615bed01210349fc4e631e3624a545de3f89f5d8684c7b8138bd94bdd531d2e213bf016b278afeffffff02a135ef01000000001976a914bc3b654dca7e56b04dca18f2566cdaf02e8d9ada88ac99c39800000000001976a9141c4bc762dd5423e332166702cb75f40df79fea1288ac19430600";
         let tx_encode = hex::decode(tx).unwrap();
         let mut cursor_tx = Cursor::new(tx_encode);
-        let tx = Tx::parse(&mut cursor_tx, false);
-        assert!(tx.fee(false) > BigInt::from(0));
+        let tx = Tx::parse(&mut cursor_tx, Network::Mainnet).unwrap();
+        assert!(tx.fee(Network::Mainnet).unwrap() > BigInt::from(0));
     }
 
     #[test]
@@ -475,8 +861,8 @@ mod tx_tests {
         let tx = "0100000001813f79011acb80925dfe69b3def355fe914bd1d96a3f5f71bf8303c6a989c7d1000000006b483045022100ed81ff192e75a3fd2304004dcadb746fa5e24c5031ccfcf21320b0277457c98f02207a986d955c6e0cb35d446a89d3f56100f4d7f67801c31967743a9c8e10615bed01210349fc4e631e3624a545de3f89f5d8684c7b8138bd94bdd531d2e213bf016b278afeffffff02a135ef01000000001976a914bc3b654dca7e56b04dca18f2566cdaf02e8d9ada88ac99c39800000000001976a9141c4bc762dd5423e332166702cb75f40df79fea1288ac19430600";
         let tx_encode = hex::decode(tx).unwrap();
         let mut cursor_tx = Cursor::new(tx_encode);
-        let tx = Tx::parse(&mut cursor_tx, false);
-        let tx_sig_hash = tx.sig_hash(0);
+        let tx = Tx::parse(&mut cursor_tx, Network::Mainnet).unwrap();
+        let tx_sig_hash = tx.sig_hash(0, None, SIGHASH_ALL).unwrap();
         assert_eq!(
             "18037338614366229343027734445863508930887653120159589908930024158807354868134",
             tx_sig_hash.to_string()
@@ -485,21 +871,429 @@ mod tx_tests {
         let der = "3045022100ed81ff192e75a3fd2304004dcadb746fa5e24c5031ccfcf21320b0277457c98f02207a986d955c6e0cb35d446a89d3f56100f4d7f67801c31967743a9c8e10615bed";
         let sig_encode = hex::decode(der).unwrap();
         let mut cursor_sig = Cursor::new(sig_encode);
-        let sig_parsed = Signature::parse(&mut cursor_sig);
+        let sig_parsed = Signature::parse(&mut cursor_sig).unwrap();
         let sec = "0349fc4e631e3624a545de3f89f5d8684c7b8138bd94bdd531d2e213bf016b278a";
         let sec_encode = hex::decode(sec).unwrap();
-        let point = S256Point::parse(&sec_encode);
+        let point = S256Point::parse(&sec_encode).unwrap();
         let z = Signature::signature_hash_from_int(tx_sig_hash);
         assert!(point.verify(&z, sig_parsed))
     }
 
+    #[test]
+    fn test_verify_p2sh_input() {
+        // 2-of-2 multisig spend from Programming Bitcoin chapter 8
+        let tx = "0100000001868278ed6ddfb6c1ed3ad5f8181eb0c7a385aa0836f01d5e4789e6bd304d87221a000000db00483045022100dc92655fe37036f47756db8102e0d7d5e28b3beb83a8fef4f5dc0559bddfb94e02205a36d4e4e6c7fcd16658c50783e00c341609977aed3ad00937bf4ee942a8993701483045022100da6bee3c93766232079a01639d07fa869598749729ae323eab8eef53577d611b02207bef15429dcadce2121ea07f233115c6f09034c0be68db99980b9a6c5e75402201475221022626e955ea6ea6d98850c994f9107b036b1334f18ca8830bfff1295d21cfdb702103b287eaf122eea69030a0e9feed096bed8045c8b98bec453e1ffac7fbdbd4bb7152aeffffffff04d3b11400000000001976a914904a49878c0adfc3aa05de7afad2cc15f483a56a88ac7f400900000000001976a914418327e3f3dda4cf5b9089325a4b95abdfa0334088ac722c0c00000000001976a914ba35042cfe9fc66fd35ac2224eebdafd1028ad2788acdc4ace020000000017a91474d691da1574e6b3c192ecfb52cc8984ee7b6c568700000000";
+        let mut cursor_tx = Cursor::new(hex::decode(tx).unwrap());
+        let tx = Tx::parse(&mut cursor_tx, Network::Mainnet).unwrap();
+        let script_sig = tx.tx_ins[0].script_sig.clone().unwrap();
+        let redeem_script = script_sig.redeem_script().unwrap();
+        assert_eq!(
+            hex::encode(redeem_script.raw_serialize()),
+            "5221022626e955ea6ea6d98850c994f9107b036b1334f18ca8830bfff1295d21cfdb702103b287eaf122eea69030a0e9feed096bed8045c8b98bec453e1ffac7fbdbd4bb7152ae"
+        );
+        let z = tx
+            .sig_hash(0, Some(redeem_script.clone()), SIGHASH_ALL)
+            .unwrap();
+        assert_eq!(
+            format!("{:x}", z),
+            "e71bfa115715d6fd33796948126f40a8cdd39f187e4afb03896795189fe1423c"
+        );
+        let script_pubkey = utils::p2sh_script(utils::hash160(&redeem_script.raw_serialize()));
+        assert_eq!(
+            Script::verify_script(
+                &script_sig,
+                &script_pubkey,
+                &TransactionSignatureChecker::new(&tx, 0),
+                VerifyFlags::P2SH
+            ),
+            Ok(())
+        );
+    }
+
+    fn sample_tx(script_pubkey: &Script) -> Tx {
+        let tx_in = |n: u8| {
+            TxIn::new(
+                vec![n; 32],
+                BigInt::from(n),
+                None,
+                Some(BigInt::from(0xfffffffe_u32)),
+            )
+        };
+        let tx_out = |amount: u32| TxOut::new(BigInt::from(amount), script_pubkey.clone());
+        Tx::new(
+            BigInt::from(1),
+            vec![tx_in(1), tx_in(2)],
+            vec![tx_out(1000), tx_out(2000), tx_out(3000)],
+            BigInt::from(0),
+            Network::Testnet,
+        )
+    }
+
+    // Runs a row of Bitcoin Core's sighash.json through Tx::sig_hash, the
+    // result is printed like Core's uint256, byte-reversed
+    fn core_sig_hash(raw_tx: &str, script: &str, input_index: usize, hash_type: i32) -> String {
+        let mut cursor = Cursor::new(hex::decode(raw_tx).unwrap());
+        let tx = Tx::parse(&mut cursor, Network::Mainnet).unwrap();
+        let script_code = Script::from_bytes(hex::decode(script).unwrap());
+        let z = tx
+            .sig_hash(input_index, Some(script_code), hash_type as u32)
+            .unwrap();
+        let mut hash = hex::decode(format!("{:064x}", z)).unwrap();
+        hash.reverse();
+        hex::encode(hash)
+    }
+
+    #[test]
+    fn test_sig_hash_types() {
+        let script_code = utils::p2pkh_script(vec![0x11; 20]);
+        let tx = sample_tx(&script_code);
+        let hash = |tx: &Tx, input_index: usize, hash_type: u32| {
+            tx.sig_hash(input_index, Some(script_code.clone()), hash_type)
+                .unwrap()
+        };
+
+        let hash_types = [
+            SIGHASH_ALL,
+            SIGHASH_NONE,
+            SIGHASH_SINGLE,
+            SIGHASH_ALL | SIGHASH_ANYONECANPAY,
+            SIGHASH_NONE | SIGHASH_ANYONECANPAY,
+            SIGHASH_SINGLE | SIGHASH_ANYONECANPAY,
+        ];
+        for (i, a) in hash_types.iter().enumerate() {
+            for b in &hash_types[i + 1..] {
+                assert_ne!(hash(&tx, 0, *a), hash(&tx, 0, *b));
+            }
+        }
+        let expected = [
+            (
+                0,
+                "318df60e66244c29aef8b48806872eade2eb32bdd3f2d627ad3502220edb08bf",
+            ),
+            (
+                0,
+                "ebc9deab68fa79a018a3fad750fdcd3d09583f960365b8edd0735b0720a2873a",
+            ),
+            (
+                0,
+                "171a96b05a908b7c69df6191aa4fcd12a016379661396b4ed7e01b4bd25bb32e",
+            ),
+            (
+                0,
+                "0b62757e360cce11842cc6ff8641ddd3e6f7b77e759050e5af5115d0a953cd66",
+            ),
+            (
+                0,
+                "a44d6bb394722cbf3f6a69b72952bc5ce27db51df282f4461045d3a619449b2f",
+            ),
+            (
+                0,
+                "0d029beb841e55820b930eb0e351daf49fe33e8d39cd3159c47f9e843ebe06b7",
+            ),
+            (
+                1,
+                "d9519678d25573e626271edd00d814d4f4ec5a7374037b0dd2bdf8a316cb983c",
+            ),
+            (
+                1,
+                "93fcc9e4057687167ccfe8a8e24dc23b963b7d0d32fdb581ad509afe18db48d1",
+            ),
+            (
+                1,
+                "d65d7b818b85a811e24ee7a3c669b02361efe4d5f218acc4922f1e4d039b6865",
+            ),
+            (
+                1,
+                "d2a5a4c4d2bcb34f2e16f897545f7743d938408831eb2a21cbb1839b5e4bdc93",
+            ),
+            (
+                1,
+                "253acadb3146a8ff2772af5bd2c7d3b3dc13db1189f4f26ee79755cbeec10764",
+            ),
+            (
+                1,
+                "fe2df8a0f4e709bc696f7866419aa7e7e60d4680effb30f5c9d183d1b9410fb4",
+            ),
+        ];
+        for ((input_index, want), hash_type) in expected.iter().zip(hash_types.iter().cycle()) {
+            assert_eq!(
+                format!("{:064x}", hash(&tx, *input_index, *hash_type)),
+                *want
+            );
+        }
+
+        // sighash.json: SINGLE|ANYONECANPAY, and ANYONECANPAY with the
+        // undefined base types Core signs like ALL
+        assert_eq!(
+            core_sig_hash(
+                "d3b7421e011f4de0f1cea9ba7458bf3486bee722519efab711a963fa8c100970cf7488b7bb0200000003525352dcd61b300148be5d05000000000000000000",
+                "535251536aac536a",
+                0,
+                -1960128125
+            ),
+            "29aa6d2d752d3310eba20442770ad345b7f6a35f96161ede5f07b33e92053e2a"
+        );
+        assert_eq!(
+            core_sig_hash(
+                "a93e93440250f97012d466a6cc24839f572def241c814fe6ae94442cf58ea33eb0fdd9bcc1030000000600636a0065acffffffff5dee3a6e7e5ad6310dea3e5b3ddda1a56bf8de7d3b75889fc024b5e233ec10f80300000007ac53635253ab53ffffffff0160468b04000000000800526a5300ac526a00000000",
+                "ac00636a53",
+                1,
+                1773442520
+            ),
+            "5c9d3a2ce9365bb72cfabbaa4579c843bb8abf200944612cf8ae4b56a908bcbd"
+        );
+        assert_eq!(
+            core_sig_hash(
+                "8d437a7304d8772210a923fd81187c425fc28c17a5052571501db05c7e89b11448b36618cd02000000026a6340fec14ad2c9298fde1477f1e8325e5747b61b7e2ff2a549f3d132689560ab6c45dd43c3010000000963ac00ac000051516a447ed907a7efffebeb103988bf5f947fc688aab2c6a7914f48238cf92c337fad4a79348102000000085352ac526a5152517436edf2d80e3ef06725227c970a816b25d0b58d2cd3c187a7af2cea66d6b27ba69bf33a0300000007000063ab526553f3f0d6140386815d030000000003ab6300de138f00000000000900525153515265abac1f87040300000000036aac6500000000",
+                "51",
+                3,
+                -315779667
+            ),
+            "b6632ac53578a741ae8c36d8b69e79f39b89913a2c781cdf1bf47a8c29d997a5"
+        );
+
+        // NONE leaves the outputs and the other sequences open
+        let mut changed = tx.clone();
+        changed.tx_outs.pop();
+        changed.tx_ins[1].sequence = Some(BigInt::from(0));
+        assert_eq!(hash(&changed, 0, SIGHASH_NONE), hash(&tx, 0, SIGHASH_NONE));
+        assert_ne!(hash(&changed, 0, SIGHASH_ALL), hash(&tx, 0, SIGHASH_ALL));
+        let mut changed = tx.clone();
+        changed.tx_ins[1].prev_index = BigInt::from(7);
+        assert_ne!(hash(&changed, 0, SIGHASH_NONE), hash(&tx, 0, SIGHASH_NONE));
+
+        // SINGLE only commits to the output with the same index
+        let mut changed = tx.clone();
+        changed.tx_outs[0].amount = BigInt::from(1);
+        changed.tx_outs[2].amount = BigInt::from(1);
+        changed.tx_ins[0].sequence = Some(BigInt::from(5));
+        assert_eq!(
+            hash(&changed, 1, SIGHASH_SINGLE),
+            hash(&tx, 1, SIGHASH_SINGLE)
+        );
+        changed.tx_outs[1].amount = BigInt::from(1);
+        assert_ne!(
+            hash(&changed, 1, SIGHASH_SINGLE),
+            hash(&tx, 1, SIGHASH_SINGLE)
+        );
+
+        // ANYONECANPAY lets inputs be added
+        let mut changed = tx.clone();
+        changed
+            .tx_ins
+            .push(TxIn::new(vec![3; 32], BigInt::from(0), None, None));
+        let all_acp = SIGHASH_ALL | SIGHASH_ANYONECANPAY;
+        assert_eq!(hash(&changed, 0, all_acp), hash(&tx, 0, all_acp));
+        assert_ne!(hash(&changed, 0, SIGHASH_ALL), hash(&tx, 0, SIGHASH_ALL));
+
+        // SINGLE without a matching output signs the number 1
+        let one = BigInt::from(1) << 248;
+        let mut short = tx.clone();
+        short.tx_outs.truncate(1);
+        assert_eq!(hash(&short, 1, SIGHASH_SINGLE), one);
+        assert_eq!(hash(&short, 1, SIGHASH_SINGLE | SIGHASH_ANYONECANPAY), one);
+        assert_ne!(hash(&short, 0, SIGHASH_SINGLE), one);
+    }
+
+    #[test]
+    fn test_sig_hash_core_vectors() {
+        // rows of Bitcoin Core's sighash.json: raw tx, script, input index,
+        // hash type, expected hash
+        let vectors = [
+            (
+                "907c2bc503ade11cc3b04eb2918b6f547b0630ab569273824748c87ea14b0696526c66ba740200000004ab65ababfd1f9bdd4ef073c7afc4ae00da8a66f429c917a0081ad1e1dabce28d373eab81d8628de802000000096aab5253ab52000052ad042b5f25efb33beec9f3364e8a9139e8439d9d7e26529c3c30b6c3fd89f8684cfd68ea0200000009ab53526500636a52ab599ac2fe02a526ed040000000008535300516352515164370e010000000003006300ab2ec229",
+                "",
+                2,
+                1864164639,
+                "31af167a6cf3f9d5f6875caa4d31704ceb0eba078d132b78dab52c3b8997317e",
+            ),
+            (
+                "a0aa3126041621a6dea5b800141aa696daf28408959dfb2df96095db9fa425ad3f427f2f6103000000015360290e9c6063fa26912c2e7fb6a0ad80f1c5fea1771d42f12976092e7a85a4229fdb6e890000000001abc109f6e47688ac0e4682988785744602b8c87228fcef0695085edf19088af1a9db126e93000000000665516aac536affffffff8fe53e0806e12dfd05d67ac68f4768fdbe23fc48ace22a5aa8ba04c96d58e2750300000009ac51abac63ab5153650524aa680455ce7b000000000000499e50030000000008636a00ac526563ac5051ee030000000003abacabd2b6fe000000000003516563910fb6b5",
+                "65",
+                0,
+                -1391424484,
+                "48d6a1bd2cd9eec54eb866fc71209418a950402b5d7e52363bfb75c98e141175",
+            ),
+            (
+                "73107cbd025c22ebc8c3e0a47b2a760739216a528de8d4dab5d45cbeb3051cebae73b01ca10200000007ab6353656a636affffffffe26816dffc670841e6a6c8c61c586da401df1261a330a6c6b3dd9f9a0789bc9e000000000800ac6552ac6aac51ffffffff0174a8f0010000000004ac52515100000000",
+                "5163ac63635151ac",
+                1,
+                1190874345,
+                "06e328de263a87b09beabe222a21627a6ea5c7f560030da31610c4611f4a46bc",
+            ),
+            (
+                "50818f4c01b464538b1e7e7f5ae4ed96ad23c68c830e78da9a845bc19b5c3b0b20bb82e5e9030000000763526a63655352ffffffff023b3f9c040000000008630051516a6a5163a83caf01000000000553ab65510000000000",
+                "6aac",
+                0,
+                946795545,
+                "746306f322de2b4b58ffe7faae83f6a72433c22f88062cdde881d4dd8a5a4e2d",
+            ),
+            (
+                "ce7d371f0476dda8b811d4bf3b64d5f86204725deeaa3937861869d5b2766ea7d17c57e40b0100000003535265ffffffff7e7e9188f76c34a46d0bbe856bde5cb32f089a07a70ea96e15e92abb37e479a10100000006ab6552ab655225bcab06d1c2896709f364b1e372814d842c9c671356a1aa5ca4e060462c65ae55acc02d0000000006abac0063ac5281b33e332f96beebdbc6a379ebe6aea36af115c067461eb99d22ba1afbf59462b59ae0bd0200000004ab635365be15c23801724a1704000000000965006a65ac00000052ca555572",
+                "53ab530051ab",
+                1,
+                2030598449,
+                "c336b2f7d3702fbbdeffc014d106c69e3413c7c71e436ba7562d8a7a2871f181",
+            ),
+            (
+                "04bac8c5033460235919a9c63c42b2db884c7c8f2ed8fcd69ff683a0a2cccd9796346a04050200000003655351fcad3a2c5a7cbadeb4ec7acc9836c3f5c3e776e5c566220f7f965cf194f8ef98efb5e3530200000007526a006552526526a2f55ba5f69699ece76692552b399ba908301907c5763d28a15b08581b23179cb01eac03000000075363ab6a516351073942c2025aa98a05000000000765006aabac65abd7ffa6030000000004516a655200000000",
+                "53ac6365ac526a",
+                1,
+                764174870,
+                "bf5fdc314ded2372a0ad078568d76c5064bf2affbde0764c335009e56634481b",
+            ),
+            (
+                "c363a70c01ab174230bbe4afe0c3efa2d7f2feaf179431359adedccf30d1f69efe0c86ed390200000002ab51558648fe0231318b04000000000151662170000000000008ac5300006a63acac00000000",
+                "",
+                0,
+                2146479410,
+                "191ab180b0d753763671717d051f138d4866b7cb0d1d4811472e64de595d2c70",
+            ),
+            (
+                "fd878840031e82fdbe1ad1d745d1185622b0060ac56638290ec4f66b1beef4450817114a2c0000000009516a63ab53650051abffffffff37b7a10322b5418bfd64fb09cd8a27ddf57731aeb1f1f920ffde7cb2dfb6cdb70300000008536a5365ac53515369ecc034f1594690dbe189094dc816d6d57ea75917de764cbf8eccce4632cbabe7e116cd0100000003515352ffffffff035777fc000000000003515200abe9140300000000050063005165bed6d10200000000076300536363ab65195e9110",
+                "635265",
+                0,
+                1729787658,
+                "6e3735d37a4b28c45919543aabcb732e7a3e1874db5315abb7cc6b143d62ff10",
+            ),
+        ];
+        for (raw_tx, script, input_index, hash_type, want) in vectors {
+            assert_eq!(core_sig_hash(raw_tx, script, input_index, hash_type), want);
+        }
+    }
+
+    #[test]
+    fn test_sig_hash_script_code_edge_cases() {
+        // the last transaction of test_sig_hash_types with the scriptCodes
+        // the sighash.json rows above don't cover, hashed the way Core's
+        // SignatureHash does
+        let raw_tx = "8d437a7304d8772210a923fd81187c425fc28c17a5052571501db05c7e89b11448b36618cd02000000026a6340fec14ad2c9298fde1477f1e8325e5747b61b7e2ff2a549f3d132689560ab6c45dd43c3010000000963ac00ac000051516a447ed907a7efffebeb103988bf5f947fc688aab2c6a7914f48238cf92c337fad4a79348102000000085352ac526a5152517436edf2d80e3ef06725227c970a816b25d0b58d2cd3c187a7af2cea66d6b27ba69bf33a0300000007000063ab526553f3f0d6140386815d030000000003ab6300de138f00000000000900525153515265abac1f87040300000000036aac6500000000";
+        let vectors = [
+            // several OP_CODESEPARATORs
+            (
+                "ab51ab52abac",
+                1,
+                0x1,
+                "8a187fd15c8dfd4c7a57320a8cb9e4430aad3cb39da3beee28eab7e96ac3c725",
+            ),
+            (
+                "ab6a02ababab51abab",
+                2,
+                0x2,
+                "e9a9df80fc7042261fa4db38c3f7b7d17e716cd0d267d6b6ce6a377eae82c904",
+            ),
+            (
+                "abac",
+                0,
+                0x83,
+                "26e8c28641d4f091eff33cda807157c18cb9abd6888708b9531140ef20c6bc80",
+            ),
+            // pushes running past the end of the scriptCode
+            (
+                "51ab4c",
+                1,
+                0x1,
+                "e8f03078d190b6dc59a947e3b584254f6a951184f7cab1737f18f7d51e07463a",
+            ),
+            (
+                "ab4d01",
+                0,
+                0x2,
+                "6413f188cf1680c962c045eaf2affcb6d7f1d715c0da128b415418380628e9d8",
+            ),
+            (
+                "52ab4d0100ab",
+                1,
+                0x3,
+                "f7a2141b1cde83b9020da334a3665613dc009d07b98091adb606d4ead999c208",
+            ),
+            (
+                "51ab05abab",
+                2,
+                0x81,
+                "620c7f1c392f0e3419ab7d60a6167703e51fc0a1c351934560106bc89fdfb926",
+            ),
+            (
+                "ab4e020000",
+                0,
+                0x1,
+                "0a295c88304f38552b718409c98d9b0e06563e6682dc227f0df6e437453725ae",
+            ),
+            // SINGLE without a matching output signs the number 1
+            (
+                "51",
+                3,
+                0x3,
+                "0000000000000000000000000000000000000000000000000000000000000001",
+            ),
+            (
+                "51",
+                3,
+                0x83,
+                "0000000000000000000000000000000000000000000000000000000000000001",
+            ),
+        ];
+        for (script, input_index, hash_type, want) in vectors {
+            assert_eq!(
+                core_sig_hash(raw_tx, script, input_index, hash_type),
+                want,
+                "{}",
+                script
+            );
+        }
+    }
+
+    #[test]
+    fn test_verify_sig_hash_types() {
+        let private_key =
+            PrivateKey::new(&PrivateKey::generate_simple_secret(BigInt::from(8675309)));
+        let sec = private_key.point.clone().sec(Some(true));
+        let script_pubkey = utils::p2pkh_script(utils::hash160(&sec));
+        let mut tx = sample_tx(&script_pubkey);
+        tx.tx_outs.truncate(1);
+        let sign = |tx: &Tx, input_index: usize, hash_type: u32, tag: u32| {
+            let z = tx
+                .sig_hash(input_index, Some(script_pubkey.clone()), hash_type)
+                .unwrap();
+            let der = private_key
+                .sign(&Signature::signature_hash_from_int(z), None)
+                .der();
+            Script::new(Some(vec![
+                Command::Element([der, vec![tag as u8]].concat()),
+                Command::Element(sec.clone()),
+            ]))
+        };
+        let verify = |tx: &Tx, input_index: usize, script_sig: &Script| {
+            Script::verify_script(
+                script_sig,
+                &script_pubkey,
+                &TransactionSignatureChecker::new(tx, input_index),
+                VerifyFlags::MANDATORY,
+            )
+        };
+
+        // the hashtype comes from the last byte of the signature
+        let none = SIGHASH_NONE | SIGHASH_ANYONECANPAY;
+        let script_sig = sign(&tx, 0, none, none);
+        assert_eq!(verify(&tx, 0, &script_sig), Ok(()));
+        let script_sig = sign(&tx, 0, none, SIGHASH_ALL);
+        assert_eq!(verify(&tx, 0, &script_sig), Err(ScriptError::EvalFalse));
+
+        // a SINGLE signature for an input without an output signs 1
+        let script_sig = sign(&tx, 1, SIGHASH_SINGLE, SIGHASH_SINGLE);
+        assert_eq!(verify(&tx, 1, &script_sig), Ok(()));
+        tx.tx_outs[0].amount = BigInt::from(1);
+        tx.tx_ins[0].sequence = None;
+        assert_eq!(verify(&tx, 1, &script_sig), Ok(()));
+    }
+
     #[test]
     fn test_tx_verify() {
         let tx = "0100000001813f79011acb80925dfe69b3def355fe914bd1d96a3f5f71bf8303c6a989c7d1000000006b483045022100ed81ff192e75a3fd2304004dcadb746fa5e24c5031ccfcf21320b0277457c98f02207a986d955c6e0cb35d446a89d3f56100f4d7f67801c31967743a9c8e10615bed01210349fc4e631e3624a545de3f89f5d8684c7b8138bd94bdd531d2e213bf016b278afeffffff02a135ef01000000001976a914bc3b654dca7e56b04dca18f2566cdaf02e8d9ada88ac99c39800000000001976a9141c4bc762dd5423e332166702cb75f40df79fea1288ac19430600";
         let tx_encode = hex::decode(tx).unwrap();
         let mut cursor_tx = Cursor::new(tx_encode);
-        let tx = Tx::parse(&mut cursor_tx, false);
-        assert!(tx.verify());
+        let tx = Tx::parse(&mut cursor_tx, Network::Mainnet).unwrap();
+        assert!(tx.verify().unwrap());
     }
 
     #[test]
@@ -507,17 +1301,14 @@ mod tx_tests {
         let tx = "0100000001813f79011acb80925dfe69b3def355fe914bd1d96a3f5f71bf8303c6a989c7d1000000006b483045022100ed81ff192e75a3fd2304004dcadb746fa5e24c5031ccfcf21320b0277457c98f02207a986d955c6e0cb35d446a89d3f56100f4d7f67801c31967743a9c8e10615bed01210349fc4e631e3624a545de3f89f5d8684c7b8138bd94bdd531d2e213bf016b278afeffffff02a135ef01000000001976a914bc3b654dca7e56b04dca18f2566cdaf02e8d9ada88ac99c39800000000001976a9141c4bc762dd5423e332166702cb75f40df79fea1288ac19430600";
         let tx_encode = hex::decode(tx).unwrap();
         let mut cursor_tx = Cursor::new(tx_encode);
-        let mut tx = Tx::parse(&mut cursor_tx, false);
-        let z = tx.sig_hash(0);
+        let mut tx = Tx::parse(&mut cursor_tx, Network::Mainnet).unwrap();
+        let z = tx.sig_hash(0, None, SIGHASH_ALL).unwrap();
         let private_key =
             PrivateKey::new(&PrivateKey::generate_simple_secret(BigInt::from(8675309)));
         let mut der_sighash = private_key
             .sign(&Signature::signature_hash_from_int(z), None)
             .der();
-        der_sighash.append(&mut utils::u32_to_little_endian(
-            *OpCodeFunctions::op_sig_hash_all().as_ref(),
-            1,
-        ));
+        der_sighash.append(&mut utils::u32_to_little_endian(SIGHASH_ALL, 1));
         let sec = private_key.point.sec(Some(true));
         let script_sig = Script::new(Some(vec![
             Command::Element(der_sighash),
@@ -532,10 +1323,10 @@ mod tx_tests {
         let tx = "010000000199a24308080ab26e6fb65c4eccfadf76749bb5bfa8cb08f291320b3c21e56f0d0d00000000ffffffff02408af701000000001976a914d52ad7ca9b3d096a38e752c2018e6fbc40cdf26f88ac80969800000000001976a914507b27411ccf7f16f10297de6cef3f291623eddf88ac00000000";
         let tx_encode = hex::decode(tx).unwrap();
         let mut cursor_tx = Cursor::new(tx_encode);
-        let mut tx = Tx::parse(&mut cursor_tx, true);
+        let mut tx = Tx::parse(&mut cursor_tx, Network::Testnet).unwrap();
         let private_key =
             PrivateKey::new(&PrivateKey::generate_simple_secret(BigInt::from(8675309)));
-        assert!(tx.sign_input(0, private_key));
+        assert!(tx.sign_input(0, private_key, SIGHASH_ALL).unwrap());
     }
     #[test]
     fn test_tx_with_two_inputs_and_one_output() {
@@ -557,14 +1348,20 @@ mod tx_tests {
         tx_ins.push(TxIn::new(prev_tx_faucet, prev_tx_faucet_index, None, None));
         tx_ins.push(TxIn::new(prev_tx_ex4, prev_tx_ex4_index, None, None));
         let mut tx_outs: Vec<TxOut> = Vec::new();
-        let h160 = utils::decode_base58(target_address);
+        let (_, h160) = utils::decode_base58_check(target_address).unwrap();
         let script_pubkey = utils::p2pkh_script(h160);
         let target_satoshis = BigInt::from((target_amount * 100_000_000_f64) as u64);
         tx_outs.push(TxOut::new(target_satoshis, script_pubkey));
-        let mut tx_obj = Tx::new(BigInt::from(1), tx_ins, tx_outs, BigInt::from(0), true);
+        let mut tx_obj = Tx::new(
+            BigInt::from(1),
+            tx_ins,
+            tx_outs,
+            BigInt::from(0),
+            Network::Testnet,
+        );
         // each may have different private keys to unlock the ScriptPubKey
-        assert!(tx_obj.sign_input(0, priva.clone()));
-        assert!(tx_obj.sign_input(1, priva.clone()));
+        assert!(tx_obj.sign_input(0, priva.clone(), SIGHASH_ALL).unwrap());
+        assert!(tx_obj.sign_input(1, priva.clone(), SIGHASH_ALL).unwrap());
         assert_eq!(want, hex::encode(tx_obj.serialize()));
     }
 }
@@ -1,4 +1,4 @@
-use crate::tx::Tx;
+use crate::{error::Error, network::Network, tx::Tx};
 use serde::Deserialize;
 use std::{collections::HashMap,io::Cursor};
 
@@ -19,33 +19,33 @@ impl TxFetcher {
          }
     }
 
-    pub fn get_url(testnet:bool)->String {
-        if testnet{
-            "https://api.blockcypher.com/v1/btc/test3".to_string()
-        } else {
-            "https://api.blockcypher.com/v1/btc/main".to_string()
+    pub fn get_url(network:Network)->Result<String,Error> {
+        match network {
+            Network::Mainnet => Ok("https://api.blockcypher.com/v1/btc/main".to_string()),
+            Network::Testnet => Ok("https://api.blockcypher.com/v1/btc/test3".to_string()),
+            _ => Err(Error::Fetch(format!("no block explorer available for {}", network)))
         }
     }
 
-    pub fn fetch(&mut self,tx_id:&str,testnet:bool,fresh:bool)->Tx{
+    pub fn fetch(&mut self,tx_id:&str,network:Network,fresh:bool)->Result<Tx,Error>{
         if fresh || !self.cache.contains_key(tx_id) {
-            let url = format!("{}/txs/{}?includeHex=true",TxFetcher::get_url(testnet),tx_id);
-            let res = reqwest::blocking::get(url).unwrap();
-            let tx_remote: TxRemote = res.json().unwrap();
-            let hex_decode = hex::decode(tx_remote.hex).unwrap();
+            let url = format!("{}/txs/{}?includeHex=true",TxFetcher::get_url(network)?,tx_id);
+            let res = reqwest::blocking::get(url).map_err(|e| Error::Fetch(e.to_string()))?;
+            let tx_remote: TxRemote = res.json().map_err(|e| Error::Fetch(e.to_string()))?;
+            let hex_decode = hex::decode(tx_remote.hex).map_err(|e| Error::Fetch(e.to_string()))?;
             let mut reader = Cursor::new(hex_decode);
-            let tx = Tx::parse(&mut reader, testnet);
+            let tx = Tx::parse(&mut reader, network)?;
             if tx_remote.hash != tx_id {
-                panic!("Not the same id")
+                return Err(Error::Fetch(format!("requested {} but got {}", tx_id, tx_remote.hash)))
             }
             self.cache.insert(tx_id.to_string(), tx);
         }
         match self.cache.get_mut(&tx_id.to_string()) {
             Some(tx) => {
-                tx.testnet = testnet;
-                tx.clone()
+                tx.network = network;
+                Ok(tx.clone())
             },
-            None => panic!("Tx not present")
+            None => Err(Error::Fetch(format!("{} missing from cache", tx_id)))
         }
     }
-}
\ No newline at end of file
+}
@@ -1,55 +1,113 @@
 use core::panic;
 use std::io::Read;
 
-use num_bigint::{BigInt, BigUint};
-use num_integer::Integer;
+use anyhow::Result;
+use byteorder::{ByteOrder, LittleEndian};
+use crypto::{
+    hmac::Hmac,
+    mac::Mac,
+    pbkdf2::pbkdf2,
+    sha2::{Sha256 as CryptoSha256, Sha512},
+};
+use num_bigint::BigInt;
 use ripemd::Ripemd160;
 use sha2::{Digest, Sha256};
 
-use crate::{
-    op,
-    script::{Command, Script},
-    utils,
-};
+use crate::{error::Error, op::OpCodeFunctions, script::Script, script_builder::ScriptBuilder};
 
 const BASE58_ALPHABET: &'static [u8] =
     b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
 
-// A better alternative would be to use this external create: https://docs.rs/base58/latest/src/base58/lib.rs.html#37-40
-// div_rem only works until this crate was added: https://docs.rs/num-integer/0.1.45/num_integer/trait.Integer.html
+#[derive(Debug, Clone, PartialEq)]
+pub enum Base58Error {
+    InvalidCharacter(char, usize),
+    TooShort,
+    BadChecksum { expected: [u8; 4], found: [u8; 4] },
+}
+
+impl std::fmt::Display for Base58Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Base58Error::InvalidCharacter(c, pos) => {
+                write!(f, "invalid base58 character {:?} at position {}", c, pos)
+            }
+            Base58Error::TooShort => write!(f, "base58 string too short for a checksum"),
+            Base58Error::BadChecksum { expected, found } => write!(
+                f,
+                "bad base58 checksum: expected {}, found {}",
+                hex::encode(expected),
+                hex::encode(found)
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Base58Error {}
+
+// Schoolbook base conversion on little-endian base58 digits.
 pub fn encode_base58(s: &[u8]) -> String {
     let leading_zeros = s.iter().take_while(|x| **x == 0).count();
-    // .fold(0, |acc, x| if x == &0 { acc + 1 } else { acc });
-    let mut num = BigInt::from_bytes_be(num_bigint::Sign::Plus, s);
-    let mut result = String::new();
-    let mut prefix = String::new();
-    for _ in 0..leading_zeros {
-        prefix.push('1')
+    let mut digits: Vec<u8> = Vec::with_capacity(s.len() * 138 / 100 + 1);
+    for byte in &s[leading_zeros..] {
+        let mut carry = *byte as u32;
+        for digit in digits.iter_mut() {
+            carry += (*digit as u32) << 8;
+            *digit = (carry % 58) as u8;
+            carry /= 58;
+        }
+        while carry > 0 {
+            digits.push((carry % 58) as u8);
+            carry /= 58;
+        }
     }
-    while num > BigInt::from(0) {
-        let (l_num, l_mod) = num.div_rem(&BigInt::from(58));
-        (num) = l_num;
-        let (sign, mut mod1) = (l_mod).to_u32_digits();
-        let modd = match sign {
-            num_bigint::Sign::Minus => panic!("should not be negative"),
-            num_bigint::Sign::NoSign => 0,
-            num_bigint::Sign::Plus => mod1.pop().unwrap(),
-        };
-
-        let ch = BASE58_ALPHABET[modd as usize];
-        result.push(ch as char);
+    let mut result = "1".repeat(leading_zeros);
+    for digit in digits.iter().rev() {
+        result.push(BASE58_ALPHABET[*digit as usize] as char);
     }
-    prefix + (&result.chars().rev().collect::<String>()[..])
+    result
 }
 
 pub fn p2pkh_script(h160: Vec<u8>) -> Script {
-    Script::new(Some(vec![
-        Command::Operation(op::parse_raw_op_codes(0x76)),
-        Command::Operation(op::parse_raw_op_codes(0xa9)),
-        Command::Element(h160),
-        Command::Operation(op::parse_raw_op_codes(0x88)),
-        Command::Operation(op::parse_raw_op_codes(0xac)),
-    ]))
+    ScriptBuilder::new()
+        .push_opcode(OpCodeFunctions::op_dup())
+        .push_opcode(OpCodeFunctions::op_hash160())
+        .push_slice(&h160)
+        .push_opcode(OpCodeFunctions::op_equalverify())
+        .push_opcode(OpCodeFunctions::op_checksig())
+        .into_script()
+}
+
+pub fn p2sh_script(h160: Vec<u8>) -> Script {
+    ScriptBuilder::new()
+        .push_opcode(OpCodeFunctions::op_hash160())
+        .push_slice(&h160)
+        .push_opcode(OpCodeFunctions::op_equal())
+        .into_script()
+}
+
+pub fn p2wpkh_script(h160: Vec<u8>) -> Script {
+    ScriptBuilder::new()
+        .push_int(0)
+        .push_slice(&h160)
+        .into_script()
+}
+
+pub fn p2wsh_script(sha256: Vec<u8>) -> Script {
+    ScriptBuilder::new()
+        .push_int(0)
+        .push_slice(&sha256)
+        .into_script()
+}
+
+pub fn sha256(b: &[u8]) -> Vec<u8> {
+    Sha256::digest(b).to_vec()
+}
+
+pub fn p2tr_script(xonly: Vec<u8>) -> Script {
+    ScriptBuilder::new()
+        .push_int(1)
+        .push_slice(&xonly)
+        .into_script()
 }
 
 pub fn hash256(b: &[u8]) -> Vec<u8> {
@@ -61,41 +119,92 @@ pub fn encode_base58_checksum(b: &[u8]) -> String {
     encode_base58(&[b, h].concat())
 }
 
-// this is a special case based on encode_base58_checksum
-pub fn decode_base58(s: &str) -> Vec<u8> {
-    let mut num = BigUint::from(0_u32);
-    for c in s.bytes() {
-        num *= 58_u32;
-        let el = BASE58_ALPHABET.iter().position(|x| c == *x).unwrap();
-        num += el
+// Each leading '1' stands for a zero byte.
+pub fn decode_base58(s: &str) -> Result<Vec<u8>, Base58Error> {
+    let leading_ones = s.bytes().take_while(|c| *c == b'1').count();
+    let mut bytes: Vec<u8> = Vec::with_capacity(s.len() * 733 / 1000 + 1);
+    for (pos, c) in s.chars().enumerate().skip(leading_ones) {
+        let mut carry = match BASE58_ALPHABET.iter().position(|x| c == *x as char) {
+            Some(el) => el as u32,
+            None => return Err(Base58Error::InvalidCharacter(c, pos)),
+        };
+        for byte in bytes.iter_mut() {
+            carry += *byte as u32 * 58;
+            *byte = carry as u8;
+            carry >>= 8;
+        }
+        while carry > 0 {
+            bytes.push(carry as u8);
+            carry >>= 8;
+        }
+    }
+    bytes.extend(vec![0; leading_ones]);
+    bytes.reverse();
+    Ok(bytes)
+}
+
+// Returns the whole payload with the checksum verified and stripped.
+pub fn decode_base58_checksum(s: &str) -> Result<Vec<u8>, Base58Error> {
+    let mut data = decode_base58(s)?;
+    if data.len() < 5 {
+        return Err(Base58Error::TooShort);
     }
-    let combined = num.to_bytes_be();
-    let checksum: Vec<u8> = combined.clone().into_iter().rev().take(4).collect();
-    let head: Vec<u8> = combined
-        .clone()
-        .into_iter()
-        .take(combined.len() - 4)
-        .to_owned()
-        .collect();
-    let hash256: Vec<u8> = utils::hash256(head.as_slice())
-        .into_iter()
-        .take(4)
-        .rev()
-        .collect();
-    if hash256 != checksum {
-        panic!(
-            "bad address: {} {}",
-            hex::encode(checksum),
-            hex::encode(hash256)
-        )
+    let checksum = data.split_off(data.len() - 4);
+    let expected: [u8; 4] = hash256(&data)[0..4].try_into().unwrap();
+    if expected[..] != checksum[..] {
+        return Err(Base58Error::BadChecksum {
+            expected,
+            found: checksum.try_into().unwrap(),
+        });
     }
-    combined[1..(combined.len() - 4)].to_vec()
+    Ok(data)
+}
+
+// Returns the version byte and the payload that follows it.
+pub fn decode_base58_check(s: &str) -> Result<(u8, Vec<u8>), Base58Error> {
+    let data = decode_base58_checksum(s)?;
+    Ok((data[0], data[1..].to_vec()))
 }
 
 pub fn hash160(s: &[u8]) -> Vec<u8> {
     Ripemd160::digest(Sha256::digest(s)).to_vec()
 }
 
+pub fn ripemd160(s: &[u8]) -> Vec<u8> {
+    Ripemd160::digest(s).to_vec()
+}
+
+pub fn tagged_hash(tag: &str, msg: &[u8]) -> Vec<u8> {
+    let tag_hash = Sha256::digest(tag.as_bytes());
+    Sha256::digest([&tag_hash[..], &tag_hash[..], msg].concat()).to_vec()
+}
+
+pub fn hmac_sha512(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::new(Sha512::new(), key);
+    mac.input(data);
+    mac.result().code().to_vec()
+}
+
+pub fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::new(CryptoSha256::new(), key);
+    mac.input(data);
+    mac.result().code().to_vec()
+}
+
+pub fn pbkdf2_hmac_sha256(password: &[u8], salt: &[u8], rounds: u32, length: usize) -> Vec<u8> {
+    let mut mac = Hmac::new(CryptoSha256::new(), password);
+    let mut output = vec![0; length];
+    pbkdf2(&mut mac, salt, rounds, &mut output);
+    output
+}
+
+pub fn pbkdf2_hmac_sha512(password: &[u8], salt: &[u8], rounds: u32, length: usize) -> Vec<u8> {
+    let mut mac = Hmac::new(Sha512::new(), password);
+    let mut output = vec![0; length];
+    pbkdf2(&mut mac, salt, rounds, &mut output);
+    output
+}
+
 pub fn little_endian_to_int(s: &[u8]) -> BigInt {
     BigInt::from_bytes_le(num_bigint::Sign::Plus, s)
 }
@@ -147,24 +256,23 @@ pub fn i32_to_little_endian(s: i32, limit: u64) -> Vec<u8> {
     buffer.to_vec()
 }
 
-pub fn read_varint<R: Read>(stream: &mut R) -> BigInt {
+pub fn read_varint<R: Read>(stream: &mut R) -> Result<u64, Error> {
     let mut buffer = [0; 1];
-    stream.read_exact(&mut buffer).unwrap();
-    if buffer[0] == 0xfd {
-        let mut buffer = [0; 2];
-        stream.read_exact(&mut buffer).unwrap();
-        little_endian_to_int(&buffer)
-    } else if buffer[0] == 0xfe {
-        let mut buffer = [0; 4];
-        stream.read_exact(&mut buffer).unwrap();
-        little_endian_to_int(&buffer)
-    } else if buffer[0] == 0xff {
-        let mut buffer = [0; 8];
-        stream.read_exact(&mut buffer).unwrap();
-        little_endian_to_int(&buffer)
-    } else {
-        little_endian_to_int(&buffer)
+    stream.read_exact(&mut buffer)?;
+    let (width, min) = match buffer[0] {
+        0xfd => (2, 0xfd),
+        0xfe => (4, 0x10000),
+        0xff => (8, 0x100000000),
+        _ => return Ok(buffer[0] as u64),
+    };
+    let mut buffer = vec![0; width];
+    stream.read_exact(&mut buffer)?;
+    let value = LittleEndian::read_uint(&buffer, width);
+    // Core rejects values that would have fit in a shorter encoding.
+    if value < min {
+        return Err(Error::NonCanonicalVarint(value));
     }
+    Ok(value)
 }
 
 pub fn encode_varint(i: usize) -> Vec<u8> {
@@ -189,9 +297,14 @@ pub fn encode_varint(i: usize) -> Vec<u8> {
 
 #[cfg(test)]
 mod utils_tests {
-    use crate::utils::{decode_base58, encode_base58_checksum};
+    use std::io::Cursor;
 
-    use super::{encode_base58, encode_varint};
+    use crate::error::Error;
+
+    use super::{
+        decode_base58, decode_base58_check, decode_base58_checksum, encode_base58,
+        encode_base58_checksum, encode_varint, read_varint, Base58Error,
+    };
 
     #[test]
     fn base58_test() {
@@ -212,7 +325,9 @@ mod utils_tests {
         assert_eq!(base58, "1111ZiCa");
 
         let addr = "mnrVtF8DWjMu839VW3rBfgYaAfKk8983Xf";
-        let h160 = hex::encode(decode_base58(addr));
+        let (version, h160) = decode_base58_check(addr).unwrap();
+        assert_eq!(version, 0x6f);
+        let h160 = hex::encode(h160);
         let want = "507b27411ccf7f16f10297de6cef3f291623eddf";
         assert_eq!(h160, want);
         let got =
@@ -220,9 +335,82 @@ mod utils_tests {
         assert_eq!(got, addr)
     }
 
+    #[test]
+    fn decode_base58_test() {
+        assert_eq!(decode_base58("").unwrap(), b"");
+        assert_eq!(decode_base58("1111ZiCa").unwrap(), b"\0\0\0\0abc");
+        assert_eq!(decode_base58("111").unwrap(), vec![0; 3]);
+        let hex = "7c076ff316692a3d7eb3c3bb0f8b1488cf72e1afcd929e29307032997a838a3d";
+        let decoded = decode_base58("9MA8fRQrT4u8Zj8ZRd6MAiiyaxb2Y1CMpvVkHQu5hVM6").unwrap();
+        assert_eq!(hex::encode(decoded), hex);
+        assert_eq!(
+            decode_base58("mnrVtF8DWjMu839VW3rBfgYaAfKk8983X0"),
+            Err(Base58Error::InvalidCharacter('0', 33))
+        );
+        assert_eq!(
+            decode_base58("1l"),
+            Err(Base58Error::InvalidCharacter('l', 1))
+        );
+    }
+
+    #[test]
+    fn decode_base58_check_test() {
+        let (version, payload) = decode_base58_check("1111111111111111111114oLvT2").unwrap();
+        assert_eq!(version, 0);
+        assert_eq!(payload, vec![0; 20]);
+        let (version, payload) = decode_base58_check("3J98t1WpEZ73CNmQviecrnyiWrnqRhWNLy").unwrap();
+        assert_eq!(version, 0x05);
+        assert_eq!(
+            hex::encode(payload),
+            "b472a266d0bd89c13706a4132ccfb16f7c3b9fcb"
+        );
+        assert!(matches!(
+            decode_base58_check("mnrVtF8DWjMu839VW3rBfgYaAfKk8983Xg"),
+            Err(Base58Error::BadChecksum { .. })
+        ));
+        assert_eq!(decode_base58_check("1111"), Err(Base58Error::TooShort));
+        assert_eq!(decode_base58_check(""), Err(Base58Error::TooShort));
+    }
+
+    #[test]
+    fn decode_base58_checksum_test() {
+        let payload = decode_base58_checksum("3J98t1WpEZ73CNmQviecrnyiWrnqRhWNLy").unwrap();
+        assert_eq!(
+            hex::encode(&payload),
+            "05b472a266d0bd89c13706a4132ccfb16f7c3b9fcb"
+        );
+        assert_eq!(
+            encode_base58_checksum(&payload),
+            "3J98t1WpEZ73CNmQviecrnyiWrnqRhWNLy"
+        );
+        assert_eq!(decode_base58_checksum("1111"), Err(Base58Error::TooShort));
+    }
+
     #[test]
     fn encode_varint_test() {
         let res = encode_varint(107);
         assert_eq!(hex::encode(res), "6b")
     }
+
+    #[test]
+    fn read_varint_test() {
+        for value in [0_usize, 0xfc, 0xfd, 0xffff, 0x10000, 0x100000000] {
+            let mut cursor = Cursor::new(encode_varint(value));
+            assert_eq!(read_varint(&mut cursor).unwrap(), value as u64);
+        }
+        let mut cursor = Cursor::new(hex::decode("ffffffffffffffffff").unwrap());
+        assert_eq!(read_varint(&mut cursor).unwrap(), u64::MAX);
+        let mut cursor = Cursor::new(hex::decode("fdfc00").unwrap());
+        assert!(matches!(
+            read_varint(&mut cursor),
+            Err(Error::NonCanonicalVarint(0xfc))
+        ));
+        let mut cursor = Cursor::new(hex::decode("feffff0000").unwrap());
+        assert!(matches!(
+            read_varint(&mut cursor),
+            Err(Error::NonCanonicalVarint(0xffff))
+        ));
+        let mut cursor = Cursor::new(hex::decode("fe0100").unwrap());
+        assert!(matches!(read_varint(&mut cursor), Err(Error::Truncated)));
+    }
 }